@@ -0,0 +1,268 @@
+use crate::config::CrawlConfig;
+use crate::sitemap::SitemapEntry;
+use crate::{PageData, SkippedUrl};
+use rusqlite::{params, Connection, OpenFlags, OptionalExtension};
+use std::collections::HashSet;
+use std::fs;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+
+const SCHEMA: &str = "
+    CREATE TABLE IF NOT EXISTS meta (key TEXT PRIMARY KEY, value TEXT NOT NULL);
+    CREATE TABLE IF NOT EXISTS visited (url TEXT PRIMARY KEY);
+    CREATE TABLE IF NOT EXISTS frontier (url TEXT PRIMARY KEY, depth INTEGER NOT NULL);
+    CREATE TABLE IF NOT EXISTS pages (url TEXT PRIMARY KEY, data TEXT NOT NULL);
+    CREATE TABLE IF NOT EXISTS skipped (url TEXT PRIMARY KEY, data TEXT NOT NULL);
+    CREATE TABLE IF NOT EXISTS sitemap_entries (url TEXT PRIMARY KEY, data TEXT NOT NULL);
+";
+
+/// Everything needed to carry on a crawl, as read back from a checkpoint.
+pub struct CheckpointState {
+    pub root_url: String,
+    pub visited: HashSet<String>,
+    /// Queued and in-flight URLs at the time of the last save, with their depths.
+    pub frontier: Vec<(String, usize)>,
+    pub pages: Vec<PageData>,
+    pub skipped: Vec<SkippedUrl>,
+    pub sitemap_entries: Vec<(String, SitemapEntry)>,
+}
+
+/// The changes since the previous save.
+pub struct CheckpointDelta<'a> {
+    pub done: &'a [String],
+    pub pages: &'a [PageData],
+    pub skipped: &'a [SkippedUrl],
+    /// Replaces the stored frontier wholesale.
+    pub frontier: &'a [(String, usize)],
+}
+
+/// An SQLite file holding the state of a crawl in progress, so that an
+/// interrupted crawl can be resumed without fetching anything twice.
+pub struct Checkpoint {
+    conn: Connection,
+    path: PathBuf,
+}
+
+impl Checkpoint {
+    /// Starts a fresh checkpoint at `path`, replacing any file already there.
+    pub fn create(path: &Path, config: &CrawlConfig, root_url: &str) -> Result<Self, Box<dyn std::error::Error>> {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        if path.exists() {
+            fs::remove_file(path)?;
+        }
+        let checkpoint = Self::open(path)?;
+        checkpoint.conn.execute_batch(SCHEMA)?;
+        checkpoint.conn.execute(
+            "INSERT INTO meta (key, value) VALUES ('config', ?1), ('root_url', ?2), ('finished', 'false')",
+            params![serde_json::to_string(config)?, root_url],
+        )?;
+        Ok(checkpoint)
+    }
+
+    pub fn open(path: &Path) -> Result<Self, Box<dyn std::error::Error>> {
+        let conn = Connection::open(path)?;
+        conn.execute_batch("PRAGMA journal_mode = WAL; PRAGMA synchronous = NORMAL;")?;
+        conn.execute_batch(SCHEMA)?;
+        Ok(Checkpoint { conn, path: path.to_path_buf() })
+    }
+
+    /// Whether `path` is a checkpoint rather than a crawl output file, which
+    /// may itself be an SQLite database.
+    pub fn is_checkpoint(path: &Path) -> bool {
+        let mut header = [0u8; 16];
+        let is_sqlite = fs::File::open(path)
+            .and_then(|mut file| file.read_exact(&mut header))
+            .is_ok_and(|_| &header == b"SQLite format 3\0");
+        is_sqlite
+            && Connection::open_with_flags(path, OpenFlags::SQLITE_OPEN_READ_ONLY)
+                .and_then(|conn| {
+                    conn.query_row("SELECT count(*) FROM sqlite_master WHERE type = 'table' AND name = 'meta'", [], |row| {
+                        row.get::<_, i64>(0)
+                    })
+                })
+                .is_ok_and(|count| count > 0)
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    fn meta(&self, key: &str) -> Result<Option<String>, Box<dyn std::error::Error>> {
+        Ok(self.conn
+            .query_row("SELECT value FROM meta WHERE key = ?1", [key], |row| row.get(0))
+            .optional()?)
+    }
+
+    pub fn config(&self) -> Result<CrawlConfig, Box<dyn std::error::Error>> {
+        let json = self.meta("config")?.ok_or("checkpoint has no stored configuration")?;
+        Ok(serde_json::from_str(&json)?)
+    }
+
+    pub fn is_finished(&self) -> Result<bool, Box<dyn std::error::Error>> {
+        Ok(self.meta("finished")?.as_deref() == Some("true"))
+    }
+
+    pub fn load(&self) -> Result<CheckpointState, Box<dyn std::error::Error>> {
+        let root_url = self.meta("root_url")?.ok_or("checkpoint has no root URL")?;
+
+        let mut visited = self.conn
+            .prepare("SELECT url FROM visited")?
+            .query_map([], |row| row.get(0))?
+            .collect::<Result<HashSet<String>, _>>()?;
+        let frontier = self.conn
+            .prepare("SELECT url, depth FROM frontier ORDER BY depth, url")?
+            .query_map([], |row| Ok((row.get(0)?, row.get::<_, i64>(1)? as usize)))?
+            .collect::<Result<Vec<(String, usize)>, _>>()?;
+
+        let pages: Vec<PageData> = self.load_json("SELECT url, data FROM pages ORDER BY url")?
+            .into_iter()
+            .map(|(_, page)| page)
+            .collect();
+        let skipped: Vec<SkippedUrl> = self.load_json("SELECT url, data FROM skipped ORDER BY url")?
+            .into_iter()
+            .map(|(_, skipped)| skipped)
+            .collect();
+        let sitemap_entries = self.load_json("SELECT url, data FROM sitemap_entries")?;
+        
+        // Pages and skipped URLs are settled even if the visited row for them was not written.
+        visited.extend(pages.iter().map(|page| page.url.clone()));
+        visited.extend(skipped.iter().map(|skipped| skipped.url.clone()));
+
+        Ok(CheckpointState { root_url, visited, frontier, pages, skipped, sitemap_entries })
+    }
+
+    /// Runs a query returning `(url, json)` rows and decodes the JSON column.
+    fn load_json<T: serde::de::DeserializeOwned>(&self, sql: &str) -> Result<Vec<(String, T)>, Box<dyn std::error::Error>> {
+        let mut rows = Vec::new();
+        let mut statement = self.conn.prepare(sql)?;
+        for row in statement.query_map([], |row| Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?)))? {
+            let (url, json) = row?;
+            rows.push((url, serde_json::from_str(&json)?));
+        }
+        Ok(rows)
+    }
+
+    pub fn save_sitemap_entries<'a>(
+        &mut self,
+        entries: impl Iterator<Item = (&'a String, &'a SitemapEntry)>,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let tx = self.conn.transaction()?;
+        {
+            let mut insert = tx.prepare("INSERT OR REPLACE INTO sitemap_entries (url, data) VALUES (?1, ?2)")?;
+            for (url, entry) in entries {
+                insert.execute(params![url, serde_json::to_string(entry)?])?;
+            }
+        }
+        tx.commit()?;
+        Ok(())
+    }
+
+    /// Writes one batch of progress in a single transaction, so a crash
+    /// mid-save leaves the previous checkpoint intact.
+    pub fn save(&mut self, delta: CheckpointDelta) -> Result<(), Box<dyn std::error::Error>> {
+        let tx = self.conn.transaction()?;
+        {
+            let mut insert = tx.prepare("INSERT OR IGNORE INTO visited (url) VALUES (?1)")?;
+            for url in delta.done {
+                insert.execute([url])?;
+            }
+            let mut insert = tx.prepare("INSERT OR REPLACE INTO pages (url, data) VALUES (?1, ?2)")?;
+            for page in delta.pages {
+                insert.execute(params![page.url, serde_json::to_string(page)?])?;
+            }
+            let mut insert = tx.prepare("INSERT OR REPLACE INTO skipped (url, data) VALUES (?1, ?2)")?;
+            for skipped in delta.skipped {
+                insert.execute(params![skipped.url, serde_json::to_string(skipped)?])?;
+            }
+            tx.execute("DELETE FROM frontier", [])?;
+            let mut insert = tx.prepare("INSERT OR REPLACE INTO frontier (url, depth) VALUES (?1, ?2)")?;
+            for (url, depth) in delta.frontier {
+                insert.execute(params![url, *depth as i64])?;
+            }
+        }
+        tx.commit()?;
+        Ok(())
+    }
+
+    pub fn mark_finished(&self) -> Result<(), Box<dyn std::error::Error>> {
+        self.conn.execute("INSERT OR REPLACE INTO meta (key, value) VALUES ('finished', 'true')", [])?;
+        Ok(())
+    }
+}
+
+/// Buffers what has happened since the last save, so each save only writes
+/// the new rows. Without a checkpoint nothing is buffered.
+pub struct CheckpointProgress {
+    enabled: bool,
+    done: Vec<String>,
+    pages: Vec<PageData>,
+    skipped: Vec<SkippedUrl>,
+}
+
+impl CheckpointProgress {
+    pub fn new(enabled: bool) -> Self {
+        CheckpointProgress { enabled, done: Vec::new(), pages: Vec::new(), skipped: Vec::new() }
+    }
+
+    pub fn record_done(&mut self, url: &str) {
+        if self.enabled {
+            self.done.push(url.to_string());
+        }
+    }
+
+    pub fn record_page(&mut self, page: &PageData) {
+        if self.enabled {
+            self.pages.push(page.clone());
+        }
+    }
+
+    pub fn record_skipped(&mut self, skipped: &SkippedUrl) {
+        if self.enabled {
+            self.skipped.push(skipped.clone());
+        }
+    }
+
+    pub fn unsaved(&self) -> usize {
+        self.done.len()
+    }
+
+    /// Saves the buffered rows. `pending` is every URL not yet finished: the
+    /// queued ones and those in flight, which would otherwise be lost.
+    pub fn flush(&mut self, checkpoint: &mut Checkpoint, pending: &[(String, usize)]) {
+        let delta = CheckpointDelta {
+            done: &self.done,
+            pages: &self.pages,
+            skipped: &self.skipped,
+            frontier: pending,
+        };
+        match checkpoint.save(delta) {
+            Ok(()) => {
+                self.done.clear();
+                self.pages.clear();
+                self.skipped.clear();
+            }
+            Err(e) => eprintln!("Error writing checkpoint {}: {}", checkpoint.path().display(), e),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn progress_is_only_buffered_for_a_checkpoint() {
+        let skip = SkippedUrl { url: "https://example.org/private".to_string(), reason: "disallowed".to_string(), found_on: None };
+        for enabled in [false, true] {
+            let mut progress = CheckpointProgress::new(enabled);
+            for i in 0..3 {
+                progress.record_done(&format!("https://example.org/{}", i));
+                progress.record_skipped(&skip);
+            }
+            assert_eq!(progress.unsaved(), if enabled { 3 } else { 0 });
+            assert_eq!(progress.skipped.len(), if enabled { 3 } else { 0 });
+        }
+    }
+}
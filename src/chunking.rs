@@ -0,0 +1,523 @@
+use crate::config::{ChunkIds, ChunkStrategy, ChunkingConfig, ConfigError};
+use crate::sentences::SentenceSegmenter;
+use crate::tokenizer::{self, Tokenizer};
+use crate::{SentenceSpan, TextChunk};
+use ego_tree::NodeId;
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use unicode_normalization::UnicodeNormalization;
+
+/// A heading as it appears in `full_text`: where its text starts, and its level.
+#[derive(Debug, Clone)]
+pub struct TextHeading {
+    /// The heading element, for matching it up with the extracted headings.
+    pub node: NodeId,
+    pub offset: usize,
+    pub level: u8,
+    pub text: String,
+    /// Boilerplate or link-only, as `extract_headings` leaves out: it still
+    /// ends a section but is left out of breadcrumbs.
+    pub navigation: bool,
+}
+
+/// Cuts `full_text` into chunks as the `[chunking]` settings say.
+pub struct Chunker {
+    config: ChunkingConfig,
+    /// Counts chunk sizes; `None` when `chunking.tokenizer` is `bytes`.
+    tokenizer: Option<Box<dyn Tokenizer>>,
+    segmenter: SentenceSegmenter,
+}
+
+impl Chunker {
+    pub fn from_config(config: &ChunkingConfig) -> Result<Self, ConfigError> {
+        Ok(Chunker {
+            config: config.clone(),
+            tokenizer: tokenizer::from_config(config)?,
+            segmenter: SentenceSegmenter::new(&config.abbreviations),
+        })
+    }
+
+    /// Splits `full_text` into chunks that end at sentence boundaries where
+    /// they can. With the `sections` strategy a chunk never spans two
+    /// sections (text under an h1–h`section_levels` heading); either way
+    /// each chunk is labelled with the heading path it starts under.
+    ///
+    /// Each chunk's text is its whole span of `full_text`, whitespace at the
+    /// edges included, so the offsets round-trip to it exactly; spans with
+    /// nothing but whitespace are dropped.
+    pub fn chunks(&self, full_text: &str, headings: &[TextHeading], url: &str) -> Vec<TextChunk> {
+        let config = &self.config;
+        let tokenizer = self.tokenizer.as_deref();
+        let sections = sections(full_text.len(), headings, config.section_levels);
+
+        let mut chunks = Vec::new();
+        let mut seen_ids: HashMap<String, usize> = HashMap::new();
+        for (byte_start, byte_end) in self.spans(full_text, &sections) {
+            let text = &full_text[byte_start..byte_end];
+            let section_heading = sections
+                .iter()
+                .find(|section| section.start <= byte_start && byte_start < section.end)
+                .and_then(|section| section.breadcrumb.clone());
+            let chunk_id = match config.chunk_ids {
+                ChunkIds::Position => format!("{}#chunk{}", url, chunks.len()),
+                ChunkIds::Content => {
+                    // The same text twice on a page gets "-2", "-3", ... after its hash.
+                    let id = content_id(url, text);
+                    let seen = seen_ids.entry(id.clone()).or_insert(0);
+                    *seen += 1;
+                    if *seen == 1 { id } else { format!("{}-{}", id, seen) }
+                }
+            };
+            chunks.push(TextChunk {
+                chunk_id,
+                text: text.to_string(),
+                byte_start,
+                byte_end,
+                char_start: char_offset(full_text, byte_start),
+                char_end: char_offset(full_text, byte_end),
+                utf16_start: config.utf16_offsets.then(|| utf16_offset(full_text, byte_start)),
+                utf16_end: config.utf16_offsets.then(|| utf16_offset(full_text, byte_end)),
+                section_heading,
+                token_count: tokenizer.map(|tokenizer| tokenizer.count(text)),
+                language: None,
+            });
+        }
+        chunks
+    }
+
+    /// The byte ranges chunks are cut at, leaving out those that are only whitespace.
+    fn spans(&self, full_text: &str, sections: &[Section]) -> Vec<(usize, usize)> {
+        let config = &self.config;
+        let tokenizer = self.tokenizer.as_deref();
+        let sentence_ends: Vec<usize> = self.segmenter.split(full_text).into_iter().map(|(_, end)| end).collect();
+        let ranges: Vec<(usize, usize)> = match config.strategy {
+            ChunkStrategy::Size => split_by_size(full_text, 0, full_text.len(), config, tokenizer, &sentence_ends),
+            ChunkStrategy::Sections => sections
+                .iter()
+                .flat_map(|section| split_by_size(full_text, section.start, section.end, config, tokenizer, &sentence_ends))
+                .collect(),
+        };
+        ranges.into_iter().filter(|&(start, end)| !full_text[start..end].trim().is_empty()).collect()
+    }
+
+    /// Every sentence of `full_text`, if `chunking.sentence_spans` is on.
+    pub fn sentences(&self, full_text: &str) -> Vec<SentenceSpan> {
+        if !self.config.sentence_spans {
+            return Vec::new();
+        }
+        self.segmenter
+            .split(full_text)
+            .into_iter()
+            .filter_map(|(start, end)| trimmed(full_text, start, end))
+            .map(|(byte_start, byte_end)| SentenceSpan {
+                text: full_text[byte_start..byte_end].to_string(),
+                byte_start,
+                byte_end,
+                char_start: char_offset(full_text, byte_start),
+                char_end: char_offset(full_text, byte_end),
+                utf16_start: self.config.utf16_offsets.then(|| utf16_offset(full_text, byte_start)),
+                utf16_end: self.config.utf16_offsets.then(|| utf16_offset(full_text, byte_end)),
+            })
+            .collect()
+    }
+}
+
+/// Hex SHA-256 of the normalised text, for telling whether a page's content changed.
+pub fn content_hash(text: &str) -> String {
+    hex(&Sha256::digest(normalize(text).as_bytes()))
+}
+
+/// The first 128 bits of the hex SHA-256 of the URL and the normalised text.
+fn content_id(url: &str, text: &str) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update(url.as_bytes());
+    hasher.update(b"\n");
+    hasher.update(normalize(text).as_bytes());
+    hex(&hasher.finalize()[..16])
+}
+
+/// NFC with whitespace runs collapsed, so text that only differs in how it
+/// was encoded or laid out hashes the same.
+fn normalize(text: &str) -> String {
+    text.nfc().collect::<String>().split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+/// `text[start..end]` without surrounding whitespace, or `None` if that leaves nothing.
+fn trimmed(text: &str, start: usize, end: usize) -> Option<(usize, usize)> {
+    let untrimmed = &text[start..end];
+    let trimmed = untrimmed.trim();
+    if trimmed.is_empty() {
+        return None;
+    }
+    let trimmed_start = start + (untrimmed.len() - untrimmed.trim_start().len());
+    Some((trimmed_start, trimmed_start + trimmed.len()))
+}
+
+/// A run of text starting at a heading (or at the top of the page) and
+/// ending where the next heading of a section level begins.
+struct Section {
+    start: usize,
+    end: usize,
+    /// Titles of the section's heading and those above it, e.g. "Open days > Undergraduate > Booking".
+    breadcrumb: Option<String>,
+}
+
+fn sections(text_len: usize, headings: &[TextHeading], section_levels: u8) -> Vec<Section> {
+    let mut sections = vec![Section { start: 0, end: text_len, breadcrumb: None }];
+    let mut path: Vec<&TextHeading> = Vec::new();
+    for heading in headings.iter().filter(|heading| heading.level <= section_levels) {
+        while path.last().is_some_and(|last| last.level >= heading.level) {
+            path.pop();
+        }
+        path.push(heading);
+        let titles: Vec<&str> = path.iter().filter(|heading| !heading.navigation).map(|heading| heading.text.as_str()).collect();
+        let breadcrumb = (!titles.is_empty()).then(|| titles.join(" > "));
+
+        let current = sections.last_mut().unwrap();
+        if heading.offset <= current.start {
+            // Nothing came before this heading, so it names the current section.
+            current.breadcrumb = breadcrumb;
+        } else {
+            current.end = heading.offset;
+            sections.push(Section { start: heading.offset, end: text_len, breadcrumb });
+        }
+    }
+    sections
+}
+
+/// Where chunks may end and how far apart those places are: every byte, or
+/// only the ends of tokens.
+enum Units {
+    Bytes,
+    /// Token ends in the section, moved forward to character boundaries.
+    Tokens(Vec<usize>),
+}
+
+impl Units {
+    fn new(full_text: &str, start: usize, end: usize, tokenizer: Option<&dyn Tokenizer>) -> Units {
+        let Some(tokenizer) = tokenizer else { return Units::Bytes };
+        let mut ends: Vec<usize> = tokenizer
+            .token_ends(&full_text[start..end])
+            .into_iter()
+            .map(|offset| ceil_char_boundary(full_text, start + offset))
+            .collect();
+        ends.dedup();
+        Units::Tokens(ends)
+    }
+
+    /// The place `count` units after `from`, or `end` if there are not that many.
+    fn forward(&self, full_text: &str, from: usize, count: usize, end: usize) -> usize {
+        match self {
+            Units::Bytes => ceil_char_boundary(full_text, (from + count).min(end)),
+            Units::Tokens(ends) => {
+                let done = ends.partition_point(|&e| e <= from);
+                ends.get(done + count - 1).copied().unwrap_or(end).min(end)
+            }
+        }
+    }
+
+    /// The place `count` units before `to`, but not before `start`.
+    fn back(&self, to: usize, count: usize, start: usize) -> usize {
+        match self {
+            Units::Bytes => to.saturating_sub(count).max(start),
+            Units::Tokens(ends) => {
+                let done = ends.partition_point(|&e| e <= to);
+                match done.checked_sub(count + 1) {
+                    Some(i) => ends[i].max(start),
+                    None => start,
+                }
+            }
+        }
+    }
+
+    /// How far past the size a chunk may run to end on a sentence. Token
+    /// limits are usually hard limits of the model, so they are never exceeded.
+    fn lookahead(&self) -> usize {
+        match self {
+            Units::Bytes => 100,
+            Units::Tokens(_) => 0,
+        }
+    }
+}
+
+/// Ranges of about `chunk_size` units covering `text[start..end]`, each
+/// ending at the last of `sentence_ends` before the size where there is one,
+/// and each starting `overlap` units before the previous one ended.
+fn split_by_size(
+    full_text: &str,
+    start: usize,
+    end: usize,
+    config: &ChunkingConfig,
+    tokenizer: Option<&dyn Tokenizer>,
+    sentence_ends: &[usize],
+) -> Vec<(usize, usize)> {
+    let units = Units::new(full_text, start, end, tokenizer);
+    let mut ranges = Vec::new();
+    let mut current_byte_start = start;
+
+    loop {
+        current_byte_start = ceil_char_boundary(full_text, current_byte_start);
+        if current_byte_start >= end {
+            break;
+        }
+
+        let mut target_end_byte = units.forward(full_text, current_byte_start, config.chunk_size, end);
+        if target_end_byte <= current_byte_start {
+            match full_text[current_byte_start..end].chars().next() {
+                Some(ch) => target_end_byte = current_byte_start + ch.len_utf8(),
+                None => break,
+            }
+        }
+
+        let mut chunk_to_slice_end_byte = target_end_byte;
+
+        if target_end_byte < end {
+            let sentence_search_limit = (target_end_byte + units.lookahead()).min(end);
+            let before_limit = sentence_ends.partition_point(|&e| e <= sentence_search_limit);
+            if let Some(&sentence_end) = before_limit.checked_sub(1).and_then(|i| sentence_ends.get(i)) {
+                if sentence_end > current_byte_start {
+                    chunk_to_slice_end_byte = sentence_end;
+                }
+            }
+        }
+
+        ranges.push((current_byte_start, chunk_to_slice_end_byte));
+        if chunk_to_slice_end_byte >= end {
+            break;
+        }
+
+        let next_start_byte_candidate = units.back(chunk_to_slice_end_byte, config.overlap, start);
+        current_byte_start = if next_start_byte_candidate > current_byte_start {
+            next_start_byte_candidate
+        } else {
+            chunk_to_slice_end_byte
+        };
+    }
+    ranges
+}
+
+fn ceil_char_boundary(text: &str, mut index: usize) -> usize {
+    while index < text.len() && !text.is_char_boundary(index) {
+        index += 1;
+    }
+    index.min(text.len())
+}
+
+/// The number of characters before byte `offset`.
+fn char_offset(text: &str, offset: usize) -> usize {
+    text[..offset].chars().count()
+}
+
+/// The number of UTF-16 code units before byte `offset`.
+fn utf16_offset(text: &str, offset: usize) -> usize {
+    text[..offset].chars().map(char::len_utf16).sum()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tokenizer::WhitespaceTokenizer;
+    use proptest::prelude::*;
+    use scraper::Html;
+
+    /// Text made of multi-byte pieces: accented, combining, CJK, astral-plane, multi-byte
+    /// whitespace and sentence punctuation, sometimes padded with whitespace at either end.
+    fn multibyte_text() -> impl Strategy<Value = String> {
+        let pieces = vec![
+            "a", "word", "é", "e\u{301}", "ß", "日本語", "中文。", "🦀", "👩‍💻", " ", " ", "  ", ". ", "? ", "\n", "\t", "\u{3000}", "\u{a0}",
+        ];
+        let padding = || prop::sample::select(vec!["", "\u{3000}", "\u{3000}\u{3000}", " \n", "\u{a0}\u{3000}"]);
+        (padding(), prop::collection::vec(prop::sample::select(pieces), 0..300), padding())
+            .prop_map(|(lead, pieces, trail)| format!("{}{}{}", lead, pieces.concat(), trail))
+    }
+
+    fn config(chunk_size: usize, overlap: usize, strategy: ChunkStrategy) -> ChunkingConfig {
+        ChunkingConfig { chunk_size, overlap: overlap % chunk_size, strategy, utf16_offsets: true, ..ChunkingConfig::default() }
+    }
+
+    fn chunker(config: ChunkingConfig, tokenizer: Option<Box<dyn Tokenizer>>) -> Chunker {
+        let segmenter = SentenceSegmenter::new(&config.abbreviations);
+        Chunker { config, tokenizer, segmenter }
+    }
+
+    /// Headings at character boundaries picked from `positions`.
+    fn headings(text: &str, positions: &[(usize, u8)]) -> Vec<TextHeading> {
+        let boundaries: Vec<usize> = text.char_indices().map(|(i, _)| i).collect();
+        let node = Html::parse_fragment("<h1></h1>").root_element().id();
+        let mut headings: Vec<TextHeading> = positions
+            .iter()
+            .filter(|_| !boundaries.is_empty())
+            .map(|&(position, level)| TextHeading {
+                node,
+                offset: boundaries[position % boundaries.len()],
+                level,
+                text: format!("Heading {}", level),
+                navigation: false,
+            })
+            .collect();
+        headings.sort_by_key(|heading| heading.offset);
+        headings
+    }
+
+    /// Checks that every chunk's offsets round-trip to its text, and that the text is the
+    /// whole, untrimmed span it was cut at.
+    fn check_offsets(text: &str, chunker: &Chunker, headings: &[TextHeading], chunks: &[TextChunk]) -> Result<(), TestCaseError> {
+        let spans = chunker.spans(text, &sections(text.len(), headings, chunker.config.section_levels));
+        let chunk_spans: Vec<(usize, usize)> = chunks.iter().map(|chunk| (chunk.byte_start, chunk.byte_end)).collect();
+        prop_assert_eq!(chunk_spans, spans);
+        let utf16: Vec<u16> = text.encode_utf16().collect();
+        for chunk in chunks {
+            prop_assert!(!chunk.text.is_empty());
+            prop_assert_eq!(&text[chunk.byte_start..chunk.byte_end], chunk.text.as_str());
+            let by_chars: String = text.chars().skip(chunk.char_start).take(chunk.char_end - chunk.char_start).collect();
+            prop_assert_eq!(&by_chars, &chunk.text);
+            let (start, end) = (chunk.utf16_start.unwrap(), chunk.utf16_end.unwrap());
+            prop_assert_eq!(String::from_utf16(&utf16[start..end]).unwrap(), chunk.text.clone());
+        }
+        // No text is lost between chunks.
+        for (i, c) in text.char_indices() {
+            if !c.is_whitespace() {
+                prop_assert!(chunks.iter().any(|chunk| chunk.byte_start <= i && i < chunk.byte_end), "byte {} is in no chunk", i);
+            }
+        }
+        Ok(())
+    }
+
+    proptest! {
+        #[test]
+        fn offsets_round_trip_in_bytes(
+            text in multibyte_text(),
+            chunk_size in 1usize..120,
+            overlap in 0usize..120,
+            positions in prop::collection::vec((0usize..1000, 1u8..=6), 0..6),
+            sections in any::<bool>(),
+        ) {
+            let strategy = if sections { ChunkStrategy::Sections } else { ChunkStrategy::Size };
+            let chunker = chunker(config(chunk_size, overlap, strategy), None);
+            let headings = headings(&text, &positions);
+            let chunks = chunker.chunks(&text, &headings, "http://example.com/");
+            check_offsets(&text, &chunker, &headings, &chunks)?;
+        }
+
+        #[test]
+        fn offsets_round_trip_in_words(
+            text in multibyte_text(),
+            chunk_size in 1usize..40,
+            overlap in 0usize..40,
+            positions in prop::collection::vec((0usize..1000, 1u8..=6), 0..6),
+        ) {
+            let chunker = chunker(config(chunk_size, overlap, ChunkStrategy::Sections), Some(Box::new(WhitespaceTokenizer)));
+            let headings = headings(&text, &positions);
+            let chunks = chunker.chunks(&text, &headings, "http://example.com/");
+            check_offsets(&text, &chunker, &headings, &chunks)?;
+        }
+    }
+
+    #[test]
+    fn chunks_keep_surrounding_whitespace() {
+        let text = "\u{3000}\u{3000}日本語の文。 Second sentence.\u{3000}";
+        let chunker = chunker(config(1000, 0, ChunkStrategy::Size), None);
+        let chunks = chunker.chunks(text, &[], "http://example.com/");
+        assert_eq!(chunks.len(), 1);
+        assert_eq!(chunks[0].text, text);
+        assert_eq!((chunks[0].byte_start, chunks[0].byte_end), (0, text.len()));
+        assert_eq!((chunks[0].char_start, chunks[0].char_end), (0, text.chars().count()));
+        assert_eq!((chunks[0].utf16_start, chunks[0].utf16_end), (Some(0), Some(text.encode_utf16().count())));
+
+        assert!(chunker.chunks("\u{3000} \n\u{a0}", &[], "http://example.com/").is_empty());
+    }
+    /// A page of `(level, title, navigation)` headings, each followed by a sentence,
+    /// and the breadcrumb of every chunk when it is chunked by sections.
+    fn breadcrumbs(outline: &[(u8, &str, bool)]) -> Vec<Option<String>> {
+        let node = Html::parse_fragment("<h1></h1>").root_element().id();
+        let mut text = String::new();
+        let mut headings = Vec::new();
+        for &(level, title, navigation) in outline {
+            headings.push(TextHeading { node, offset: text.len(), level, text: title.to_string(), navigation });
+            text.push_str(&format!("{} Some text about {}. ", title, title.to_lowercase()));
+        }
+        let chunker = chunker(config(1000, 0, ChunkStrategy::Sections), None);
+        chunker.chunks(&text, &headings, "http://example.com/").into_iter().map(|chunk| chunk.section_heading).collect()
+    }
+
+    #[test]
+    fn breadcrumbs_follow_the_heading_path() {
+        let crumbs = breadcrumbs(&[
+            (1, "Open days", false),
+            (2, "Undergraduate", false),
+            (3, "Booking", false),
+            (2, "Postgraduate", false),
+            (1, "Visiting", false),
+        ]);
+        assert_eq!(
+            crumbs,
+            [
+                Some("Open days".to_string()),
+                Some("Open days > Undergraduate".to_string()),
+                Some("Open days > Undergraduate > Booking".to_string()),
+                Some("Open days > Postgraduate".to_string()),
+                Some("Visiting".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn breadcrumbs_skip_missing_levels() {
+        let crumbs = breadcrumbs(&[(1, "Open days", false), (3, "Parking", false), (2, "Travel", false), (3, "By train", false)]);
+        assert_eq!(
+            crumbs,
+            [
+                Some("Open days".to_string()),
+                Some("Open days > Parking".to_string()),
+                Some("Open days > Travel".to_string()),
+                Some("Open days > Travel > By train".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn breadcrumbs_leave_out_navigation_headings() {
+        let crumbs = breadcrumbs(&[(1, "Open days", false), (2, "Home", true), (3, "Booking", false), (2, "Menu", true)]);
+        assert_eq!(
+            crumbs,
+            [
+                Some("Open days".to_string()),
+                Some("Open days".to_string()),
+                Some("Open days > Booking".to_string()),
+                Some("Open days".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn content_hash_is_sha256_of_normalised_text() {
+        let hello = "b94d27b9934d3e08a52e52d7da7dabfac484efe37a5380ee9088f7ace2efcde9";
+        assert_eq!(content_hash("hello world"), hello);
+        assert_eq!(content_hash("  hello\n\t world\u{3000}"), hello);
+        // "e" plus a combining acute accent is NFC-normalised to "é".
+        let cafe = "7c413039fbb2248e2b18b98e7a8d4d85bdcac7cd79b9477a0923f97e3a1f2b50";
+        assert_eq!(content_hash("cafe\u{301} au lait"), cafe);
+        assert_eq!(content_hash("caf\u{e9} au lait"), cafe);
+    }
+
+    #[test]
+    fn repeated_chunks_get_numbered_content_ids() {
+        // One section, and so one chunk, per sentence.
+        let sentences = ["Same sentence here. ", "Same sentence here. ", "Other text here. ", "Same  sentence\nhere."];
+        let node = Html::parse_fragment("<h1></h1>").root_element().id();
+        let mut text = String::new();
+        let mut headings = Vec::new();
+        for sentence in sentences {
+            headings.push(TextHeading { node, offset: text.len(), level: 1, text: "Heading".to_string(), navigation: false });
+            text.push_str(sentence);
+        }
+        let config = ChunkingConfig { chunk_ids: ChunkIds::Content, ..config(1000, 0, ChunkStrategy::Sections) };
+        let ids: Vec<String> = chunker(config, None).chunks(&text, &headings, "http://example.com/").into_iter().map(|chunk| chunk.chunk_id).collect();
+        let same = "c4cf208ebfde9a3b84474e713ea370b7";
+        assert_eq!(ids, [same.to_string(), format!("{}-2", same), content_id("http://example.com/", "Other text here."), format!("{}-3", same)]);
+    }
+}
@@ -0,0 +1,168 @@
+use crate::output::OutputFormat;
+use clap::{Args, Parser, Subcommand};
+use std::path::PathBuf;
+use url::Url;
+
+#[derive(Debug, Parser)]
+#[command(name = "web_crawler_rust", version, about = "Crawl a site and extract its text content for downstream processing")]
+pub struct Cli {
+    #[command(subcommand)]
+    pub command: Command,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum Command {
+    /// Crawl one or more seed URLs and save the results
+    Crawl(CrawlArgs),
+    /// Continue an interrupted crawl from its checkpoint, or a finished one from its output file
+    Resume(ResumeArgs),
+    /// Convert a saved crawl output file into another format
+    Export(ExportArgs),
+    /// Print a summary of a saved crawl output file
+    Inspect(InspectArgs),
+    /// Run extraction over archived or saved pages instead of the network
+    Reprocess(ReprocessArgs),
+}
+
+#[derive(Debug, Args)]
+pub struct CrawlArgs {
+    /// Seed URLs to start crawling from; the first one names the output file.
+    /// Replaces the seeds of the profile when given.
+    #[arg(value_name = "URL")]
+    pub seeds: Vec<String>,
+
+    #[command(flatten)]
+    pub profile: ProfileArgs,
+
+    #[command(flatten)]
+    pub limits: LimitArgs,
+
+    #[command(flatten)]
+    pub output: OutputArgs,
+}
+
+#[derive(Debug, Args)]
+pub struct ResumeArgs {
+    /// Checkpoint database, or crawl output file, written by a previous `crawl` or `resume`
+    #[arg(value_name = "FILE")]
+    pub input: PathBuf,
+
+    #[command(flatten)]
+    pub profile: ProfileArgs,
+
+    #[command(flatten)]
+    pub limits: LimitArgs,
+
+    #[command(flatten)]
+    pub output: OutputArgs,
+}
+
+#[derive(Debug, Args)]
+pub struct ExportArgs {
+    /// Crawl output file to convert
+    #[arg(value_name = "FILE")]
+    pub input: PathBuf,
+
+    /// Destination file
+    #[arg(short, long, value_name = "FILE")]
+    pub output: PathBuf,
+
+    /// Format to write
+    #[arg(short, long, value_enum, default_value_t = OutputFormat::Json)]
+    pub format: OutputFormat,
+}
+
+#[derive(Debug, Args)]
+pub struct InspectArgs {
+    /// Crawl output file to summarise
+    #[arg(value_name = "FILE")]
+    pub input: PathBuf,
+}
+
+#[derive(Debug, Args)]
+pub struct ReprocessArgs {
+    /// WARC files written by `crawl --warc`, directories of HTML files, or
+    /// `.tar`/`.tar.gz` archives of them, read in the order given
+    #[arg(value_name = "PATH", required = true)]
+    pub inputs: Vec<PathBuf>,
+
+    /// URL that local file paths are resolved against (`a/index.html` becomes `<base>/a/`)
+    #[arg(long, value_name = "URL")]
+    pub base_url: Option<Url>,
+
+    /// CSV with `path` and `url` columns naming the URL of individual local files
+    #[arg(long, value_name = "FILE")]
+    pub url_map: Option<PathBuf>,
+
+    /// Crawl profile supplying the selectors and chunking settings to extract with
+    #[arg(short, long, value_name = "FILE")]
+    pub config: Option<PathBuf>,
+
+    #[command(flatten)]
+    pub output: OutputArgs,
+}
+
+#[derive(Debug, Args)]
+pub struct ProfileArgs {
+    /// Crawl profile (.toml, .yaml or .yml); flags given on the command line take precedence
+    #[arg(short, long, value_name = "FILE")]
+    pub config: Option<PathBuf>,
+
+    /// User-Agent header sent with every request
+    #[arg(long)]
+    pub user_agent: Option<String>,
+
+    /// Per-request timeout in seconds
+    #[arg(long, value_name = "SECS")]
+    pub timeout: Option<u64>,
+
+    /// Number of requests allowed in flight at once [default: 8]
+    #[arg(short = 'j', long)]
+    pub concurrency: Option<usize>,
+
+    /// Do not fetch or obey robots.txt
+    #[arg(long)]
+    pub ignore_robots: bool,
+
+    /// Also seed the crawl from the sitemaps listed in robots.txt or at /sitemap.xml
+    #[arg(long)]
+    pub sitemaps: bool,
+
+    /// Crawl only the URLs listed in sitemaps, without following links
+    #[arg(long)]
+    pub sitemap_only: bool,
+
+    /// Where to checkpoint progress [default: <output-dir>/<domain>.checkpoint.db]
+    #[arg(long, value_name = "FILE")]
+    pub checkpoint: Option<PathBuf>,
+
+    /// Keep all progress in memory until the crawl finishes
+    #[arg(long, conflicts_with = "checkpoint")]
+    pub no_checkpoint: bool,
+
+    /// Archive every request and response to WARC files in the output directory
+    #[arg(long)]
+    pub warc: bool,
+}
+
+#[derive(Debug, Args)]
+pub struct LimitArgs {
+    /// Maximum link depth to follow from the seeds (seeds are depth 0) [default: 2]
+    #[arg(short = 'd', long)]
+    pub max_depth: Option<usize>,
+
+    /// Stop after this many pages have been crawled
+    #[arg(short = 'n', long)]
+    pub max_pages: Option<usize>,
+}
+
+#[derive(Debug, Args)]
+pub struct OutputArgs {
+    /// Directory the crawl output is written to [default: crawled_data]
+    #[arg(short, long, value_name = "DIR")]
+    pub output_dir: Option<PathBuf>,
+
+    /// Formats of the crawl output; repeat or comma-separate to write several at once [default: json]
+    #[arg(short, long, value_enum, value_delimiter = ',')]
+    pub format: Vec<OutputFormat>,
+}
@@ -0,0 +1,531 @@
+use regex::Regex;
+use scraper::Selector;
+use crate::content_type;
+use crate::frontier::CrawlOrder;
+use crate::output::OutputFormat;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+use url::Url;
+
+/// A reusable crawl definition, loaded from a TOML or YAML profile and/or
+/// assembled from command-line flags. Every field has a default so a profile
+/// only needs to list what it changes.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct CrawlConfig {
+    pub seeds: Vec<String>,
+    /// Hosts treated as internal in addition to the hosts of the seeds.
+    pub allowed_hosts: Vec<String>,
+    pub max_depth: usize,
+    pub max_pages: Option<usize>,
+    /// Number of requests allowed in flight at once.
+    pub concurrency: usize,
+    /// Order in which queued URLs are crawled.
+    pub order: CrawlOrder,
+    /// Regexes matched against the normalised URL; when non-empty a URL must match one to be crawled.
+    pub include: Vec<String>,
+    /// Regexes matched against the normalised URL; a match stops the URL being crawled.
+    pub exclude: Vec<String>,
+    pub banned_extensions: Vec<String>,
+    pub selectors: SelectorConfig,
+    pub chunking: ChunkingConfig,
+    pub language: LanguageConfig,
+    pub content_types: ContentTypeConfig,
+    pub http: HttpConfig,
+    pub robots: RobotsConfig,
+    pub politeness: PolitenessConfig,
+    pub sitemaps: SitemapConfig,
+    pub checkpoint: CheckpointConfig,
+    pub output: OutputConfig,
+    pub warc: WarcConfig,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SelectorConfig {
+    /// Hints for the main content detector: matching elements score higher.
+    /// With `content_scoring` off, the first match is taken outright.
+    pub main_content: Vec<String>,
+    /// Elements (and their descendants) dropped from the extracted text.
+    pub boilerplate: Vec<String>,
+    pub always_remove: String,
+    /// Lower-case phrases that mark a text node as cookie banner noise.
+    pub cookie_banner_text: Vec<String>,
+    /// Pick the main content by Readability-style scoring rather than by the first `main_content` match.
+    pub content_scoring: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ChunkingConfig {
+    pub chunk_size: usize,
+    pub overlap: usize,
+    /// Put tables into the text (and so the chunks) as Markdown rather than as runs of cell text.
+    pub tables_as_markdown: bool,
+    pub strategy: ChunkStrategy,
+    /// Headings from h1 down to this level start a new section.
+    pub section_levels: u8,
+    /// What `chunk_size` and `overlap` are counted in.
+    pub tokenizer: TokenizerKind,
+    /// A `.tiktoken` file or Hugging Face `tokenizer.json`, for the `bpe` tokenizer.
+    pub vocab_file: Option<PathBuf>,
+    /// The regex a `.tiktoken` vocabulary splits text with before BPE, for
+    /// files whose name doesn't say which encoding they are.
+    pub split_pattern: Option<String>,
+    /// Also give chunk offsets in UTF-16 code units, as JavaScript strings index them.
+    pub utf16_offsets: bool,
+    /// Words that end in a full stop without ending the sentence, such as "Dr".
+    pub abbreviations: Vec<String>,
+    /// List every sentence of `full_text` with its offsets in `content.sentences`.
+    pub sentence_spans: bool,
+    pub chunk_ids: ChunkIds,
+}
+
+/// How `TextChunk::chunk_id` is made.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ChunkIds {
+    /// `<url>#chunk<index>`, which changes whenever an earlier chunk is added or removed.
+    Position,
+    /// A hash of the page URL and the chunk's normalised text, unchanged while the text is.
+    Content,
+}
+
+/// The unit chunk sizes are measured in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum TokenizerKind {
+    /// Bytes of UTF-8 text.
+    Bytes,
+    /// Runs of non-whitespace.
+    Whitespace,
+    /// Byte-pair encoding with the vocabulary in `vocab_file`.
+    Bpe,
+}
+
+/// How `full_text` is cut into chunks.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ChunkStrategy {
+    /// Fixed-size chunks across the whole text.
+    Size,
+    /// Split at section headings first, then by size within each section.
+    Sections,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct LanguageConfig {
+    /// Run the n-gram detector over page text; when off only `<html lang>` and `Content-Language` are used.
+    pub detect: bool,
+    /// Detections below this confidence give way to the declared language.
+    pub min_confidence: f64,
+    /// ISO 639-1 codes the detector chooses between; empty means every language it knows.
+    pub languages: Vec<String>,
+    /// Detect each chunk's language too, for pages that mix languages.
+    pub per_chunk: bool,
+}
+
+/// Which responses are extracted, by MIME type (`type/subtype` or `type/*`).
+/// A missing or uninformative `Content-Type` is sniffed from the body.
+/// Responses of any other type are recorded as skipped.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ContentTypeConfig {
+    /// Parsed as HTML.
+    pub html: Vec<String>,
+    /// Kept as plain text.
+    pub text: Vec<String>,
+    /// Send a HEAD request first and skip unwanted types without downloading them.
+    pub head_first: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct HttpConfig {
+    pub timeout_secs: u64,
+    pub user_agent: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct RobotsConfig {
+    pub obey: bool,
+    /// Product token matched against the `User-agent` lines of robots.txt.
+    pub user_agent: String,
+    /// How long a robots.txt that failed to load (network error or 5xx) blocks its host before it is fetched again.
+    /// URLs on that host wait in the meantime, and are skipped after three tries.
+    pub retry_unreachable_secs: u64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct PolitenessConfig {
+    /// Minimum gap between requests to the same host; a longer robots.txt `Crawl-delay` takes precedence.
+    pub min_delay_ms: u64,
+    pub max_connections_per_host: usize,
+    /// Times a request answered with 429 or 503 is retried before it is recorded as failed.
+    pub max_retries: u32,
+    /// Upper bound on how long a single `Retry-After` may pause a host.
+    pub max_retry_after_secs: u64,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SitemapConfig {
+    /// Look for sitemaps in each seed host's robots.txt, falling back to `/sitemap.xml`.
+    pub discover: bool,
+    /// Crawl only the URLs found in sitemaps, without following links. Implies `discover`.
+    pub only: bool,
+    /// Sitemaps (or sitemap indexes) to read in addition to any discovered ones.
+    pub urls: Vec<String>,
+    pub max_urls: Option<usize>,
+}
+
+impl SitemapConfig {
+    pub fn enabled(&self) -> bool {
+        self.discover || self.only || !self.urls.is_empty()
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct CheckpointConfig {
+    pub enabled: bool,
+    /// Defaults to `<output_dir>/<domain>.checkpoint.db`.
+    pub path: Option<PathBuf>,
+    /// Number of finished URLs between saves.
+    pub interval_pages: usize,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct OutputConfig {
+    pub dir: PathBuf,
+    /// Every format listed is written side by side, each to `<dir>/<domain>.<extension>`.
+    pub formats: Vec<OutputFormat>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct WarcConfig {
+    /// Archive every fetched page's request and response.
+    pub enabled: bool,
+    /// Defaults to `output.dir`.
+    pub dir: Option<PathBuf>,
+    /// A new file is started once the current one reaches this size.
+    pub max_file_size_mb: u64,
+    pub gzip: bool,
+}
+
+impl Default for CrawlConfig {
+    fn default() -> Self {
+        CrawlConfig {
+            seeds: vec![],
+            allowed_hosts: vec![],
+            max_depth: 2,
+            max_pages: None,
+            concurrency: 8,
+            order: CrawlOrder::default(),
+            include: vec![],
+            exclude: vec![],
+            banned_extensions: [
+                ".pdf", ".jpg", ".jpeg", ".png", ".gif", ".zip", ".doc", ".docx", ".xls", ".xlsx", ".ppt", ".pptx",
+                ".mp3", ".mp4", ".avi", ".mov", ".xml", ".css", ".js", ".svg", ".webp", ".woff", ".woff2", ".ttf",
+                ".eot", ".ics",
+            ]
+            .iter()
+            .map(|s| s.to_string())
+            .collect(),
+            selectors: SelectorConfig::default(),
+            chunking: ChunkingConfig::default(),
+            language: LanguageConfig::default(),
+            content_types: ContentTypeConfig::default(),
+            http: HttpConfig::default(),
+            robots: RobotsConfig::default(),
+            politeness: PolitenessConfig::default(),
+            sitemaps: SitemapConfig::default(),
+            checkpoint: CheckpointConfig::default(),
+            output: OutputConfig::default(),
+            warc: WarcConfig::default(),
+        }
+    }
+}
+
+impl Default for SelectorConfig {
+    fn default() -> Self {
+        SelectorConfig {
+            main_content: ["main", "[role='main']", "#main-content", "#content", ".main-content", ".content", "article"]
+                .iter()
+                .map(|s| s.to_string())
+                .collect(),
+            boilerplate: [
+                "header", "footer", "nav", "aside", ".cookie-banner", "#cookie-consent", ".sidebar",
+                "div.secondary-navigation", "div.global-main-menu", "div.footer-menu", "div#onetrust-consent-sdk",
+            ]
+            .iter()
+            .map(|s| s.to_string())
+            .collect(),
+            always_remove: "script, style, noscript, svg, path, button, form, input, textarea, select, option, figure > figcaption, .visually-hidden, [aria-hidden='true']".to_string(),
+            cookie_banner_text: vec![
+                "cookies we use cookies to help our site work".to_string(),
+                "by accepting, you agree to cookies being stored".to_string(),
+                "manage settings accept".to_string(),
+            ],
+            content_scoring: false,
+        }
+    }
+}
+
+impl Default for ChunkingConfig {
+    fn default() -> Self {
+        ChunkingConfig {
+            chunk_size: 1000,
+            overlap: 200,
+            tables_as_markdown: false,
+            strategy: ChunkStrategy::Size,
+            section_levels: 3,
+            tokenizer: TokenizerKind::Bytes,
+            vocab_file: None,
+            split_pattern: None,
+            utf16_offsets: false,
+            abbreviations: [
+                "Mr", "Mrs", "Ms", "Dr", "Prof", "Rev", "Hon", "St", "Sr", "Jr", "vs", "e.g", "i.e", "cf", "approx", "fig",
+                "vol", "pp", "dept", "univ", "inc", "ltd", "co", "corp", "jan", "feb", "mar", "apr", "jun", "jul", "aug",
+                "sep", "sept", "oct", "nov", "dec",
+            ]
+            .iter()
+            .map(|s| s.to_string())
+            .collect(),
+            sentence_spans: false,
+            chunk_ids: ChunkIds::Position,
+        }
+    }
+}
+
+impl Default for LanguageConfig {
+    fn default() -> Self {
+        LanguageConfig {
+            detect: true,
+            min_confidence: 0.5,
+            languages: vec![],
+            per_chunk: true,
+        }
+    }
+}
+
+impl Default for ContentTypeConfig {
+    fn default() -> Self {
+        ContentTypeConfig {
+            html: vec!["text/html".to_string(), "application/xhtml+xml".to_string()],
+            text: vec!["text/plain".to_string(), "text/markdown".to_string()],
+            head_first: false,
+        }
+    }
+}
+
+impl Default for HttpConfig {
+    fn default() -> Self {
+        HttpConfig {
+            timeout_secs: 30,
+            user_agent: "Mozilla/5.0 (compatible; RustCrawler/1.0; +http://yourdomain.com/bot.html)".to_string(),
+        }
+    }
+}
+
+impl Default for RobotsConfig {
+    fn default() -> Self {
+        RobotsConfig {
+            obey: true,
+            user_agent: "RustCrawler".to_string(),
+            retry_unreachable_secs: 300,
+        }
+    }
+}
+
+impl Default for PolitenessConfig {
+    fn default() -> Self {
+        PolitenessConfig {
+            min_delay_ms: 500,
+            max_connections_per_host: 2,
+            max_retries: 3,
+            max_retry_after_secs: 300,
+        }
+    }
+}
+
+impl Default for CheckpointConfig {
+    fn default() -> Self {
+        CheckpointConfig {
+            enabled: true,
+            path: None,
+            interval_pages: 25,
+        }
+    }
+}
+
+impl Default for OutputConfig {
+    fn default() -> Self {
+        OutputConfig {
+            dir: PathBuf::from("crawled_data"),
+            formats: vec![OutputFormat::Json],
+        }
+    }
+}
+
+impl Default for WarcConfig {
+    fn default() -> Self {
+        WarcConfig {
+            enabled: false,
+            dir: None,
+            max_file_size_mb: 1024,
+            gzip: true,
+        }
+    }
+}
+
+/// A configuration problem, tagged with the key it was found at.
+#[derive(Debug)]
+pub struct ConfigError {
+    pub key: String,
+    pub message: String,
+}
+
+impl ConfigError {
+    pub fn new(key: impl Into<String>, message: impl Into<String>) -> Self {
+        ConfigError { key: key.into(), message: message.into() }
+    }
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.key.is_empty() {
+            write!(f, "{}", self.message)
+        } else {
+            write!(f, "`{}`: {}", self.key, self.message)
+        }
+    }
+}
+
+impl std::error::Error for ConfigError {}
+
+impl CrawlConfig {
+    /// Loads a profile, picking the parser from the file extension (`.toml`, `.yaml` or `.yml`).
+    pub fn load(path: &Path) -> Result<Self, ConfigError> {
+        let display = path.display().to_string();
+        let text = fs::read_to_string(path).map_err(|e| ConfigError::new("", format!("{}: {}", display, e)))?;
+        let extension = path.extension().and_then(|e| e.to_str()).unwrap_or("").to_lowercase();
+        match extension.as_str() {
+            "toml" => toml::from_str(&text).map_err(|e| ConfigError::new("", format!("{}: {}", display, e))),
+            "yaml" | "yml" => serde_yaml::from_str(&text).map_err(|e| ConfigError::new("", format!("{}: {}", display, e))),
+            _ => Err(ConfigError::new("", format!("{}: unsupported profile format, expected .toml, .yaml or .yml", display))),
+        }
+    }
+
+    /// Checks the values that deserialisation alone cannot. Selectors and
+    /// patterns are checked where they are compiled, so they get the same key-tagged errors.
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        if self.seeds.is_empty() {
+            return Err(ConfigError::new("seeds", "at least one seed URL is required"));
+        }
+        for (i, seed) in self.seeds.iter().enumerate() {
+            let url = Url::parse(seed).map_err(|e| ConfigError::new(format!("seeds[{}]", i), format!("invalid URL {:?}: {}", seed, e)))?;
+            if url.host_str().is_none() {
+                return Err(ConfigError::new(format!("seeds[{}]", i), format!("URL {:?} has no host", seed)));
+            }
+        }
+        for (i, host) in self.allowed_hosts.iter().enumerate() {
+            if host.is_empty() || host.contains('/') {
+                return Err(ConfigError::new(format!("allowed_hosts[{}]", i), format!("expected a bare host name, got {:?}", host)));
+            }
+        }
+        if self.max_pages == Some(0) {
+            return Err(ConfigError::new("max_pages", "must be greater than zero"));
+        }
+        if self.concurrency == 0 {
+            return Err(ConfigError::new("concurrency", "must be greater than zero"));
+        }
+        for (i, extension) in self.banned_extensions.iter().enumerate() {
+            if !extension.starts_with('.') {
+                return Err(ConfigError::new(format!("banned_extensions[{}]", i), format!("expected a leading '.', got {:?}", extension)));
+            }
+        }
+        if self.chunking.chunk_size == 0 {
+            return Err(ConfigError::new("chunking.chunk_size", "must be greater than zero"));
+        }
+        if self.chunking.overlap >= self.chunking.chunk_size {
+            return Err(ConfigError::new("chunking.overlap", format!("must be smaller than chunking.chunk_size ({})", self.chunking.chunk_size)));
+        }
+        if !(1..=6).contains(&self.chunking.section_levels) {
+            return Err(ConfigError::new("chunking.section_levels", format!("must be between 1 and 6, got {}", self.chunking.section_levels)));
+        }
+        if self.chunking.tokenizer == TokenizerKind::Bpe && self.chunking.vocab_file.is_none() {
+            return Err(ConfigError::new("chunking.vocab_file", "is required by the bpe tokenizer"));
+        }
+        if !(0.0..=1.0).contains(&self.language.min_confidence) {
+            return Err(ConfigError::new("language.min_confidence", format!("must be between 0 and 1, got {}", self.language.min_confidence)));
+        }
+        for (key, types) in [("content_types.html", &self.content_types.html), ("content_types.text", &self.content_types.text)] {
+            for (i, mime_type) in types.iter().enumerate() {
+                if mime_type.contains(';') || content_type::essence(mime_type).is_none() {
+                    return Err(ConfigError::new(format!("{}[{}]", key, i), format!("expected a MIME type such as \"text/html\" or \"text/*\", got {:?}", mime_type)));
+                }
+            }
+        }
+        if self.http.timeout_secs == 0 {
+            return Err(ConfigError::new("http.timeout_secs", "must be greater than zero"));
+        }
+        if self.http.user_agent.trim().is_empty() {
+            return Err(ConfigError::new("http.user_agent", "must not be empty"));
+        }
+        if self.politeness.max_connections_per_host == 0 {
+            return Err(ConfigError::new("politeness.max_connections_per_host", "must be greater than zero"));
+        }
+        for (i, url) in self.sitemaps.urls.iter().enumerate() {
+            Url::parse(url).map_err(|e| ConfigError::new(format!("sitemaps.urls[{}]", i), format!("invalid URL {:?}: {}", url, e)))?;
+        }
+        if self.checkpoint.interval_pages == 0 {
+            return Err(ConfigError::new("checkpoint.interval_pages", "must be greater than zero"));
+        }
+        if self.robots.user_agent.trim().is_empty() {
+            return Err(ConfigError::new("robots.user_agent", "must not be empty"));
+        }
+        if self.warc.max_file_size_mb == 0 {
+            return Err(ConfigError::new("warc.max_file_size_mb", "must be greater than zero"));
+        }
+        if self.output.formats.is_empty() {
+            return Err(ConfigError::new("output.formats", "at least one output format is required"));
+        }
+        for (i, format) in self.output.formats.iter().enumerate() {
+            if let Some(j) = self.output.formats[..i].iter().position(|other| other.extension() == format.extension()) {
+                return Err(ConfigError::new(
+                    format!("output.formats[{}]", i),
+                    format!("writes the same .{} file as output.formats[{}]", format.extension(), j),
+                ));
+            }
+        }
+        Ok(())
+    }
+}
+
+pub fn parse_selector(key: &str, selector: &str) -> Result<Selector, ConfigError> {
+    Selector::parse(selector).map_err(|e| ConfigError::new(key, format!("invalid CSS selector {:?}: {}", selector, e)))
+}
+
+pub fn parse_selectors(key: &str, selectors: &[String]) -> Result<Vec<Selector>, ConfigError> {
+    selectors
+        .iter()
+        .enumerate()
+        .map(|(i, s)| parse_selector(&format!("{}[{}]", key, i), s))
+        .collect()
+}
+
+pub fn compile_patterns(key: &str, patterns: &[String]) -> Result<Vec<Regex>, ConfigError> {
+    patterns
+        .iter()
+        .enumerate()
+        .map(|(i, p)| Regex::new(p).map_err(|e| ConfigError::new(format!("{}[{}]", key, i), format!("invalid pattern: {}", e))))
+        .collect()
+}
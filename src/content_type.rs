@@ -0,0 +1,227 @@
+use crate::config::ContentTypeConfig;
+
+/// How a response is turned into a page.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Handler {
+    /// Parsed as HTML, with the full extraction pipeline.
+    Html,
+    /// Taken as it is: the body becomes `full_text`, split into paragraphs at blank lines.
+    Text,
+}
+
+/// The MIME types listed in `content_types`, each a full `type/subtype` or a `type/*` wildcard.
+pub struct ContentTypes {
+    html: Vec<String>,
+    text: Vec<String>,
+}
+
+impl ContentTypes {
+    pub fn from_config(config: &ContentTypeConfig) -> Self {
+        let lower = |types: &[String]| types.iter().map(|t| t.trim().to_ascii_lowercase()).collect();
+        ContentTypes { html: lower(&config.html), text: lower(&config.text) }
+    }
+
+    /// The handler for `mime_type`, or `None` when responses of that type are skipped.
+    pub fn handler(&self, mime_type: &str) -> Option<Handler> {
+        let listed = |types: &[String]| {
+            types.iter().any(|t| match t.strip_suffix("/*") {
+                Some(top) => mime_type.split('/').next() == Some(top),
+                None => t == mime_type,
+            })
+        };
+        if listed(&self.html) {
+            Some(Handler::Html)
+        } else if listed(&self.text) {
+            Some(Handler::Text)
+        } else {
+            None
+        }
+    }
+}
+
+/// The lower-case `type/subtype` of a `Content-Type` value, without its parameters.
+pub fn essence(content_type: &str) -> Option<String> {
+    let essence = content_type.split(';').next().unwrap_or("").trim().to_ascii_lowercase();
+    let (top, sub) = essence.split_once('/')?;
+    (!top.is_empty() && !sub.is_empty() && top != "*").then_some(essence)
+}
+
+/// How many bytes sniffing looks at.
+const SNIFF_LIMIT: usize = 1445;
+
+/// The body's MIME type: the `Content-Type` essence when there is a usable
+/// one, otherwise sniffed from the first bytes by the WHATWG rules for an
+/// unknown type. Servers that send `text/plain` by default (the "Apache
+/// bug") have their binary files caught too.
+pub fn mime_type(content_type: Option<&str>, body: &[u8]) -> String {
+    let header = &body[..body.len().min(SNIFF_LIMIT)];
+    let declared = content_type.and_then(essence).filter(|essence| essence != "unknown/unknown" && essence != "application/unknown");
+    match (declared, content_type) {
+        (Some(_), Some(content_type)) if is_default_text_plain(content_type) => {
+            if header.iter().copied().any(is_binary) { "application/octet-stream" } else { "text/plain" }.to_string()
+        }
+        (Some(declared), _) => declared,
+        (None, _) => sniff(header).to_string(),
+    }
+}
+
+fn is_default_text_plain(content_type: &str) -> bool {
+    ["text/plain", "text/plain; charset=iso-8859-1", "text/plain; charset=utf-8"]
+        .iter()
+        .any(|default| content_type.trim().eq_ignore_ascii_case(default))
+}
+
+/// Tags whose presence at the start of a body marks it as HTML.
+const HTML_TAGS: &[&[u8]] = &[
+    b"<!DOCTYPE HTML", b"<HTML", b"<HEAD", b"<SCRIPT", b"<IFRAME", b"<H1", b"<DIV", b"<FONT", b"<TABLE", b"<A",
+    b"<STYLE", b"<TITLE", b"<B", b"<BODY", b"<BR", b"<P", b"<!--",
+];
+
+/// Leading bytes of the binary formats a crawler is likely to run into.
+const SIGNATURES: &[(&[u8], &str)] = &[
+    (b"%PDF-", "application/pdf"),
+    (b"%!PS-Adobe-", "application/postscript"),
+    (b"GIF87a", "image/gif"),
+    (b"GIF89a", "image/gif"),
+    (b"\x89PNG\r\n\x1a\n", "image/png"),
+    (b"\xff\xd8\xff", "image/jpeg"),
+    (b"BM", "image/bmp"),
+    (b"\x00\x00\x01\x00", "image/x-icon"),
+    (b"\x1f\x8b\x08", "application/x-gzip"),
+    (b"PK\x03\x04", "application/zip"),
+    (b"Rar!\x1a\x07\x00", "application/x-rar-compressed"),
+    (b"OggS\x00", "application/ogg"),
+    (b"ID3", "audio/mpeg"),
+];
+
+fn sniff(header: &[u8]) -> &'static str {
+    let start = header.iter().position(|&b| !matches!(b, b'\t' | b'\n' | b'\x0c' | b'\r' | b' ')).unwrap_or(header.len());
+    let trimmed = &header[start..];
+    for tag in HTML_TAGS {
+        let terminated = trimmed.get(tag.len()).is_some_and(|&b| b == b' ' || b == b'>');
+        if terminated && trimmed[..tag.len()].eq_ignore_ascii_case(tag) {
+            return "text/html";
+        }
+    }
+    if trimmed.starts_with(b"<?xml") {
+        return "text/xml";
+    }
+    if let Some((_, mime_type)) = SIGNATURES.iter().find(|(signature, _)| header.starts_with(signature)) {
+        return mime_type;
+    }
+    if header.starts_with(b"RIFF") && header.get(8..14) == Some(b"WEBPVP") {
+        return "image/webp";
+    }
+    // Byte order marks, then anything without control characters, is text.
+    if [&b"\xfe\xff"[..], b"\xff\xfe", b"\xef\xbb\xbf"].iter().any(|bom| header.starts_with(bom)) || !header.iter().copied().any(is_binary) {
+        return "text/plain";
+    }
+    "application/octet-stream"
+}
+
+/// A control byte that does not turn up in text.
+fn is_binary(b: u8) -> bool {
+    matches!(b, 0x00..=0x08 | 0x0b | 0x0e..=0x1a | 0x1c..=0x1f)
+}
+
+/// Wraps plain text in a minimal HTML page so it goes through the same
+/// extraction as HTML: the first line is the title and blank lines separate paragraphs.
+pub fn text_as_html(text: &str) -> String {
+    let escape = |text: &str| text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;");
+    let title = text.lines().map(str::trim).find(|line| !line.is_empty()).unwrap_or("");
+    let mut html = format!("<html><head><title>{}</title></head><body><main>", escape(title));
+    let mut paragraph: Vec<&str> = Vec::new();
+    for line in text.lines().chain([""]) {
+        if !line.trim().is_empty() {
+            paragraph.push(line.trim());
+        } else if !paragraph.is_empty() {
+            html.push_str(&format!("<p>{}</p>", escape(&paragraph.join(" "))));
+            paragraph.clear();
+        }
+    }
+    html.push_str("</main></body></html>");
+    html
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PNG: &[u8] = b"\x89PNG\r\n\x1a\n\x00\x00\x00\rIHDR\x00\x00\x00\x01";
+
+    fn handler(content_type: Option<&str>, body: &[u8]) -> Option<Handler> {
+        ContentTypes::from_config(&ContentTypeConfig::default()).handler(&mime_type(content_type, body))
+    }
+
+    #[test]
+    fn essence_drops_parameters_and_case() {
+        assert_eq!(essence("Text/HTML; charset=UTF-8").as_deref(), Some("text/html"));
+        assert_eq!(essence(" application/xhtml+xml ").as_deref(), Some("application/xhtml+xml"));
+        assert_eq!(essence("text"), None);
+        assert_eq!(essence("text/"), None);
+        assert_eq!(essence("*/*"), None);
+        assert_eq!(essence(""), None);
+    }
+
+    #[test]
+    fn sniffing_without_a_header() {
+        assert_eq!(mime_type(None, b"  \n<!doctype html><html><body>Hi"), "text/html");
+        assert_eq!(mime_type(None, b"<P>Para"), "text/html");
+        // The tag name has to end there: <Preamble> is not <P>.
+        assert_eq!(mime_type(None, b"<Preamble>"), "text/plain");
+        assert_eq!(mime_type(None, b"<?xml version=\"1.0\"?><rss/>"), "text/xml");
+        assert_eq!(mime_type(None, PNG), "image/png");
+        assert_eq!(mime_type(None, b"%PDF-1.7\n"), "application/pdf");
+        assert_eq!(mime_type(None, b"RIFF\x00\x00\x00\x00WEBPVP8 "), "image/webp");
+        assert_eq!(mime_type(None, b"plain words"), "text/plain");
+        assert_eq!(mime_type(None, b"\x00\x01\x02\x03"), "application/octet-stream");
+        // Unusable types are sniffed as if there were no header.
+        assert_eq!(mime_type(Some("application/unknown"), PNG), "image/png");
+        assert_eq!(mime_type(Some("nonsense"), b"<html>"), "text/html");
+    }
+
+    #[test]
+    fn declared_types_are_trusted() {
+        assert_eq!(mime_type(Some("image/png"), b"<html>"), "image/png");
+        assert_eq!(mime_type(Some("text/html; charset=utf-8"), PNG), "text/html");
+    }
+
+    #[test]
+    fn default_text_plain_is_checked_for_binary() {
+        assert_eq!(mime_type(Some("text/plain"), PNG), "application/octet-stream");
+        assert_eq!(mime_type(Some("text/plain; charset=ISO-8859-1"), PNG), "application/octet-stream");
+        assert_eq!(mime_type(Some("text/plain; charset=UTF-8"), b"Just text.\n"), "text/plain");
+        // Only the exact values Apache sends by default are second-guessed.
+        assert_eq!(mime_type(Some("text/plain; charset=windows-1252"), PNG), "text/plain");
+    }
+
+    #[test]
+    fn handlers_follow_the_configured_types() {
+        assert_eq!(handler(None, PNG), None);
+        assert_eq!(handler(Some("application/json"), b"{\"a\": 1}"), None);
+        assert_eq!(handler(None, b"<html><body><p>Hi</p></body></html>"), Some(Handler::Html));
+        assert_eq!(handler(Some("application/xhtml+xml"), b"<html/>"), Some(Handler::Html));
+        assert_eq!(handler(Some("text/plain"), b"Hi"), Some(Handler::Text));
+        assert_eq!(handler(Some("text/markdown"), b"# Hi"), Some(Handler::Text));
+
+        let types = ContentTypes::from_config(&ContentTypeConfig {
+            html: vec![" TEXT/HTML ".to_string()],
+            text: vec!["text/*".to_string()],
+            head_first: false,
+        });
+        assert_eq!(types.handler("text/html"), Some(Handler::Html));
+        assert_eq!(types.handler("text/csv"), Some(Handler::Text));
+        assert_eq!(types.handler("textual/csv"), None);
+    }
+
+    #[test]
+    fn text_becomes_a_titled_page_of_paragraphs() {
+        let html = text_as_html("\n  Release notes\n\nFirst line\nwrapped here.\n\n\n  a < b & c  \n");
+        assert_eq!(
+            html,
+            "<html><head><title>Release notes</title></head><body><main>\
+             <p>Release notes</p><p>First line wrapped here.</p><p>a &lt; b &amp; c</p>\
+             </main></body></html>"
+        );
+    }
+}
@@ -0,0 +1,320 @@
+use chardetng::{EncodingDetector, Iso2022JpDetection, Utf8Detection};
+use encoding_rs::{Encoding, UTF_16BE, UTF_16LE, UTF_8, WINDOWS_1252, X_USER_DEFINED};
+use serde::{Deserialize, Serialize};
+use url::Url;
+
+/// Where a body's character encoding came from, in the order the WHATWG
+/// encoding sniffing algorithm tries them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum EncodingSource {
+    ByteOrderMark,
+    /// The `charset` parameter of `Content-Type`.
+    Header,
+    /// `<meta charset>` or `<meta http-equiv="Content-Type">` in the first 1024 bytes.
+    Meta,
+    /// Guessed from the bytes themselves.
+    Detected,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct DetectedEncoding {
+    pub encoding: &'static Encoding,
+    pub source: EncodingSource,
+}
+
+/// How many bytes the `<meta>` prescan looks at.
+const PRESCAN_LIMIT: usize = 1024;
+
+/// Decodes an HTML body. `content_type` is the response header, if there was
+/// a response; `url` lends its top-level domain to the detector, which
+/// would otherwise have to tell, say, Shift_JIS from EUC-JP on the bytes alone.
+pub fn decode(body: &[u8], content_type: Option<&str>, url: Option<&Url>) -> (String, DetectedEncoding) {
+    let detected = sniff(body, content_type, url);
+    // `decode` drops a byte order mark, which `sniff` has already given precedence.
+    let (text, _, _) = detected.encoding.decode(body);
+    (text.into_owned(), detected)
+}
+
+fn sniff(body: &[u8], content_type: Option<&str>, url: Option<&Url>) -> DetectedEncoding {
+    if let Some((encoding, _)) = Encoding::for_bom(body) {
+        return DetectedEncoding { encoding, source: EncodingSource::ByteOrderMark };
+    }
+    if let Some(encoding) = content_type.and_then(header_charset) {
+        return DetectedEncoding { encoding, source: EncodingSource::Header };
+    }
+    if let Some(encoding) = prescan(&body[..body.len().min(PRESCAN_LIMIT)]) {
+        return DetectedEncoding { encoding, source: EncodingSource::Meta };
+    }
+    // Browsers refuse to guess UTF-8, so that pages keep declaring it, and
+    // ISO-2022-JP, which can hide script; a crawler only wants the text right.
+    let mut detector = EncodingDetector::new(Iso2022JpDetection::Allow);
+    detector.feed(body, true);
+    let tld = url.and_then(Url::domain).and_then(|domain| domain.rsplit('.').next()).map(str::to_ascii_lowercase);
+    let encoding = detector.guess(tld.as_deref().map(str::as_bytes), Utf8Detection::Allow);
+    DetectedEncoding { encoding, source: EncodingSource::Detected }
+}
+
+/// The encoding named by the `charset` parameter of a `Content-Type` value.
+fn header_charset(content_type: &str) -> Option<&'static Encoding> {
+    content_type.split(';').skip(1).find_map(|param| {
+        let (name, value) = param.split_once('=')?;
+        if !name.trim().eq_ignore_ascii_case("charset") {
+            return None;
+        }
+        Encoding::for_label(value.trim().trim_matches(['"', '\'']).as_bytes())
+    })
+}
+
+/// The WHATWG "prescan a byte stream to determine its encoding" algorithm:
+/// finds the first `<meta>` that declares an encoding, skipping comments
+/// and the attributes of other tags.
+fn prescan(bytes: &[u8]) -> Option<&'static Encoding> {
+    let mut scanner = Prescanner { bytes, pos: 0 };
+    while scanner.pos < bytes.len() {
+        let rest = &bytes[scanner.pos..];
+        if rest.starts_with(b"<!--") {
+            // The `-->` may share its dashes with the `<!--`, as in `<!-->`.
+            match find(&rest[2..], b"-->") {
+                Some(end) => scanner.pos += 2 + end + 3,
+                None => return None,
+            }
+            continue;
+        }
+        if starts_with_ignore_case(rest, b"<meta") && rest.get(5).is_some_and(|&b| is_space(b) || b == b'/') {
+            scanner.pos += 5;
+            if let Some(encoding) = scanner.meta() {
+                return Some(encoding);
+            }
+            continue;
+        }
+        if matches!(rest, [b'<', b'/', letter, ..] | [b'<', letter, ..] if letter.is_ascii_alphabetic()) {
+            // Skip the tag name, then its attributes, so `<a title="<meta charset=x>">` is not read as a meta.
+            scanner.pos += bytes[scanner.pos..].iter().position(|&b| is_space(b) || b == b'>').unwrap_or(rest.len());
+            while scanner.attribute().is_some() {}
+        } else if rest.starts_with(b"<!") || rest.starts_with(b"</") || rest.starts_with(b"<?") {
+            scanner.pos += rest.iter().position(|&b| b == b'>').unwrap_or(rest.len());
+        }
+        scanner.pos += 1;
+    }
+    None
+}
+
+struct Prescanner<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+}
+
+impl Prescanner<'_> {
+    /// Reads the attributes of a `<meta>` and returns the encoding it declares, if any.
+    fn meta(&mut self) -> Option<&'static Encoding> {
+        let mut seen: Vec<Vec<u8>> = Vec::new();
+        let mut got_pragma = false;
+        let mut need_pragma = None;
+        let mut charset = None;
+        while let Some((name, value)) = self.attribute() {
+            if seen.contains(&name) {
+                continue;
+            }
+            match name.as_slice() {
+                b"http-equiv" => got_pragma |= value.eq_ignore_ascii_case(b"content-type"),
+                b"content" if charset.is_none() => {
+                    if let Some(encoding) = meta_content_charset(&value) {
+                        charset = Some(encoding);
+                        need_pragma = Some(true);
+                    }
+                }
+                b"charset" if charset.is_none() => {
+                    charset = Encoding::for_label(&value);
+                    need_pragma = Some(false);
+                }
+                _ => {}
+            }
+            seen.push(name);
+        }
+        if need_pragma? && !got_pragma {
+            return None;
+        }
+        // A document that can be prescanned as ASCII cannot really be UTF-16.
+        match charset? {
+            encoding if encoding == UTF_16BE || encoding == UTF_16LE => Some(UTF_8),
+            encoding if encoding == X_USER_DEFINED => Some(WINDOWS_1252),
+            encoding => Some(encoding),
+        }
+    }
+
+    /// The WHATWG "get an attribute" steps: the next name, lower-cased, and
+    /// value, or `None` at the end of the tag.
+    fn attribute(&mut self) -> Option<(Vec<u8>, Vec<u8>)> {
+        while self.peek().is_some_and(|b| is_space(b) || b == b'/') {
+            self.pos += 1;
+        }
+        if self.peek()? == b'>' {
+            return None;
+        }
+        let mut name = Vec::new();
+        loop {
+            match self.peek()? {
+                b'=' if !name.is_empty() => {
+                    self.pos += 1;
+                    break;
+                }
+                b if is_space(b) => {
+                    self.skip_spaces();
+                    if self.peek()? != b'=' {
+                        return Some((name, Vec::new()));
+                    }
+                    self.pos += 1;
+                    break;
+                }
+                b'/' | b'>' => return Some((name, Vec::new())),
+                b => {
+                    name.push(b.to_ascii_lowercase());
+                    self.pos += 1;
+                }
+            }
+        }
+        self.skip_spaces();
+        let mut value = Vec::new();
+        match self.peek()? {
+            quote @ (b'"' | b'\'') => {
+                self.pos += 1;
+                loop {
+                    let b = self.peek()?;
+                    self.pos += 1;
+                    if b == quote {
+                        return Some((name, value));
+                    }
+                    value.push(b.to_ascii_lowercase());
+                }
+            }
+            b'>' => Some((name, value)),
+            _ => {
+                while let Some(b) = self.peek().filter(|&b| !is_space(b) && b != b'>') {
+                    value.push(b.to_ascii_lowercase());
+                    self.pos += 1;
+                }
+                Some((name, value))
+            }
+        }
+    }
+
+    fn peek(&self) -> Option<u8> {
+        self.bytes.get(self.pos).copied()
+    }
+
+    fn skip_spaces(&mut self) {
+        while self.peek().is_some_and(is_space) {
+            self.pos += 1;
+        }
+    }
+}
+
+/// The WHATWG "extract a character encoding from a meta element" steps,
+/// for `content="text/html; charset=shift_jis"`.
+fn meta_content_charset(content: &[u8]) -> Option<&'static Encoding> {
+    let mut rest = content;
+    loop {
+        let at = find(rest, b"charset")?;
+        rest = &rest[at + 7..];
+        let after_spaces = &rest[rest.iter().position(|&b| !is_space(b)).unwrap_or(rest.len())..];
+        if let Some(value) = after_spaces.strip_prefix(b"=") {
+            rest = value;
+            break;
+        }
+    }
+    let rest = &rest[rest.iter().position(|&b| !is_space(b))?..];
+    let value = match rest[0] {
+        quote @ (b'"' | b'\'') => {
+            let end = rest[1..].iter().position(|&b| b == quote)?;
+            &rest[1..1 + end]
+        }
+        _ => &rest[..rest.iter().position(|&b| is_space(b) || b == b';').unwrap_or(rest.len())],
+    };
+    Encoding::for_label(value)
+}
+
+fn is_space(b: u8) -> bool {
+    matches!(b, b'\t' | b'\n' | b'\x0c' | b'\r' | b' ')
+}
+
+fn starts_with_ignore_case(bytes: &[u8], prefix: &[u8]) -> bool {
+    bytes.len() >= prefix.len() && bytes[..prefix.len()].eq_ignore_ascii_case(prefix)
+}
+
+fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack.windows(needle.len()).position(|window| window == needle)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use encoding_rs::{KOI8_R, SHIFT_JIS};
+
+    fn sniffed(body: &[u8], content_type: Option<&str>) -> (&'static str, EncodingSource) {
+        let detected = sniff(body, content_type, None);
+        (detected.encoding.name(), detected.source)
+    }
+
+    #[test]
+    fn byte_order_mark_beats_header_and_meta() {
+        let mut body = vec![0xFF, 0xFE];
+        body.extend("<meta charset=koi8-r>".encode_utf16().flat_map(u16::to_le_bytes));
+        assert_eq!(sniffed(&body, Some("text/html; charset=windows-1252")), ("UTF-16LE", EncodingSource::ByteOrderMark));
+    }
+
+    #[test]
+    fn header_charset_beats_meta() {
+        let body = b"<html><head><meta charset=\"utf-8\"></head></html>";
+        assert_eq!(sniffed(body, Some("text/html; charset=\"KOI8-R\"")), ("KOI8-R", EncodingSource::Header));
+        // An unknown label is ignored rather than trusted.
+        assert_eq!(sniffed(body, Some("text/html; charset=bogus")), ("UTF-8", EncodingSource::Meta));
+    }
+
+    #[test]
+    fn meta_charset_and_http_equiv() {
+        assert_eq!(prescan(b"<html><head><meta charset=\"windows-1252\"><title>Caf\xe9</title>"), Some(WINDOWS_1252));
+        assert_eq!(
+            prescan(b"<meta http-equiv=\"Content-Type\" content=\"text/html; charset=Shift_JIS\">"),
+            Some(SHIFT_JIS)
+        );
+        // `content` only counts alongside `http-equiv="Content-Type"`.
+        assert_eq!(prescan(b"<meta name=\"x\" content=\"text/html; charset=Shift_JIS\">"), None);
+        // Whichever of `content` and `charset` comes first wins.
+        assert_eq!(
+            prescan(b"<meta http-equiv=content-type content=\"text/html; charset=koi8-r\" charset=utf-8>"),
+            Some(KOI8_R)
+        );
+        assert_eq!(prescan(b"<META CHARSET='Shift_JIS'>"), Some(SHIFT_JIS));
+    }
+
+    #[test]
+    fn comments_and_other_tags_attributes_are_skipped() {
+        assert_eq!(prescan(b"<!-- <meta charset=koi8-r> --><p>x</p>"), None);
+        assert_eq!(prescan(b"<!--><meta charset=koi8-r>"), Some(KOI8_R));
+        assert_eq!(prescan(b"<a title=\"<meta charset=koi8-r>\">x</a>"), None);
+        assert_eq!(prescan(b"<a title=\"<meta charset=koi8-r>\">x</a><meta charset=shift_jis>"), Some(SHIFT_JIS));
+    }
+
+    #[test]
+    fn utf16_and_x_user_defined_labels_are_mapped() {
+        assert_eq!(prescan(b"<meta charset=utf-16le>"), Some(UTF_8));
+        assert_eq!(prescan(b"<meta charset=x-user-defined>"), Some(WINDOWS_1252));
+    }
+
+    #[test]
+    fn undeclared_bodies_are_detected() {
+        let (shift_jis, _, _) = SHIFT_JIS.encode("<p>日本語のテキストです。これはテストです。東京は日本の首都です。</p>");
+        assert_eq!(sniffed(&shift_jis, None), ("Shift_JIS", EncodingSource::Detected));
+        let (windows_1252, _, _) = WINDOWS_1252.encode("<p>Café crème brûlée, a naïve résumé of “quoted” déjà vu.</p>");
+        assert_eq!(sniffed(&windows_1252, None), ("windows-1252", EncodingSource::Detected));
+    }
+
+    #[test]
+    fn decode_uses_the_sniffed_encoding() {
+        let (body, _, _) = SHIFT_JIS.encode("<meta charset=shift_jis><p>日本語</p>");
+        let (text, detected) = decode(&body, None, None);
+        assert_eq!(text, "<meta charset=shift_jis><p>日本語</p>");
+        assert_eq!(detected.source, EncodingSource::Meta);
+    }
+}
@@ -0,0 +1,186 @@
+use serde::{Deserialize, Serialize};
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, HashMap};
+
+/// A URL waiting to be crawled.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FrontierEntry {
+    pub url: String,
+    pub depth: usize,
+}
+
+/// Decides which queued URL is crawled next. Entries with the smallest key
+/// go first; `seq` is the order in which entries were pushed.
+pub trait FrontierOrder: Send {
+    fn key(&self, entry: &FrontierEntry, seq: u64) -> (i64, i64);
+
+    /// Whether an entry may only be handed out once nothing shallower than
+    /// its parent depth is still in flight. This is what makes breadth-first
+    /// depths shortest-path even when pages finish out of order.
+    fn is_level_synchronous(&self) -> bool {
+        false
+    }
+}
+
+pub struct BreadthFirst;
+
+impl FrontierOrder for BreadthFirst {
+    fn key(&self, entry: &FrontierEntry, seq: u64) -> (i64, i64) {
+        (entry.depth as i64, seq as i64)
+    }
+
+    fn is_level_synchronous(&self) -> bool {
+        true
+    }
+}
+
+pub struct DepthFirst;
+
+impl FrontierOrder for DepthFirst {
+    fn key(&self, entry: &FrontierEntry, seq: u64) -> (i64, i64) {
+        (-(entry.depth as i64), -(seq as i64))
+    }
+}
+
+/// The built-in orderings, as named in crawl profiles.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum CrawlOrder {
+    #[default]
+    BreadthFirst,
+    DepthFirst,
+}
+
+impl CrawlOrder {
+    pub fn build(self) -> Box<dyn FrontierOrder> {
+        match self {
+            CrawlOrder::BreadthFirst => Box::new(BreadthFirst),
+            CrawlOrder::DepthFirst => Box::new(DepthFirst),
+        }
+    }
+}
+
+#[derive(PartialEq, Eq, PartialOrd, Ord)]
+struct Queued {
+    key: (i64, i64),
+    depth: usize,
+    url: String,
+}
+
+/// The queue of URLs still to crawl. A URL is held at most once; pushing it
+/// again at a shallower depth moves it up rather than adding a duplicate.
+pub struct Frontier {
+    order: Box<dyn FrontierOrder>,
+    heap: BinaryHeap<Reverse<Queued>>,
+    queued: HashMap<String, usize>,
+    seq: u64,
+}
+
+impl Frontier {
+    pub fn new(order: Box<dyn FrontierOrder>) -> Self {
+        Frontier {
+            order,
+            heap: BinaryHeap::new(),
+            queued: HashMap::new(),
+            seq: 0,
+        }
+    }
+
+    /// Queues `url` at `depth`, returning false if it is already queued at the same depth or shallower.
+    pub fn push(&mut self, url: String, depth: usize) -> bool {
+        if self.queued.get(&url).is_some_and(|&queued_depth| queued_depth <= depth) {
+            return false;
+        }
+        let entry = FrontierEntry { url, depth };
+        let key = self.order.key(&entry, self.seq);
+        self.seq += 1;
+        self.queued.insert(entry.url.clone(), depth);
+        self.heap.push(Reverse(Queued { key, depth, url: entry.url }));
+        true
+    }
+
+    /// Takes the next URL to crawl. `min_in_flight_depth` is the shallowest
+    /// depth still being fetched; for level-synchronous orders nothing more
+    /// than one level below it is released, because an in-flight page could
+    /// still discover a shorter path to it.
+    pub fn pop(&mut self, min_in_flight_depth: Option<usize>) -> Option<FrontierEntry> {
+        loop {
+            let Reverse(next) = self.heap.peek()?;
+            if self.queued.get(&next.url) != Some(&next.depth) {
+                // Superseded by a shallower push of the same URL.
+                self.heap.pop();
+                continue;
+            }
+            if self.order.is_level_synchronous() && min_in_flight_depth.is_some_and(|min| next.depth > min + 1) {
+                return None;
+            }
+            let Reverse(next) = self.heap.pop()?;
+            self.queued.remove(&next.url);
+            return Some(FrontierEntry { url: next.url, depth: next.depth });
+        }
+    }
+
+    /// The queued URLs and their depths, in no particular order.
+    pub fn entries(&self) -> impl Iterator<Item = (&String, usize)> {
+        self.queued.iter().map(|(url, &depth)| (url, depth))
+    }
+
+    pub fn len(&self) -> usize {
+        self.queued.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.queued.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn drain(frontier: &mut Frontier) -> Vec<(String, usize)> {
+        std::iter::from_fn(|| frontier.pop(None)).map(|entry| (entry.url, entry.depth)).collect()
+    }
+
+    #[test]
+    fn a_shorter_path_found_later_wins() {
+        let mut frontier = Frontier::new(CrawlOrder::BreadthFirst.build());
+        assert!(frontier.push("https://example.org/a".to_string(), 0));
+        assert!(frontier.push("https://example.org/deep".to_string(), 3));
+        assert!(!frontier.push("https://example.org/deep".to_string(), 4));
+        assert!(frontier.push("https://example.org/deep".to_string(), 1));
+        assert!(!frontier.push("https://example.org/deep".to_string(), 3));
+        assert_eq!(frontier.len(), 2);
+        assert_eq!(
+            drain(&mut frontier),
+            [("https://example.org/a".to_string(), 0), ("https://example.org/deep".to_string(), 1)]
+        );
+        assert!(frontier.is_empty());
+    }
+
+    #[test]
+    fn breadth_first_waits_for_shallower_pages_in_flight() {
+        let mut frontier = Frontier::new(CrawlOrder::BreadthFirst.build());
+        frontier.push("https://example.org/b".to_string(), 2);
+        frontier.push("https://example.org/a".to_string(), 2);
+        // A depth-0 page still being fetched could link to either at depth 1.
+        assert_eq!(frontier.pop(Some(0)), None);
+        assert_eq!(frontier.len(), 2);
+        let next = frontier.pop(Some(1)).unwrap();
+        assert_eq!((next.url.as_str(), next.depth), ("https://example.org/b", 2));
+        assert_eq!(frontier.pop(Some(2)).map(|entry| entry.depth), Some(2));
+    }
+
+    #[test]
+    fn depth_first_takes_the_newest_deepest_entry() {
+        let mut frontier = Frontier::new(CrawlOrder::DepthFirst.build());
+        frontier.push("https://example.org/a".to_string(), 1);
+        frontier.push("https://example.org/b".to_string(), 2);
+        frontier.push("https://example.org/c".to_string(), 2);
+        assert_eq!(frontier.pop(Some(0)).map(|entry| entry.url), Some("https://example.org/c".to_string()));
+        assert_eq!(
+            drain(&mut frontier),
+            [("https://example.org/b".to_string(), 2), ("https://example.org/a".to_string(), 1)]
+        );
+    }
+}
@@ -0,0 +1,168 @@
+use crate::config::{ConfigError, LanguageConfig};
+use lingua::{Language, LanguageDetector, LanguageDetectorBuilder};
+use scraper::{Html, Selector};
+
+/// The language a page (or a chunk of it) is written in.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DetectedLanguage {
+    /// ISO 639-1 code, e.g. `cy` or `zh`.
+    pub code: String,
+    /// The detector's confidence in `code`, from 0 to 1. `None` when the
+    /// code was only declared and the detector doesn't know the language.
+    pub confidence: Option<f64>,
+}
+
+/// Works out languages from what the page declares and from an offline
+/// n-gram detector run over its text. A confident detection beats the
+/// declaration, because templates often say `lang="en"` whatever the content.
+pub struct LanguageIdentifier {
+    /// `None` when `language.detect` is off.
+    detector: Option<LanguageDetector>,
+    min_confidence: f64,
+    per_chunk: bool,
+}
+
+impl LanguageIdentifier {
+    pub fn from_config(config: &LanguageConfig) -> Result<Self, ConfigError> {
+        let detector = if config.detect {
+            let mut builder = if config.languages.is_empty() {
+                LanguageDetectorBuilder::from_all_languages()
+            } else {
+                let languages = config
+                    .languages
+                    .iter()
+                    .enumerate()
+                    .map(|(i, code)| {
+                        supported(code).ok_or_else(|| {
+                            ConfigError::new(format!("language.languages[{}]", i), format!("{:?} is not a supported ISO 639-1 code", code))
+                        })
+                    })
+                    .collect::<Result<Vec<_>, _>>()?;
+                LanguageDetectorBuilder::from_languages(&languages)
+            };
+            Some(builder.build())
+        } else {
+            None
+        };
+        Ok(LanguageIdentifier { detector, min_confidence: config.min_confidence, per_chunk: config.per_chunk })
+    }
+
+    /// The page's language: the detected one when its confidence reaches
+    /// `min_confidence`, otherwise the declared one, otherwise the best guess.
+    pub fn page(&self, text: &str, declared: Option<&str>) -> Option<DetectedLanguage> {
+        let Some(detector) = &self.detector else {
+            return declared.map(|code| DetectedLanguage { code: code.to_string(), confidence: None });
+        };
+        let values = detector.compute_language_confidence_values(text);
+        // Text with no letters in it scores zero for every language.
+        let best = values.first().filter(|(_, confidence)| *confidence > 0.0);
+        match (best, declared) {
+            (Some(&(language, confidence)), _) if confidence >= self.min_confidence => {
+                Some(DetectedLanguage { code: code(language), confidence: Some(confidence) })
+            }
+            (best, Some(declared)) => Some(DetectedLanguage {
+                code: declared.to_string(),
+                confidence: best.and_then(|_| values.iter().find(|(language, _)| code(*language) == declared)).map(|(_, confidence)| *confidence),
+            }),
+            (Some(&(language, confidence)), None) => Some(DetectedLanguage { code: code(language), confidence: Some(confidence) }),
+            (None, None) => None,
+        }
+    }
+
+    /// A chunk's language: its own when that is confidently detected, so the
+    /// Welsh half of a bilingual page is labelled `cy`, otherwise the page's.
+    pub fn chunk(&self, text: &str, page: Option<&DetectedLanguage>) -> Option<String> {
+        let detected = self
+            .detector
+            .as_ref()
+            .filter(|_| self.per_chunk)
+            .and_then(|detector| detector.compute_language_confidence_values(text).into_iter().next())
+            .filter(|(_, confidence)| *confidence >= self.min_confidence)
+            .map(|(language, _)| code(language));
+        detected.or_else(|| page.map(|page| page.code.clone()))
+    }
+}
+
+/// The primary subtag of `<html lang>`, or of the first `Content-Language`
+/// entry: `en-GB` becomes `en`.
+pub fn declared(document: &Html, content_language: Option<&str>) -> Option<String> {
+    let html = Selector::parse("html[lang]").unwrap();
+    let lang = document.select(&html).next().and_then(|html| html.value().attr("lang"));
+    [lang, content_language.and_then(|header| header.split(',').next())]
+        .into_iter()
+        .flatten()
+        .filter_map(|tag| tag.trim().split(['-', '_']).next())
+        .map(str::to_ascii_lowercase)
+        .find(|subtag| (2..=3).contains(&subtag.len()) && subtag.chars().all(|c| c.is_ascii_alphabetic()))
+}
+
+fn code(language: Language) -> String {
+    language.iso_code_639_1().to_string()
+}
+
+/// The compiled-in language with ISO 639-1 code `code`.
+fn supported(code: &str) -> Option<Language> {
+    let code = code.trim().to_ascii_lowercase();
+    Language::all().into_iter().find(|language| language.iso_code_639_1().to_string() == code)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ENGLISH: &str = "Our open days are the best way to find out what studying here is like. \
+        Tour the campus, meet academics and current students, and see where you could be living.";
+    const WELSH: &str = "Ein diwrnodau agored yw'r ffordd orau o ddarganfod sut beth yw astudio yma. \
+        Ewch ar daith o amgylch y campws a chwrdd â'r myfyrwyr presennol a'r staff academaidd.";
+
+    fn identifier(per_chunk: bool) -> LanguageIdentifier {
+        let languages = ["en", "cy", "zh", "fr", "de"].iter().map(|s| s.to_string()).collect();
+        LanguageIdentifier::from_config(&LanguageConfig { languages, per_chunk, ..LanguageConfig::default() }).unwrap()
+    }
+
+    #[test]
+    fn detects_an_english_page() {
+        let page = identifier(true).page(ENGLISH, None).unwrap();
+        assert_eq!(page.code, "en");
+        assert!(page.confidence.unwrap() >= 0.5);
+        // A template's wrong declaration loses to a confident detection.
+        assert_eq!(identifier(true).page(ENGLISH, Some("cy")).unwrap().code, "en");
+    }
+
+    #[test]
+    fn labels_each_chunk_of_a_mixed_page() {
+        let identifier = identifier(true);
+        // Whichever language wins for the page as a whole, each chunk gets its own.
+        let page = identifier.page(&format!("{} {}", ENGLISH, WELSH), Some("en"));
+        let page_code = page.as_ref().map(|page| page.code.clone());
+        assert!(page_code.is_some());
+        assert_eq!(identifier.chunk(ENGLISH, page.as_ref()).as_deref(), Some("en"));
+        assert_eq!(identifier.chunk(WELSH, page.as_ref()).as_deref(), Some("cy"));
+        assert_eq!(identifier.chunk("欢迎参加我们的开放日，来参观校园，与在校学生交流。", page.as_ref()).as_deref(), Some("zh"));
+        // A chunk with nothing to go on keeps the page's language.
+        assert_eq!(identifier.chunk("2024", page.as_ref()), page_code);
+
+        let declared_only = Some(DetectedLanguage { code: "en".to_string(), confidence: None });
+        assert_eq!(self::identifier(false).chunk(WELSH, declared_only.as_ref()).as_deref(), Some("en"));
+    }
+
+    #[test]
+    fn short_text_falls_back_to_the_declared_language() {
+        let identifier = identifier(true);
+        let page = identifier.page("OK", Some("cy")).unwrap();
+        assert_eq!(page.code, "cy");
+        assert_eq!(identifier.page("12:30 – 14:00", Some("cy")).unwrap(), DetectedLanguage { code: "cy".to_string(), confidence: None });
+        assert_eq!(identifier.page("12:30 – 14:00", None), None);
+    }
+
+    #[test]
+    fn declared_language_ignores_invalid_lang_attributes() {
+        let page = |lang: &str| Html::parse_document(&format!(r#"<html lang="{}"><body></body></html>"#, lang));
+        assert_eq!(declared(&page("en-GB"), None).as_deref(), Some("en"));
+        assert_eq!(declared(&page("CY_gb"), None).as_deref(), Some("cy"));
+        assert_eq!(declared(&page("english"), None), None);
+        assert_eq!(declared(&page("{{ lang }}"), Some("cy, en")).as_deref(), Some("cy"));
+        assert_eq!(declared(&page(""), Some("zh-Hans")).as_deref(), Some("zh"));
+        assert_eq!(declared(&Html::parse_document("<p>Hi</p>"), None), None);
+    }
+}
@@ -1,10 +1,57 @@
+mod checkpoint;
+mod chunking;
+mod cli;
+mod config;
+mod content_type;
+mod encoding;
+mod frontier;
+mod language;
+mod markdown;
+mod output;
+mod politeness;
+mod readability;
+mod robots;
+mod sentences;
+mod sitemap;
+mod source;
+mod structured_data;
+mod tables;
+mod tokenizer;
+mod warc;
+
 use chrono::{DateTime, Utc};
-use reqwest::header::USER_AGENT;
+use clap::Parser;
+use checkpoint::{Checkpoint, CheckpointProgress, CheckpointState};
+use chunking::{Chunker, TextHeading};
+use cli::{Cli, Command};
+use config::{ConfigError, CrawlConfig};
+use content_type::{ContentTypes, Handler};
+use encoding::{DetectedEncoding, EncodingSource};
+use frontier::Frontier;
+use language::LanguageIdentifier;
+use markdown::MarkdownRenderer;
+use output::{CrawlInfo, OutputSink};
+use politeness::HostScheduler;
+use readability::{ContentScorer, MainContent};
+use robots::RobotsCache;
+use sitemap::{Sitemap, SitemapEntry};
+use tables::Table;
+use source::{PageSource, UrlMapping};
+use structured_data::StructuredData;
+use warc::{HttpResponse, WarcReader, WarcWriter};
+use reqwest::header::{HeaderMap, HeaderValue, USER_AGENT};
+use reqwest::{Method, StatusCode};
+use ego_tree::NodeId;
 use scraper::{Html, Selector, Node, ElementRef, Element}; // Added Element
 use serde::{Deserialize, Serialize};
-use std::collections::HashSet; 
+use std::collections::hash_map::Entry;
+use std::collections::{BTreeMap, HashMap, HashSet, VecDeque};
 use std::fs;
-use std::path::Path;
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+use std::sync::Mutex;
+use std::thread;
+use std::time::{Duration, Instant};
 use url::Url; 
 use regex::Regex; // Ensure this crate is in Cargo.toml
 
@@ -15,42 +62,131 @@ struct CrawlOutput {
     crawl_timestamp: DateTime<Utc>,
     total_pages: usize,
     pages: Vec<PageData>,
+    #[serde(default)]
+    skipped: Vec<SkippedUrl>,
+}
+
+/// An in-scope URL that was deliberately not fetched, or fetched but not
+/// extracted because of its content type.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+struct SkippedUrl {
+    url: String,
+    reason: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    found_on: Option<String>,
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
 struct PageData {
     url: String,
     title: String,
+    /// SHA-256 of the normalised `full_text`, for spotting pages whose content changed.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    content_hash: Option<String>,
     content: PageContent,
     metadata: PageMetadata,
+    #[serde(default, skip_serializing_if = "StructuredData::is_empty")]
+    structured_data: StructuredData,
     links: Vec<LinkData>,
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
 struct PageContent {
     full_text: String,
+    /// Every heading in document order.
     headings: Vec<Heading>,
+    /// The same headings nested under their parents.
+    #[serde(default)]
+    outline: Vec<HeadingNode>,
     paragraphs: Vec<String>,
     lists: Vec<String>,
+    #[serde(default)]
+    tables: Vec<Table>,
+    /// The main content as Markdown, keeping its structure for LLM pipelines.
+    #[serde(default)]
+    markdown: String,
+    /// Every sentence of `full_text`, with `chunking.sentence_spans`.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    sentences: Vec<SentenceSpan>,
     chunks: Vec<TextChunk>,
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
 struct Heading {
+    /// Position among the page's headings.
+    #[serde(default)]
+    id: usize,
     level: u8,
     text: String,
     #[serde(skip_serializing_if = "Option::is_none")]
     parent_heading: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    parent_id: Option<usize>,
+    /// Fragment that links to the heading, from its `id` or a named anchor inside it.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    anchor: Option<String>,
+    /// Byte range of the heading's section in `full_text`, when its text is there.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    section_start: Option<usize>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    section_end: Option<usize>,
+}
+
+/// A heading with the headings under it.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+struct HeadingNode {
+    id: usize,
+    level: u8,
+    text: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    anchor: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    section_start: Option<usize>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    section_end: Option<usize>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    children: Vec<HeadingNode>,
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
 struct TextChunk {
     chunk_id: String,
     text: String,
+    /// `full_text[byte_start..byte_end]` is exactly `text`, whitespace at its edges included.
+    #[serde(default)]
+    byte_start: usize,
+    #[serde(default)]
+    byte_end: usize,
+    /// The same span counted in Unicode scalar values.
     char_start: usize,
     char_end: usize,
+    /// The same span counted in UTF-16 code units, with `chunking.utf16_offsets`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    utf16_start: Option<usize>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    utf16_end: Option<usize>,
     #[serde(skip_serializing_if = "Option::is_none")]
     section_heading: Option<String>,
+    /// Tokens in `text` by the `chunking.tokenizer`; absent when sizes are in bytes.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    token_count: Option<usize>,
+    /// ISO 639-1 code; the chunk's own when it is confidently detected, otherwise the page's.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    language: Option<String>,
+}
+
+/// A sentence of `full_text`, with offsets like those of `TextChunk`.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+struct SentenceSpan {
+    text: String,
+    byte_start: usize,
+    byte_end: usize,
+    char_start: usize,
+    char_end: usize,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    utf16_start: Option<usize>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    utf16_end: Option<usize>,
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
@@ -58,8 +194,33 @@ struct PageMetadata {
     crawl_timestamp: DateTime<Utc>,
     depth: usize,
     word_count: usize,
+    /// ISO 639-1 code, see `language::LanguageIdentifier::page`.
     language: Option<String>,
+    /// The detector's confidence in `language`, from 0 to 1.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    language_confidence: Option<f64>,
+    /// The primary subtag of `<html lang>`, or failing that of `Content-Language`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    declared_language: Option<String>,
+    /// WHATWG name of the body's character encoding, such as `Shift_JIS`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    encoding: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    encoding_source: Option<EncodingSource>,
+    /// The `Content-Type` essence, or the sniffed type when there was none.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    mime_type: Option<String>,
+    /// Length of the body as received, before decoding.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    byte_size: Option<usize>,
     description: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    lastmod: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    sitemap_priority: Option<f32>,
+    /// Selector path of the element the content was extracted from.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    content_selector: Option<String>,
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
@@ -76,11 +237,102 @@ enum LinkType {
     Anchor,
 }
 
+/// A response body handed from a fetch worker, or read from a `PageSource`, to a parser.
+struct FetchedPage {
+    url: String,
+    depth: usize,
+    body: Result<Body, String>,
+}
+
+/// A body as it arrived, with the headers extraction needs. It is decoded
+/// only once its type is known to be one that is extracted.
+struct Body {
+    bytes: Vec<u8>,
+    content_type: Option<String>,
+    /// The response's `Content-Language` header; local files have none.
+    content_language: Option<String>,
+}
+
+impl FetchedPage {
+    /// Takes the body of a response, or carries the error that left none.
+    fn from_response(url: String, depth: usize, response: Result<HttpResponse, String>) -> Self {
+        let body = response.map(|response| Body {
+            content_type: response.header("content-type").map(str::to_string),
+            content_language: response.header("content-language").map(str::to_string),
+            bytes: response.body,
+        });
+        FetchedPage { url, depth, body }
+    }
+}
+
+/// What is known about a body once its type and encoding are settled.
+struct BodyInfo<'a> {
+    body: &'a Body,
+    mime_type: String,
+    encoding: DetectedEncoding,
+}
+
+/// What became of a fetched URL.
+enum Extraction {
+    Page(Box<PageData>),
+    /// Left alone because robots.txt forbids it or its content type is not
+    /// one that is extracted; the reason says which.
+    Skipped(String),
+    Failed(String),
+    /// Not fetched yet because its host's robots.txt could not be loaded.
+    Deferred,
+}
+
+/// How many times a URL is tried while its host's robots.txt cannot be
+/// loaded, `robots.retry_unreachable_secs` apart, before it is skipped.
+const ROBOTS_ATTEMPTS: usize = 3;
+
+/// Redirects followed for a page before giving up, as many as reqwest follows by default.
+const MAX_REDIRECTS: usize = 10;
+
+/// The outcome of fetching and parsing one URL, sent back to the crawl loop.
+struct CrawledPage {
+    url: String,
+    depth: usize,
+    page: Extraction,
+}
+
+/// What `filter_url` decided about a link.
+enum UrlFilter {
+    /// In scope, and not known to be disallowed: a host whose robots.txt
+    /// isn't loaded yet is checked by the fetch worker.
+    Accept(String),
+    /// In scope, but robots.txt forbids fetching it.
+    Disallowed { url: String, reason: String },
+    Reject,
+}
+
 struct Crawler {
     visited: HashSet<String>,
-    pages: Vec<PageData>,
+    page_count: usize,
+    skipped: Vec<SkippedUrl>,
     domain: String,
+    allowed_hosts: HashSet<String>,
+    include: Vec<Regex>,
+    exclude: Vec<Regex>,
+    selectors: Selectors,
+    chunker: Chunker,
+    language: LanguageIdentifier,
+    content_types: ContentTypes,
+    robots: Option<RobotsCache>,
+    politeness: HostScheduler,
+    /// Sitemap `<url>` entries keyed by normalised URL, attached to the pages they describe.
+    sitemap_entries: HashMap<String, SitemapEntry>,
+    /// Behind a mutex only so that `Crawler` stays `Sync`; the crawl loop is its sole user.
+    checkpoint: Option<Mutex<Checkpoint>>,
+    /// Every finished page is handed to each of these. Behind a mutex for the same reason as `checkpoint`.
+    outputs: Mutex<Vec<Box<dyn OutputSink>>>,
+    /// Shared by the fetch workers, which archive each response as it arrives.
+    warc: Option<Mutex<WarcWriter>>,
+    config: CrawlConfig,
     client: reqwest::blocking::Client,
+    /// Like `client` but leaves redirects to `fetch_page`, so every hop can be archived.
+    page_client: reqwest::blocking::Client,
 }
 
 // Helper struct to hold common selectors
@@ -90,117 +342,468 @@ struct Selectors {
     always_remove: Selector,
     cookie_banner_text: Vec<String>,
     json_like_pattern: Regex,
+    /// `None` when `selectors.content_scoring` is off and the first `main_content` match is taken.
+    content_scorer: Option<ContentScorer>,
 }
 
 impl Selectors {
-    fn new() -> Self {
-        Selectors {
-            main_content: vec![
-                Selector::parse("main").unwrap(),
-                Selector::parse("[role='main']").unwrap(),
-                Selector::parse("#main-content").unwrap(),
-                Selector::parse("#content").unwrap(),
-                Selector::parse(".main-content").unwrap(),
-                Selector::parse(".content").unwrap(),
-                Selector::parse("article").unwrap(),
-            ],
-            boilerplate: vec![
-                Selector::parse("header").unwrap(),
-                Selector::parse("footer").unwrap(),
-                Selector::parse("nav").unwrap(),
-                Selector::parse("aside").unwrap(),
-                Selector::parse(".cookie-banner").unwrap(), 
-                Selector::parse("#cookie-consent").unwrap(), 
-                Selector::parse(".sidebar").unwrap(),
-                Selector::parse("div.secondary-navigation").unwrap(),
-                Selector::parse("div.global-main-menu").unwrap(),
-                Selector::parse("div.footer-menu").unwrap(),
-                Selector::parse("div#onetrust-consent-sdk").unwrap(),
-            ],
-            always_remove: Selector::parse("script, style, noscript, svg, path, button, form, input, textarea, select, option, figure > figcaption, .visually-hidden, [aria-hidden='true']").unwrap(),
-            cookie_banner_text: vec![
-                "cookies we use cookies to help our site work".to_string(),
-                "by accepting, you agree to cookies being stored".to_string(),
-                "manage settings accept".to_string(),
-            ],
+    fn from_config(config: &config::SelectorConfig) -> Result<Self, ConfigError> {
+        Ok(Selectors {
+            main_content: config::parse_selectors("selectors.main_content", &config.main_content)?,
+            boilerplate: config::parse_selectors("selectors.boilerplate", &config.boilerplate)?,
+            always_remove: config::parse_selector("selectors.always_remove", &config.always_remove)?,
+            cookie_banner_text: config.cookie_banner_text.iter().map(|s| s.to_lowercase()).collect(),
             json_like_pattern: Regex::new(r#"\A\{.*\}\z|\A\[.*\]\z"#).unwrap(),
-        }
+            content_scorer: config.content_scoring.then(ContentScorer::new),
+        })
+    }
+
+    /// Cookie banner phrases and inline JSON, which are never page content.
+    fn is_noise_text(&self, text: &str) -> bool {
+        let lower = text.to_lowercase();
+        self.cookie_banner_text.iter().any(|p| lower.contains(p))
+            || self.json_like_pattern.is_match(text)
+            || lower.contains("permissionshash")
     }
 }
 
 
 impl Crawler {
-    fn new(root_url: &str) -> Result<Self, Box<dyn std::error::Error>> {
-        let url = Url::parse(root_url)?;
-        let domain = url.host_str().unwrap_or("").to_string();
+    fn new(config: CrawlConfig) -> Result<Self, Box<dyn std::error::Error>> {
+        config.validate()?;
+        
+        let mut allowed_hosts: HashSet<String> = config.allowed_hosts.iter().cloned().collect();
+        for seed in &config.seeds {
+            let url = Url::parse(seed)?;
+            allowed_hosts.insert(url.host_str().unwrap_or("").to_string());
+        }
+        let domain = Url::parse(&config.seeds[0])?.host_str().unwrap_or("").to_string();
         
         let client = reqwest::blocking::Client::builder()
-            .timeout(std::time::Duration::from_secs(30))
+            .timeout(std::time::Duration::from_secs(config.http.timeout_secs))
+            .build()?;
+        let page_client = reqwest::blocking::Client::builder()
+            .timeout(std::time::Duration::from_secs(config.http.timeout_secs))
+            .redirect(reqwest::redirect::Policy::none())
             .build()?;
         
         Ok(Crawler {
             visited: HashSet::new(),
-            pages: Vec::new(),
+            page_count: 0,
+            skipped: Vec::new(),
             domain,
+            allowed_hosts,
+            include: config::compile_patterns("include", &config.include)?,
+            exclude: config::compile_patterns("exclude", &config.exclude)?,
+            selectors: Selectors::from_config(&config.selectors)?,
+            chunker: Chunker::from_config(&config.chunking)?,
+            language: LanguageIdentifier::from_config(&config.language)?,
+            content_types: ContentTypes::from_config(&config.content_types),
+            robots: config.robots.obey.then(|| RobotsCache::new(&config.robots.user_agent, std::time::Duration::from_secs(config.robots.retry_unreachable_secs))),
+            politeness: HostScheduler::new(
+                std::time::Duration::from_millis(config.politeness.min_delay_ms),
+                config.politeness.max_connections_per_host,
+            ),
+            sitemap_entries: HashMap::new(),
+            checkpoint: None,
+            outputs: Mutex::new(Vec::new()),
+            warc: None,
+            config,
             client,
+            page_client,
         })
     }
+
+    fn is_allowed_host(&self, host: Option<&str>) -> bool {
+        host.is_some_and(|h| self.allowed_hosts.contains(h))
+    }
     
-    fn crawl(&mut self, url: &str, depth: usize, max_depth: usize) {
-        if depth >= max_depth || self.visited.contains(url) {
-            return;
-        }
+    /// Crawls outward from `seeds` (each paired with its depth), taking URLs
+    /// from a `Frontier` in the order set by `config.order`. Pages are fetched
+    /// by `config.concurrency` worker threads and handed to a separate pool of
+    /// parser threads, so slow responses never hold up extraction. Finished
+    /// pages go straight to the output sinks.
+    fn crawl(&mut self, seeds: Vec<(String, usize)>, max_depth: usize) {
+        let mut visited = std::mem::take(&mut self.visited);
+        let mut skipped = std::mem::take(&mut self.skipped);
+        let mut checkpoint = self.checkpoint.take().map(|checkpoint| checkpoint.into_inner().unwrap());
+        let mut outputs = std::mem::take(self.outputs.get_mut().unwrap());
+        let mut progress = CheckpointProgress::new(checkpoint.is_some());
+        let mut page_count = self.page_count;
+        let this = &*self;
         
-        self.visited.insert(url.to_string());
-        println!("Crawling: {} (depth: {})", url, depth);
+        let concurrency = this.config.concurrency;
+        let parse_workers = thread::available_parallelism().map_or(1, |n| n.get()).min(concurrency);
+        let max_pages = this.config.max_pages.unwrap_or(usize::MAX);
+        let checkpoint_interval = this.config.checkpoint.interval_pages;
+        let mut frontier = Frontier::new(this.config.order.build());
+        for (url, depth) in seeds {
+            if depth >= max_depth || visited.contains(&url) {
+                continue;
+            }
+            match this.check_robots(url) {
+                UrlFilter::Accept(url) => {
+                    frontier.push(url, depth);
+                }
+                UrlFilter::Disallowed { url, reason } => {
+                    if visited.insert(url.clone()) {
+                        let skip = SkippedUrl { url, reason, found_on: None };
+                        progress.record_skipped(&skip);
+                        skipped.push(skip);
+                    }
+                }
+                UrlFilter::Reject => {}
+            }
+        }
         
-        match self.scrape_page(url, depth) {
-            Ok(page_data) => {
-                if !page_data.content.full_text.trim().is_empty() || 
-                   !page_data.content.paragraphs.is_empty() || 
-                   !page_data.content.headings.is_empty() {
-                    let links = page_data.links.clone();
-                    self.pages.push(page_data);
-                    
-                    for link in links.iter() {
-                        if matches!(link.link_type, LinkType::Internal) {
-                            if let Some(filtered_url) = self.filter_url(url, &link.href) {
-                                if !self.visited.contains(&filtered_url) {
-                                    self.crawl(&filtered_url, depth + 1, max_depth);
+        // URLs currently being fetched or parsed, with their depths.
+        let mut in_flight: HashMap<String, usize> = HashMap::new();
+        // URLs waiting for their host's robots.txt, with when to try again, and how often each has been tried.
+        let mut deferred: Vec<(Instant, String, usize)> = Vec::new();
+        let mut robots_attempts: HashMap<String, usize> = HashMap::new();
+        let robots_retry = Duration::from_secs(this.config.robots.retry_unreachable_secs);
+        thread::scope(|scope| {
+            let (fetch_tx, fetch_rx) = crossbeam_channel::bounded::<(String, usize)>(concurrency);
+            let (parse_tx, parse_rx) = crossbeam_channel::unbounded::<FetchedPage>();
+            let (result_tx, result_rx) = crossbeam_channel::unbounded::<CrawledPage>();
+            
+            for _ in 0..concurrency {
+                let fetch_rx = fetch_rx.clone();
+                let parse_tx = parse_tx.clone();
+                let result_tx = result_tx.clone();
+                scope.spawn(move || {
+                    for (url, depth) in fetch_rx {
+                        if let Some(page) = this.refused_by_robots(&url) {
+                            if result_tx.send(CrawledPage { url, depth, page }).is_err() {
+                                break;
+                            }
+                            continue;
+                        }
+                        let response = this.fetch_page(&url, depth).map_err(|e| e.to_string());
+                        if parse_tx.send(FetchedPage::from_response(url, depth, response)).is_err() {
+                            break;
+                        }
+                    }
+                });
+            }
+            for _ in 0..parse_workers {
+                let parse_rx = parse_rx.clone();
+                let result_tx = result_tx.clone();
+                scope.spawn(move || {
+                    for fetched in parse_rx {
+                        let page = this.extract(&fetched);
+                        if result_tx.send(CrawledPage { url: fetched.url, depth: fetched.depth, page }).is_err() {
+                            break;
+                        }
+                    }
+                });
+            }
+            // Only the workers hold these now, so they shut down in turn once `fetch_tx` is dropped.
+            drop((fetch_rx, parse_tx, parse_rx, result_tx));
+            
+            loop {
+                let now = Instant::now();
+                deferred.retain(|(retry_at, url, depth)| {
+                    let due = *retry_at <= now;
+                    if due {
+                        frontier.push(url.clone(), *depth);
+                    }
+                    !due
+                });
+                while in_flight.len() < concurrency && page_count + in_flight.len() < max_pages {
+                    let Some(entry) = frontier.pop(in_flight.values().min().copied()) else { break };
+                    if !visited.insert(entry.url.clone()) {
+                        continue;
+                    }
+                    println!("Crawling: {} (depth: {})", entry.url, entry.depth);
+                    in_flight.insert(entry.url.clone(), entry.depth);
+                    if fetch_tx.send((entry.url, entry.depth)).is_err() {
+                        break;
+                    }
+                }
+                if in_flight.is_empty() {
+                    match deferred.iter().map(|(retry_at, _, _)| *retry_at).min() {
+                        Some(retry_at) if page_count < max_pages => {
+                            thread::sleep(retry_at.saturating_duration_since(now));
+                            continue;
+                        }
+                        _ => break,
+                    }
+                }
+                let Ok(crawled) = result_rx.recv() else { break };
+                in_flight.remove(&crawled.url);
+                if let Extraction::Deferred = crawled.page {
+                    let attempts = robots_attempts.entry(crawled.url.clone()).or_default();
+                    *attempts += 1;
+                    if *attempts < ROBOTS_ATTEMPTS {
+                        println!("Deferring {}: robots.txt could not be fetched", crawled.url);
+                        visited.remove(&crawled.url);
+                        deferred.push((Instant::now() + robots_retry, crawled.url, crawled.depth));
+                        continue;
+                    }
+                }
+                progress.record_done(&crawled.url);
+                
+                let finished = match crawled.page {
+                    Extraction::Page(page_data) => {
+                        if has_content(&page_data) {
+                            if crawled.depth + 1 < max_depth && !this.config.sitemaps.only {
+                                for link in page_data.links.iter().filter(|link| matches!(link.link_type, LinkType::Internal)) {
+                                    match this.filter_url(&crawled.url, &link.href) {
+                                        UrlFilter::Accept(filtered_url) => {
+                                            if !visited.contains(&filtered_url) {
+                                                frontier.push(filtered_url, crawled.depth + 1);
+                                            }
+                                        }
+                                        UrlFilter::Disallowed { url, reason } => {
+                                            if visited.insert(url.clone()) {
+                                                println!("Skipping {}: {}", url, reason);
+                                                let skip = SkippedUrl { url, reason, found_on: Some(crawled.url.clone()) };
+                                                progress.record_skipped(&skip);
+                                                skipped.push(skip);
+                                            }
+                                        }
+                                        UrlFilter::Reject => {}
+                                    }
                                 }
                             }
+                            Some(*page_data)
+                        } else {
+                            println!("Skipping page due to no meaningful content after cleaning: {}", crawled.url);
+                            None
                         }
                     }
-                } else {
-                    println!("Skipping page due to no meaningful content after cleaning: {}", url);
+                    Extraction::Skipped(reason) => {
+                        println!("Skipping {}: {}", crawled.url, reason);
+                        let skip = SkippedUrl { url: crawled.url.clone(), reason, found_on: None };
+                        progress.record_skipped(&skip);
+                        skipped.push(skip);
+                        None
+                    }
+                    Extraction::Failed(e) => {
+                        eprintln!("Error scraping {}: {}", crawled.url, e);
+                        Some(failed_page(&crawled.url, crawled.depth, &e))
+                    }
+                    Extraction::Deferred => {
+                        let reason = format!("robots.txt could not be fetched after {} attempts", ROBOTS_ATTEMPTS);
+                        println!("Skipping {}: {}", crawled.url, reason);
+                        let skip = SkippedUrl { url: crawled.url.clone(), reason, found_on: None };
+                        progress.record_skipped(&skip);
+                        skipped.push(skip);
+                        None
+                    }
+                };
+                if let Some(page_data) = finished {
+                    page_count += 1;
+                    progress.record_page(&page_data);
+                    write_page(&mut outputs, &page_data);
+                }
+                
+                if let Some(checkpoint) = checkpoint.as_mut() {
+                    if progress.unsaved() >= checkpoint_interval {
+                        progress.flush(checkpoint, &pending_urls(&frontier, &in_flight, &deferred));
+                    }
                 }
             }
-            Err(e) => {
-                eprintln!("Error scraping {}: {}", url, e);
-                self.pages.push(PageData {
-                    url: url.to_string(),
-                    title: "Failed to crawl".to_string(),
-                    content: PageContent {
-                        full_text: String::new(), headings: vec![], paragraphs: vec![], lists: vec![], chunks: vec![],
-                    },
-                    metadata: PageMetadata {
-                        crawl_timestamp: Utc::now(), depth, word_count: 0, language: None, description: Some(format!("Error: {}", e)),
-                    },
-                    links: vec![],
-                });
+            drop(fetch_tx);
+        });
+        
+        if !frontier.is_empty() {
+            println!("Page limit reached with {} URLs still queued", frontier.len());
+        }
+        if let Some(checkpoint) = checkpoint.as_mut() {
+            progress.flush(checkpoint, &pending_urls(&frontier, &in_flight, &deferred));
+            if let Err(e) = checkpoint.mark_finished() {
+                eprintln!("Error writing checkpoint {}: {}", checkpoint.path().display(), e);
+            }
+        }
+        skipped.sort_by(|a, b| a.url.cmp(&b.url));
+        self.visited = visited;
+        self.page_count = page_count;
+        self.skipped = skipped;
+        self.checkpoint = checkpoint.map(Mutex::new);
+        *self.outputs.get_mut().unwrap() = outputs;
+    }
+    
+    /// Fetches a page, following up to `MAX_REDIRECTS` redirects. When WARC
+    /// output is on, each hop's exchange is archived under the URL requested
+    /// for it. With `content_types.head_first`, a HEAD response naming a type
+    /// that is not extracted is returned instead, so the body is never downloaded.
+    fn fetch_page(&self, url: &str, depth: usize) -> Result<HttpResponse, Box<dyn std::error::Error>> {
+        if self.config.content_types.head_first {
+            let head = self.fetch_with(&self.client, Method::HEAD, url, HttpResponse::read)?;
+            let unwanted = head.header("content-type").is_some_and(|content_type| {
+                self.content_types.handler(&content_type::mime_type(Some(content_type), &[])).is_none()
+            });
+            if StatusCode::from_u16(head.status).is_ok_and(|status| status.is_success()) && unwanted {
+                return Ok(head);
+            }
+        }
+        let mut target = Url::parse(url)?;
+        for _ in 0..=MAX_REDIRECTS {
+            let response = self.fetch_with(&self.page_client, Method::GET, target.as_str(), HttpResponse::read)?;
+            if let Some(warc) = &self.warc {
+                if let Err(e) = warc.lock().unwrap().write_exchange(&target, &self.request_headers(), &response, depth) {
+                    eprintln!("Error archiving {}: {}", target, e);
+                }
+            }
+            let redirected = StatusCode::from_u16(response.status).is_ok_and(|status| status.is_redirection());
+            match response.header("location").filter(|_| redirected) {
+                Some(location) => target = target.join(location)?,
+                None => return Ok(response),
             }
         }
+        Err(format!("more than {} redirects", MAX_REDIRECTS).into())
     }
     
-    fn scrape_page(&self, url: &str, depth: usize) -> Result<PageData, Box<dyn std::error::Error>> {
-        let response = self.client
-            .get(url)
-            .header(USER_AGENT, "Mozilla/5.0 (compatible; RustCrawler/1.0; +http://yourdomain.com/bot.html)")
-            .send()?;
+    /// Headers sent with every request.
+    fn request_headers(&self) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        if let Ok(user_agent) = HeaderValue::from_str(&self.config.http.user_agent) {
+            headers.insert(USER_AGENT, user_agent);
+        }
+        headers
+    }
+    
+    /// Requests `url`, waiting for its host's politeness slot first, and hands
+    /// the response to `read` while the slot is still held. 429 and 503
+    /// responses push the whole host back (by `Retry-After` when given,
+    /// otherwise exponentially) and are retried up to `politeness.max_retries` times.
+    fn fetch_with<T>(
+        &self,
+        client: &reqwest::blocking::Client,
+        method: Method,
+        url: &str,
+        read: impl Fn(reqwest::blocking::Response) -> reqwest::Result<T>,
+    ) -> Result<T, Box<dyn std::error::Error>> {
+        let parsed = Url::parse(url)?;
+        let host = parsed.host_str().unwrap_or("").to_string();
+        let crawl_delay = self.robots
+            .as_ref()
+            .and_then(|robots| robots.get(&self.client, &self.config.http.user_agent, &self.politeness, &parsed).crawl_delay());
+        let max_wait = std::time::Duration::from_secs(self.config.politeness.max_retry_after_secs);
+        
+        let mut attempt = 0;
+        loop {
+            let _permit = self.politeness.acquire(&host, crawl_delay);
+            let response = client
+                .request(method.clone(), url)
+                .headers(self.request_headers())
+                .send()?;
+            
+            let status = response.status();
+            if status != StatusCode::TOO_MANY_REQUESTS && status != StatusCode::SERVICE_UNAVAILABLE {
+                return Ok(read(response)?);
+            }
+            if attempt >= self.config.politeness.max_retries {
+                return Err(format!("HTTP {} after {} retries", status, attempt).into());
+            }
+            
+            let delay = politeness::retry_after(response.headers())
+                .unwrap_or_else(|| std::time::Duration::from_secs(1 << attempt.min(10)))
+                .min(max_wait);
+            eprintln!("{} returned {}, retrying in {:.1}s", url, status, delay.as_secs_f64());
+            self.politeness.back_off(&host, delay);
+            attempt += 1;
+        }
+    }
+    
+    /// Reads the sitemaps listed in `sitemaps.urls` and, when discovery is on,
+    /// those named by each seed host's robots.txt (falling back to
+    /// `/sitemap.xml`). Sitemap indexes are followed. Returns the in-scope page
+    /// URLs as depth-0 seeds and remembers their `lastmod`/`priority`.
+    fn discover_sitemaps(&mut self) -> Vec<(String, usize)> {
+        let mut pending: VecDeque<String> = self.config.sitemaps.urls.iter().cloned().collect();
+        if self.config.sitemaps.discover || self.config.sitemaps.only {
+            let mut origins = HashSet::new();
+            for seed in &self.config.seeds {
+                let Ok(url) = Url::parse(seed) else { continue };
+                if !origins.insert(url.origin().ascii_serialization()) {
+                    continue;
+                }
+                let listed: Vec<String> = match &self.robots {
+                    Some(robots) => robots.get(&self.client, &self.config.http.user_agent, &self.politeness, &url).sitemaps().to_vec(),
+                    None => vec![],
+                };
+                if listed.is_empty() {
+                    pending.push_back(format!("{}/sitemap.xml", url.origin().ascii_serialization()));
+                } else {
+                    pending.extend(listed);
+                }
+            }
+        }
+        
+        let max_urls = self.config.sitemaps.max_urls.unwrap_or(usize::MAX);
+        let mut read = HashSet::new();
+        let mut seeds = Vec::new();
+        while let Some(sitemap_url) = pending.pop_front() {
+            if seeds.len() >= max_urls {
+                break;
+            }
+            if !read.insert(sitemap_url.clone()) {
+                continue;
+            }
+            println!("Reading sitemap: {}", sitemap_url);
+            
+            let sitemap = self
+                .fetch_with(&self.client, Method::GET, &sitemap_url, |response| response.error_for_status()?.bytes())
+                .and_then(|body| sitemap::parse(&body));
+            match sitemap {
+                Ok(Sitemap::Index(locations)) => pending.extend(locations),
+                Ok(Sitemap::UrlSet(entries)) => {
+                    for entry in entries {
+                        if seeds.len() >= max_urls {
+                            break;
+                        }
+                        if let Some(url) = self.normalize_url(&sitemap_url, &entry.loc) {
+                            if let Entry::Vacant(slot) = self.sitemap_entries.entry(url.clone()) {
+                                slot.insert(entry);
+                                seeds.push((url, 0));
+                            }
+                        }
+                    }
+                }
+                Err(e) => eprintln!("Error reading sitemap {}: {}", sitemap_url, e),
+            }
+        }
         
-        let body = response.text()?;
-        let document = Html::parse_document(&body);
-        let selectors = Selectors::new();
+        println!("Found {} URLs in {} sitemaps", seeds.len(), read.len());
+        if let Some(checkpoint) = self.checkpoint.as_mut().map(|checkpoint| checkpoint.get_mut().unwrap()) {
+            if let Err(e) = checkpoint.save_sitemap_entries(self.sitemap_entries.iter()) {
+                eprintln!("Error writing checkpoint {}: {}", checkpoint.path().display(), e);
+            }
+        }
+        seeds
+    }
+    
+    /// Extracts a page with the handler for its MIME type, decoding it first.
+    fn extract(&self, fetched: &FetchedPage) -> Extraction {
+        let body = match &fetched.body {
+            Ok(body) => body,
+            Err(e) => return Extraction::Failed(e.clone()),
+        };
+        let mime_type = content_type::mime_type(body.content_type.as_deref(), &body.bytes);
+        let Some(handler) = self.content_types.handler(&mime_type) else {
+            return Extraction::Skipped(format!("content type {} is not extracted", mime_type));
+        };
+        let (text, encoding) = encoding::decode(&body.bytes, body.content_type.as_deref(), Url::parse(&fetched.url).ok().as_ref());
+        let info = BodyInfo { body, mime_type, encoding };
+        let page = match handler {
+            Handler::Html => self.scrape_page(&fetched.url, fetched.depth, &text, &info),
+            Handler::Text => self.scrape_page(&fetched.url, fetched.depth, &content_type::text_as_html(&text), &info).map(|mut page| {
+                page.metadata.content_selector = None;
+                page
+            }),
+        };
+        match page {
+            Ok(page) => Extraction::Page(Box::new(page)),
+            Err(e) => Extraction::Failed(e.to_string()),
+        }
+    }
+    
+    fn scrape_page(&self, url: &str, depth: usize, html: &str, info: &BodyInfo) -> Result<PageData, Box<dyn std::error::Error>> {
+        let document = Html::parse_document(html);
+        let selectors = &self.selectors;
         
         let title_selector = Selector::parse("title").unwrap();
         let title = document
@@ -216,38 +819,79 @@ impl Crawler {
             .and_then(|el| el.value().attr("content"))
             .map(|s| s.trim().to_string());
         
-        let main_content_element = self.find_main_content_element(&document, &selectors);
+        let MainContent { element: main_content_element, selector_path } = self.find_main_content_element(&document, selectors);
 
-        let headings = self.extract_headings(&main_content_element, &selectors);
-        let paragraphs = self.extract_paragraphs(&main_content_element, &selectors);
-        let lists = self.extract_lists(&main_content_element, &selectors);
+        let paragraphs = self.extract_paragraphs(&main_content_element, selectors);
+        let lists = self.extract_lists(&main_content_element, selectors);
+        let tables = tables::extract_tables(main_content_element, &|element| self.is_skippable(element, selectors));
         let links = self.extract_links(&document, url)?;
+        let structured_data = structured_data::extract(&document, &Url::parse(url)?);
         
-        let full_text = self.build_full_text(&main_content_element, &selectors);
+        let (full_text, text_headings) = self.build_full_text(&main_content_element, selectors);
+        let headings = self.extract_headings(&main_content_element, selectors, &text_headings, full_text.len());
+        let outline = build_outline(&headings);
+        let markdown = self.render_markdown(main_content_element, url, selectors)?;
         let word_count = full_text.split_whitespace().count();
         
-        let chunks = self.create_chunks(&full_text, &headings, url);
+        let declared_language = language::declared(&document, info.body.content_language.as_deref());
+        let language = self.language.page(&full_text, declared_language.as_deref());
+        let mut chunks = self.chunker.chunks(&full_text, &text_headings, url);
+        for chunk in &mut chunks {
+            chunk.language = self.language.chunk(&chunk.text, language.as_ref());
+        }
+        let sentences = self.chunker.sentences(&full_text);
+        let sitemap_entry = self.sitemap_entries.get(url);
         
         Ok(PageData {
             url: url.to_string(),
             title,
+            content_hash: Some(chunking::content_hash(&full_text)),
             content: PageContent {
-                full_text, headings, paragraphs, lists, chunks,
+                full_text, headings, outline, paragraphs, lists, tables, markdown, sentences, chunks,
             },
             metadata: PageMetadata {
-                crawl_timestamp: Utc::now(), depth, word_count, language: Some("en".to_string()), description,
+                crawl_timestamp: Utc::now(), depth, word_count, description,
+                language_confidence: language.as_ref().and_then(|language| language.confidence),
+                language: language.map(|language| language.code),
+                declared_language,
+                encoding: Some(info.encoding.encoding.name().to_string()),
+                encoding_source: Some(info.encoding.source),
+                mime_type: Some(info.mime_type.clone()),
+                byte_size: Some(info.body.bytes.len()),
+                lastmod: sitemap_entry.and_then(|entry| entry.lastmod.clone()),
+                sitemap_priority: sitemap_entry.and_then(|entry| entry.priority),
+                content_selector: Some(selector_path),
             },
+            structured_data,
             links,
         })
     }
 
-    fn find_main_content_element<'a>(&self, document: &'a Html, selectors: &Selectors) -> ElementRef<'a> {
-        for selector in &selectors.main_content {
-            if let Some(main_node) = document.select(selector).next() {
-                return main_node;
+    /// Scores the page for its main content, using the `main_content`
+    /// selectors as hints. Without scoring, or when nothing scores, the first
+    /// selector match is taken, falling back to the whole document.
+    fn find_main_content_element<'a>(&self, document: &'a Html, selectors: &Selectors) -> MainContent<'a> {
+        if let Some(scorer) = &selectors.content_scorer {
+            if let Some(main) = scorer.find(document, &selectors.main_content, &|element| self.is_skippable(element, selectors)) {
+                return main;
             }
         }
-        document.root_element()
+        let element = selectors.main_content
+            .iter()
+            .find_map(|selector| document.select(selector).next())
+            .unwrap_or_else(|| document.root_element());
+        MainContent { element, selector_path: readability::selector_path(element) }
+    }
+
+    /// Renders the main content as Markdown, leaving out what `build_full_text` does.
+    fn render_markdown(&self, main_content_element: ElementRef, url: &str, selectors: &Selectors) -> Result<String, Box<dyn std::error::Error>> {
+        let base = Url::parse(url)?;
+        let renderer = MarkdownRenderer {
+            base: &base,
+            skippable: &|element| self.is_skippable(element, selectors),
+            is_noise: &|text| selectors.is_noise_text(text),
+        };
+        Ok(renderer.render(main_content_element))
     }
 
     fn is_skippable(&self, element: ElementRef, selectors: &Selectors) -> bool {
@@ -266,13 +910,21 @@ impl Crawler {
         false
     }
     
-    fn build_full_text<'a>(&self, main_content_element: &ElementRef<'a>, selectors: &Selectors) -> String {
+    /// Collects the text under the main content element, collapsing
+    /// whitespace. With `chunking.tables_as_markdown` each table appears as a
+    /// Markdown table on lines of its own instead of as a run of cell text.
+    /// Also returns where each heading's text starts in it, for chunking.
+    fn build_full_text<'a>(&self, main_content_element: &ElementRef<'a>, selectors: &Selectors) -> (String, Vec<TextHeading>) {
         let mut text_parts: Vec<String> = Vec::new();
+        // Headings with the index of the first part after them.
+        let mut heading_parts: Vec<(usize, TextHeading)> = Vec::new();
     
         fn extract_text_recursively(
             element: ElementRef,
             text_parts: &mut Vec<String>,
+            heading_parts: &mut Vec<(usize, TextHeading)>,
             selectors: &Selectors,
+            tables_as_markdown: bool,
             depth: usize,
         ) {
             if depth > 50 || selectors.always_remove.matches(&element) {
@@ -286,22 +938,41 @@ impl Crawler {
                     }
                 }
             }
+            
+            if tables_as_markdown && element.value().name() == "table" {
+                // Subtrees are checked top-down here, so an element need only be checked by itself.
+                let skippable = |element: ElementRef| {
+                    selectors.always_remove.matches(&element) || selectors.boilerplate.iter().any(|bp| bp.matches(&element))
+                };
+                if let Some(table) = Table::from_element(element, None, &skippable) {
+                    text_parts.push(format!("\n\n{}\n", table.to_markdown().trim_end()));
+                }
+                // Tables in its cells are left out of its Markdown and follow it as tables of their own.
+                for nested in tables::nested_tables(element, &skippable) {
+                    extract_text_recursively(nested, text_parts, heading_parts, selectors, tables_as_markdown, depth + 1);
+                }
+                return;
+            }
+
+            if let Some(level) = heading_level(element) {
+                let text = element.text().flat_map(str::split_whitespace).collect::<Vec<_>>().join(" ");
+                if !text.is_empty() {
+                    let navigation = is_navigation_heading(element, &text);
+                    heading_parts.push((text_parts.len(), TextHeading { node: element.id(), offset: 0, level, text, navigation }));
+                }
+            }
     
             for node in element.children() {
                 match node.value() {
                     Node::Text(text_node) => {
                         let original_text_trimmed = text_node.trim();
-                        let processed_text_lower = original_text_trimmed.to_lowercase();
-                        if !original_text_trimmed.is_empty() && 
-                           !selectors.cookie_banner_text.iter().any(|p| processed_text_lower.contains(p)) &&
-                           !selectors.json_like_pattern.is_match(original_text_trimmed) && 
-                           !processed_text_lower.contains("permissionshash") {
-                            text_parts.push(original_text_trimmed.to_string());
+                        if !original_text_trimmed.is_empty() && !selectors.is_noise_text(original_text_trimmed) {
+                            text_parts.push(original_text_trimmed.split_whitespace().collect::<Vec<_>>().join(" "));
                         }
                     }
                     Node::Element(_) => {
                         if let Some(sub_element_ref) = ElementRef::wrap(node) {
-                           extract_text_recursively(sub_element_ref, text_parts, selectors, depth + 1);
+                           extract_text_recursively(sub_element_ref, text_parts, heading_parts, selectors, tables_as_markdown, depth + 1);
                         }
                     }
                     _ => {}
@@ -309,79 +980,114 @@ impl Crawler {
             }
         }
     
-        extract_text_recursively(*main_content_element, &mut text_parts, selectors, 0);
+        let tables_as_markdown = self.config.chunking.tables_as_markdown;
+        extract_text_recursively(*main_content_element, &mut text_parts, &mut heading_parts, selectors, tables_as_markdown, 0);
     
-        text_parts.join(" ")
-            .split_whitespace()
-            .filter(|s| !s.is_empty())
-            .collect::<Vec<_>>()
-            .join(" ")
+        // Parts are already whitespace-collapsed; a table's newlines are kept.
+        let mut full_text = String::new();
+        let mut part_offsets = Vec::with_capacity(text_parts.len());
+        for part in text_parts {
+            if !full_text.is_empty() && !part.starts_with('\n') && !full_text.ends_with('\n') {
+                full_text.push(' ');
+            }
+            part_offsets.push(full_text.len());
+            full_text.push_str(&part);
+        }
+        let leading = full_text.len() - full_text.trim_start().len();
+        let text_len = full_text.trim().len();
+        let headings = heading_parts
+            .into_iter()
+            .map(|(part, mut heading)| {
+                let offset = part_offsets.get(part).copied().unwrap_or(full_text.len());
+                heading.offset = offset.saturating_sub(leading).min(text_len);
+                heading
+            })
+            .collect();
+        (full_text.trim().to_string(), headings)
     }
 
 
-    fn extract_headings<'a>(&self, main_content_element: &ElementRef<'a>, selectors: &Selectors) -> Vec<Heading> {
-        let mut headings_data = Vec::new();
-        let mut last_h1: Option<String> = None;
-        let mut last_h2: Option<String> = None;
-        let common_boilerplate_headings = ["navigation", "menu", "footer", "cookies", "search results", "search"];
+    /// Walks the main content in document order, parenting each heading to
+    /// the nearest heading above it of a higher level. `text_headings` (from
+    /// `build_full_text`) gives where each section starts in `full_text`; a
+    /// section runs to the next of those headings of the same or a higher
+    /// level. Boilerplate and link-only headings are left out afterwards, so
+    /// they still end the sections before them, and their children move up
+    /// to the nearest heading that is kept.
+    fn extract_headings<'a>(
+        &self,
+        main_content_element: &ElementRef<'a>,
+        selectors: &Selectors,
+        text_headings: &[TextHeading],
+        text_len: usize,
+    ) -> Vec<Heading> {
+        let positions: HashMap<NodeId, usize> = text_headings.iter().enumerate().map(|(i, heading)| (heading.node, i)).collect();
 
-        for level in 1..=6 {
-            let selector_str = format!("h{}", level);
-            
-            // Perform parsing and handle error case by skipping iteration
-            let heading_selector = match Selector::parse(&selector_str) {
-                Ok(sel) => sel, // sel is Selector, which is 'static
-                Err(e) => {
-                    // selector_str is still alive here.
-                    // e (SelectorErrorKind) borrows selector_str.
-                    // We convert e to a string for potential logging, then e is dropped.
-                    // The 'continue' ensures this whole path related to 'e' terminates.
-                    let _error_string = e.to_string(); 
-                    // eprintln!("Failed to parse heading selector for 'h{}': {}", level, _error_string);
-                    continue; // Skip this iteration if parsing fails
-                }
-            };
-            // If we reach here, selector_str is still alive, and heading_selector is a valid 'static Selector.
-            // The temporary Result from Selector::parse and any SelectorErrorKind are gone.
+        // Every heading with its parent among them, and whether it is kept.
+        let mut all: Vec<(Heading, bool)> = Vec::new();
+        // Indices of the headings enclosing the current position, outermost first.
+        let mut open: Vec<usize> = Vec::new();
+        for element in main_content_element.descendants().filter_map(ElementRef::wrap) {
+            let Some(level) = heading_level(element) else { continue };
+            if self.is_skippable(element, selectors) {
+                continue;
+            }
 
-            for element in main_content_element.select(&heading_selector) {
-                if self.is_skippable(element, selectors) {
-                    continue;
-                }
+            let text = element.text().flat_map(str::split_whitespace).collect::<Vec<_>>().join(" ");
+            if text.is_empty() {
+                continue;
+            }
+            let keep = !is_navigation_heading(element, &text);
 
-                let text = element.text().collect::<String>().trim().to_string();
-                let lower_text = text.to_lowercase();
+            let position = positions.get(&element.id()).copied();
+            let section_end = position.map(|i| {
+                text_headings[i + 1..]
+                    .iter()
+                    .find(|heading| heading.level <= level)
+                    .map_or(text_len, |heading| heading.offset)
+            });
 
-                if text.is_empty() || common_boilerplate_headings.iter().any(|&s| lower_text.contains(s)) {
-                    continue;
-                }
-                
-                // Assuming Selector::parse("a") is infallible or handled appropriately
-                let link_selector = Selector::parse("a").unwrap(); 
-                let link_text_len: usize = element.select(&link_selector).flat_map(|a| a.text()).map(|t| t.len()).sum();
-                if !text.is_empty() && link_text_len > text.len() / 2 && text.split_whitespace().count() < 5 {
-                    continue;
-                }
+            while open.last().is_some_and(|&i| all[i].0.level >= level) {
+                open.pop();
+            }
+            let parent = open.last().copied();
+            open.push(all.len());
+            let heading = Heading {
+                id: all.len(),
+                level,
+                text,
+                parent_heading: None,
+                parent_id: parent,
+                anchor: heading_anchor(element),
+                section_start: position.map(|i| text_headings[i].offset),
+                section_end,
+            };
+            all.push((heading, keep));
+        }
 
-                let parent_heading = match level {
-                    2 => last_h1.clone(),
-                    3..=6 => last_h2.clone(),
-                    _ => None,
-                };
-                
-                headings_data.push(Heading {
-                    level: level as u8,
-                    text: text.clone(),
-                    parent_heading,
-                });
-                
-                match level {
-                    1 => last_h1 = Some(text.clone()),
-                    2 => last_h2 = Some(text.clone()), 
-                    _ => {}
-                }
+        // Renumber the kept headings and point each at its nearest kept ancestor.
+        let mut ids: Vec<Option<usize>> = Vec::with_capacity(all.len());
+        let mut kept = 0;
+        for (_, keep) in &all {
+            ids.push(keep.then_some(kept));
+            kept += usize::from(*keep);
+        }
+        let mut headings_data: Vec<Heading> = Vec::with_capacity(kept);
+        for (i, (heading, keep)) in all.iter().enumerate() {
+            if !keep {
+                continue;
+            }
+            let mut parent = heading.parent_id;
+            while let Some(p) = parent.filter(|&p| !all[p].1) {
+                parent = all[p].0.parent_id;
             }
-            // selector_str is dropped at the end of the loop iteration here.
+            let parent_id = parent.and_then(|p| ids[p]);
+            headings_data.push(Heading {
+                id: ids[i].unwrap_or_default(),
+                parent_heading: parent_id.map(|p| headings_data[p].text.clone()),
+                parent_id,
+                ..heading.clone()
+            });
         }
         headings_data
     }
@@ -460,7 +1166,7 @@ impl Crawler {
                 } else {
                     match base.join(href_attr) {
                         Ok(full_url) => {
-                            if full_url.host_str() == Some(&self.domain) {
+                            if self.is_allowed_host(full_url.host_str()) {
                                 LinkType::Internal
                             } else {
                                 LinkType::External
@@ -479,106 +1185,40 @@ impl Crawler {
             .collect())
     }
     
-    fn create_chunks(&self, full_text: &str, _headings: &[Heading], url: &str) -> Vec<TextChunk> {
-        const CHUNK_SIZE: usize = 1000; 
-        const OVERLAP: usize = 200;    
-
-        let mut chunks = Vec::new();
-        let mut current_byte_start = 0; 
-        let text_len_bytes = full_text.len();
-        let mut chunk_index = 0;
-
-        if full_text.is_empty() {
-            return chunks;
+    fn filter_url(&self, base_url_str: &str, href: &str) -> UrlFilter {
+        match self.normalize_url(base_url_str, href) {
+            Some(url) => self.check_robots(url),
+            None => UrlFilter::Reject,
         }
+    }
 
-        loop {
-            while current_byte_start < text_len_bytes && !full_text.is_char_boundary(current_byte_start) {
-                current_byte_start += 1;
-            }
-
-            if current_byte_start >= text_len_bytes {
-                break;
-            }
-
-            let mut target_end_byte = (current_byte_start + CHUNK_SIZE).min(text_len_bytes);
-
-            while target_end_byte < text_len_bytes && !full_text.is_char_boundary(target_end_byte) {
-                target_end_byte += 1;
-            }
-            
-            if target_end_byte <= current_byte_start && current_byte_start < text_len_bytes {
-                if let Some((_idx, ch)) = full_text[current_byte_start..].char_indices().next() {
-                    target_end_byte = current_byte_start + ch.len_utf8();
-                } else { 
-                    break; 
-                }
-            }
-            target_end_byte = target_end_byte.min(text_len_bytes);
-            
-            if target_end_byte <= current_byte_start {
-                break; 
-            }
-
-            let mut chunk_to_slice_end_byte = target_end_byte;
-
-            if target_end_byte < text_len_bytes { 
-                let mut sentence_search_limit = (target_end_byte + 100).min(text_len_bytes);
-                while sentence_search_limit < text_len_bytes && !full_text.is_char_boundary(sentence_search_limit) {
-                    sentence_search_limit += 1;
-                }
-                
-                if sentence_search_limit > current_byte_start { 
-                    let search_slice = &full_text[current_byte_start..sentence_search_limit];
-                    if let Some(pos) = search_slice.rfind(". ") {
-                        let sentence_end_abs_byte = current_byte_start + pos + 2; 
-                        if sentence_end_abs_byte > current_byte_start && sentence_end_abs_byte <= sentence_search_limit {
-                            if full_text.is_char_boundary(sentence_end_abs_byte) {
-                                 chunk_to_slice_end_byte = sentence_end_abs_byte;
-                            }
-                        }
-                    }
-                }
-            }
-            
-            if chunk_to_slice_end_byte <= current_byte_start {
-                chunk_to_slice_end_byte = target_end_byte; 
-                 if chunk_to_slice_end_byte <= current_byte_start { 
-                     break; 
-                 }
-            }
-
-            let chunk_text_slice = &full_text[current_byte_start..chunk_to_slice_end_byte];
-            let trimmed_chunk_text = chunk_text_slice.trim();
-
-            if !trimmed_chunk_text.is_empty() {
-                chunks.push(TextChunk {
-                    chunk_id: format!("{}#chunk{}", url, chunk_index),
-                    text: trimmed_chunk_text.to_string(),
-                    char_start: current_byte_start, 
-                    char_end: chunk_to_slice_end_byte,
-                    section_heading: None, 
-                });
-                chunk_index += 1;
-            }
-
-            let next_start_byte_candidate = chunk_to_slice_end_byte.saturating_sub(OVERLAP); // Removed mut
+    /// Checks `url` against the robots.txt already loaded for its host. The
+    /// crawl loop calls this for every link, so it never fetches anything.
+    fn check_robots(&self, url: String) -> UrlFilter {
+        let Some(robots) = &self.robots else { return UrlFilter::Accept(url) };
+        let Ok(parsed) = Url::parse(&url) else { return UrlFilter::Reject };
+        
+        match robots.cached(&parsed).filter(|robots| !robots.is_unreachable()).and_then(|robots| robots.disallow_reason(&parsed)) {
+            Some(reason) => UrlFilter::Disallowed { url, reason },
+            None => UrlFilter::Accept(url),
+        }
+    }
 
-            if next_start_byte_candidate <= current_byte_start && chunk_to_slice_end_byte > current_byte_start {
-                current_byte_start = chunk_to_slice_end_byte;
-            } else if next_start_byte_candidate > current_byte_start {
-                current_byte_start = next_start_byte_candidate;
-            } else { 
-                break;
-            }
+    /// Checks `url` against its host's robots.txt on a fetch worker, loading
+    /// it first if needed. `None` means the URL may be fetched.
+    fn refused_by_robots(&self, url: &str) -> Option<Extraction> {
+        let robots = self.robots.as_ref()?;
+        let parsed = Url::parse(url).ok()?;
+        let robots = robots.get(&self.client, &self.config.http.user_agent, &self.politeness, &parsed);
+        if robots.is_unreachable() {
+            return Some(Extraction::Deferred);
         }
-        chunks
+        robots.disallow_reason(&parsed).map(Extraction::Skipped)
     }
     
-    fn filter_url(&self, base_url_str: &str, href: &str) -> Option<String> {
+    fn normalize_url(&self, base_url_str: &str, href: &str) -> Option<String> {
         let lower_href = href.to_lowercase();
-        let banned_extensions = [".pdf", ".jpg", ".jpeg", ".png", ".gif", ".zip", ".doc", ".docx", ".xls", ".xlsx", ".ppt", ".pptx", ".mp3", ".mp4", ".avi", ".mov", ".xml", ".css", ".js", ".svg", ".webp", ".woff", ".woff2", ".ttf", ".eot", ".ics"];
-        if banned_extensions.iter().any(|ext| lower_href.ends_with(ext) || lower_href.contains(&format!("{}?", ext)) ) {
+        if self.config.banned_extensions.iter().any(|ext| lower_href.ends_with(ext) || lower_href.contains(&format!("{}?", ext)) ) {
             return None;
         }
 
@@ -599,7 +1239,7 @@ impl Crawler {
 
         match base_url.join(href) {
             Ok(mut full_url) => {
-                if full_url.host_str() == Some(&self.domain) {
+                if self.is_allowed_host(full_url.host_str()) {
                     full_url.set_fragment(None);
                     let query_pairs: Vec<(String, String)> = full_url.query_pairs()
                         .filter(|(key, _)| !key.starts_with("utm_") && key != "fbclid" && key != "gclid")
@@ -614,7 +1254,14 @@ impl Crawler {
                             .join("&");
                         full_url.set_query(Some(&new_query));
                     }
-                    Some(full_url.to_string())
+                    let normalized = full_url.to_string();
+                    if !self.include.is_empty() && !self.include.iter().any(|re| re.is_match(&normalized)) {
+                        return None;
+                    }
+                    if self.exclude.iter().any(|re| re.is_match(&normalized)) {
+                        return None;
+                    }
+                    Some(normalized)
                 } else {
                     None 
                 }
@@ -623,47 +1270,672 @@ impl Crawler {
         }
     }
     
-    fn save_results(&self, root_url: &str) -> Result<(), Box<dyn std::error::Error>> {
-        let output = CrawlOutput {
+    fn resume_from(&mut self, previous: CrawlOutput, max_depth: usize) {
+        // Pages that failed last time are retried; everything else is kept as-is.
+        let (failed, completed): (Vec<PageData>, Vec<PageData>) = previous.pages
+            .into_iter()
+            .partition(|page| page.title == "Failed to crawl" && page.content.full_text.is_empty());
+        
+        self.visited.extend(completed.iter().map(|page| page.url.clone()));
+        self.visited.extend(previous.skipped.iter().map(|skipped| skipped.url.clone()));
+        self.skipped = previous.skipped;
+        println!("Resuming with {} completed pages, retrying {} failed pages", completed.len(), failed.len());
+
+        let mut seeds: Vec<(String, usize)> = failed.iter().map(|page| (page.url.clone(), page.metadata.depth)).collect();
+        if self.config.sitemaps.enabled() {
+            seeds.extend(self.discover_sitemaps());
+        }
+        for page in completed.iter().filter(|_| !self.config.sitemaps.only) {
+            for link in page.links.iter().filter(|link| matches!(link.link_type, LinkType::Internal)) {
+                if let Some(filtered_url) = self.normalize_url(&page.url, &link.href) {
+                    if !self.visited.contains(&filtered_url) {
+                        seeds.push((filtered_url, page.metadata.depth + 1));
+                    }
+                }
+            }
+        }
+        self.adopt_pages(completed);
+        self.crawl(seeds, max_depth);
+    }
+    
+    /// Loads the progress stored in a checkpoint and returns the URLs that
+    /// were still queued or in flight, ready to be passed to `crawl`.
+    fn restore(&mut self, state: CheckpointState) -> Vec<(String, usize)> {
+        println!(
+            "Resuming with {} completed pages, {} skipped URLs and {} URLs queued",
+            state.pages.len(), state.skipped.len(), state.frontier.len()
+        );
+        self.visited = state.visited;
+        self.adopt_pages(state.pages);
+        self.skipped = state.skipped;
+        self.sitemap_entries = state.sitemap_entries.into_iter().collect();
+        state.frontier
+    }
+    
+    /// Takes over pages finished by an earlier run, passing them to the output sinks.
+    fn adopt_pages(&mut self, pages: Vec<PageData>) {
+        self.page_count += pages.len();
+        let outputs = self.outputs.get_mut().unwrap();
+        for page in &pages {
+            write_page(outputs, page);
+        }
+    }
+    
+    /// Runs extraction over pages read from `sources` instead of fetching
+    /// them. Only the first copy of each URL is used, and a source that cannot
+    /// be read to the end (such as a WARC cut short by a killed crawl)
+    /// contributes the pages before the damage.
+    fn reprocess(&mut self, sources: Vec<Box<dyn PageSource>>) {
+        for mut source in sources {
+            println!("Reading {}", source.describe());
+            if let Err(e) = source.read(&mut |page| self.extract_offline(page)) {
+                eprintln!("Error reading {}: {}", source.describe(), e);
+            }
+        }
+    }
+    
+    fn extract_offline(&mut self, fetched: FetchedPage) {
+        if !self.visited.insert(fetched.url.clone()) {
+            return;
+        }
+        let page = match self.extract(&fetched) {
+            Extraction::Page(page) if has_content(&page) => *page,
+            Extraction::Page(_) => {
+                println!("Skipping page due to no meaningful content after cleaning: {}", fetched.url);
+                return;
+            }
+            Extraction::Skipped(reason) => {
+                println!("Skipping {}: {}", fetched.url, reason);
+                self.skipped.push(SkippedUrl { url: fetched.url, reason, found_on: None });
+                return;
+            }
+            Extraction::Failed(e) => {
+                eprintln!("Error scraping {}: {}", fetched.url, e);
+                failed_page(&fetched.url, fetched.depth, &e)
+            }
+            // Only fetching defers a page.
+            Extraction::Deferred => return,
+        };
+        write_page(self.outputs.get_mut().unwrap(), &page);
+        self.page_count += 1;
+    }
+    
+    /// File name (without extension) shared by everything this crawl writes.
+    fn output_stem(&self) -> String {
+        self.domain.replace(|c: char| !c.is_alphanumeric() && c != '-', "_")
+    }
+    
+    /// Opens a sink for each of `config.output.formats`, all sharing one file stem.
+    fn open_outputs(&mut self, root_url: &str) -> Result<(), Box<dyn std::error::Error>> {
+        let crawl = CrawlInfo {
             domain: self.domain.clone(),
             root_url: root_url.to_string(),
             crawl_timestamp: Utc::now(),
-            total_pages: self.pages.len(),
-            pages: self.pages.clone(),
         };
+        let stem = self.output_stem();
+        let mut outputs = Vec::new();
+        for format in &self.config.output.formats {
+            let path = self.config.output.dir.join(format!("{}.{}", stem, format.extension()));
+            let mut sink = format.sink(path);
+            sink.open(&crawl)?;
+            outputs.push(sink);
+        }
+        *self.outputs.get_mut().unwrap() = outputs;
         
-        let output_dir = Path::new("crawled_data");
-        fs::create_dir_all(&output_dir)?;
-        
-        let sanitized_domain = self.domain.replace(|c: char| !c.is_alphanumeric() && c != '-', "_");
-        let filename = format!("{}/{}.json", output_dir.display(), sanitized_domain);
-        let json = serde_json::to_string_pretty(&output)?;
-        fs::write(&filename, json)?;
-        
-        println!("Saved {} pages to {}", self.pages.len(), filename);
+        if self.config.warc.enabled {
+            let dir = self.config.warc.dir.clone().unwrap_or_else(|| self.config.output.dir.clone());
+            let prefix = format!("{}-{}", stem, crawl.crawl_timestamp.format("%Y%m%d%H%M%S"));
+            let info = vec![
+                ("isPartOf".to_string(), root_url.to_string()),
+                ("robots".to_string(), if self.config.robots.obey { "obey" } else { "ignore" }.to_string()),
+                ("http-header-user-agent".to_string(), self.config.http.user_agent.clone()),
+            ];
+            let max_file_size = self.config.warc.max_file_size_mb.saturating_mul(1024 * 1024);
+            let writer = WarcWriter::new(&dir, &prefix, max_file_size, self.config.warc.gzip, info)?;
+            self.warc = Some(Mutex::new(writer));
+        }
         Ok(())
     }
+    
+    /// Finishes every output sink. A failing sink does not stop the others
+    /// from being finished; the first error is returned once all have run.
+    fn save_results(&mut self) -> Result<(), Box<dyn std::error::Error>> {
+        let mut result = Ok(());
+        if let Some(warc) = self.warc.take() {
+            let mut warc = warc.into_inner().unwrap();
+            match warc.flush() {
+                Ok(()) => println!("Archived responses to {} WARC files", warc.file_count()),
+                Err(e) => result = Err(e.into()),
+            }
+        }
+        for mut sink in std::mem::take(self.outputs.get_mut().unwrap()) {
+            match sink.finish(&self.skipped) {
+                Ok(path) => println!("Saved {} pages to {}", self.page_count, path.display()),
+                Err(e) => {
+                    if result.is_ok() {
+                        result = Err(e);
+                    }
+                }
+            }
+        }
+        result
+    }
+}
+
+/// The level of an `h1`–`h6` element.
+fn heading_level(element: ElementRef) -> Option<u8> {
+    match element.value().name() {
+        "h1" => Some(1),
+        "h2" => Some(2),
+        "h3" => Some(3),
+        "h4" => Some(4),
+        "h5" => Some(5),
+        "h6" => Some(6),
+        _ => None,
+    }
+}
+
+/// Whether a heading with this (whitespace-collapsed) text is boilerplate
+/// like "Menu" or a short run of links rather than a real section title.
+fn is_navigation_heading(element: ElementRef, text: &str) -> bool {
+    let common_boilerplate_headings = ["navigation", "menu", "footer", "cookies", "search results", "search"];
+    let link_selector = Selector::parse("a").unwrap();
+    let lower_text = text.to_lowercase();
+    let link_text_len: usize = element.select(&link_selector).flat_map(|a| a.text()).map(|t| t.len()).sum();
+    let is_boilerplate = common_boilerplate_headings.contains(&lower_text.trim_end_matches(':'));
+    let is_link_heavy = link_text_len > text.len() / 2 && text.split_whitespace().count() < 5;
+    is_boilerplate || is_link_heavy
+}
+
+/// The `id` of a heading, or of an anchor inside it.
+fn heading_anchor(element: ElementRef) -> Option<String> {
+    element
+        .descendants()
+        .filter_map(ElementRef::wrap)
+        .find_map(|el| el.value().id().or_else(|| (el.value().name() == "a").then(|| el.value().attr("name")).flatten()))
+        .map(str::trim)
+        .filter(|anchor| !anchor.is_empty())
+        .map(str::to_string)
+}
+
+/// Nests flat headings under their `parent_id`s.
+fn build_outline(headings: &[Heading]) -> Vec<HeadingNode> {
+    fn children_of(headings: &[Heading], parent: Option<usize>) -> Vec<HeadingNode> {
+        headings
+            .iter()
+            .filter(|heading| heading.parent_id == parent)
+            .map(|heading| HeadingNode {
+                id: heading.id,
+                level: heading.level,
+                text: heading.text.clone(),
+                anchor: heading.anchor.clone(),
+                section_start: heading.section_start,
+                section_end: heading.section_end,
+                children: children_of(headings, Some(heading.id)),
+            })
+            .collect()
+    }
+    children_of(headings, None)
+}
+
+/// Whether extraction left anything worth keeping.
+fn has_content(page: &PageData) -> bool {
+    !page.content.full_text.trim().is_empty() || !page.content.paragraphs.is_empty() || !page.content.headings.is_empty()
+}
+
+/// Hands a finished page to every output sink, reporting rather than stopping on errors.
+fn write_page(outputs: &mut [Box<dyn OutputSink>], page: &PageData) {
+    for sink in outputs {
+        if let Err(e) = sink.write_page(page) {
+            eprintln!("Error writing {}: {}", page.url, e);
+        }
+    }
 }
 
-fn main() {
-    let root_url = "https://www.surrey.ac.uk/open-days";
-    let max_depth = 2; 
+/// Every URL that is not finished yet: those still queued plus those in flight.
+fn pending_urls(frontier: &Frontier, in_flight: &HashMap<String, usize>, deferred: &[(Instant, String, usize)]) -> Vec<(String, usize)> {
+    let mut pending: Vec<(String, usize)> = frontier.entries().map(|(url, depth)| (url.clone(), depth)).collect();
+    pending.extend(in_flight.iter().map(|(url, &depth)| (url.clone(), depth)));
+    pending.extend(deferred.iter().map(|(_, url, depth)| (url.clone(), *depth)));
+    pending
+}
+
+fn failed_page(url: &str, depth: usize, error: &str) -> PageData {
+    PageData {
+        url: url.to_string(),
+        title: "Failed to crawl".to_string(),
+        content_hash: None,
+        content: PageContent {
+            full_text: String::new(), headings: vec![], outline: vec![], paragraphs: vec![], lists: vec![], tables: vec![], markdown: String::new(), sentences: vec![], chunks: vec![],
+        },
+        metadata: PageMetadata {
+            crawl_timestamp: Utc::now(), depth, word_count: 0, language: None, language_confidence: None, declared_language: None, encoding: None, encoding_source: None, mime_type: None, byte_size: None, description: Some(format!("Error: {}", error)),
+            lastmod: None, sitemap_priority: None, content_selector: None,
+        },
+        structured_data: StructuredData::default(),
+        links: vec![],
+    }
+}
+
+fn load_output(path: &Path) -> Result<CrawlOutput, Box<dyn std::error::Error>> {
+    if output::is_jsonl_output(path) {
+        return output::read_jsonl(path);
+    }
+    match path.extension().and_then(|e| e.to_str()) {
+        Some("sqlite") => output::read_sqlite(path),
+        Some("csv") => Err("a CSV output only holds page summaries and cannot be read back".into()),
+        _ => Ok(serde_json::from_str(&fs::read_to_string(path)?)?),
+    }
+}
+
+fn finish_crawl(crawler: &mut Crawler) -> ExitCode {
+    if let Err(e) = crawler.save_results() {
+        eprintln!("Error saving results: {}", e);
+        return ExitCode::from(2);
+    }
+    if crawler.page_count == 0 {
+        println!("No pages were saved. The crawl might have resulted in no processable content or all pages were filtered out.");
+    }
+    ExitCode::SUCCESS
+}
+
+/// Builds the crawl configuration from `--config` (or `base` when no
+/// profile is given), then applies the command-line overrides.
+fn build_config(
+    profile: &cli::ProfileArgs,
+    limits: &cli::LimitArgs,
+    output: &cli::OutputArgs,
+    seeds: &[String],
+    base: Option<CrawlConfig>,
+) -> Result<CrawlConfig, ConfigError> {
+    let mut config = match &profile.config {
+        Some(path) => CrawlConfig::load(path)?,
+        None => base.unwrap_or_default(),
+    };
     
-    match Crawler::new(root_url) {
-        Ok(mut crawler) => {
-            crawler.crawl(root_url, 0, max_depth);
-            
-            if let Err(e) = crawler.save_results(root_url) {
-                eprintln!("Error saving results: {}", e);
-            } else if crawler.pages.is_empty() {
-                println!("No pages were saved. The crawl might have resulted in no processable content or all pages were filtered out.");
+    if !seeds.is_empty() {
+        config.seeds = seeds.to_vec();
+    }
+    if let Some(max_depth) = limits.max_depth {
+        config.max_depth = max_depth;
+    }
+    if let Some(max_pages) = limits.max_pages {
+        config.max_pages = Some(max_pages);
+    }
+    if let Some(user_agent) = &profile.user_agent {
+        config.http.user_agent = user_agent.clone();
+    }
+    if let Some(timeout) = profile.timeout {
+        config.http.timeout_secs = timeout;
+    }
+    if let Some(concurrency) = profile.concurrency {
+        config.concurrency = concurrency;
+    }
+    if profile.ignore_robots {
+        config.robots.obey = false;
+    }
+    if profile.sitemaps {
+        config.sitemaps.discover = true;
+    }
+    if profile.sitemap_only {
+        config.sitemaps.only = true;
+    }
+    if let Some(path) = &profile.checkpoint {
+        config.checkpoint.path = Some(path.clone());
+    }
+    if profile.no_checkpoint {
+        config.checkpoint.enabled = false;
+    }
+    if profile.warc {
+        config.warc.enabled = true;
+    }
+    apply_output_args(&mut config, output);
+    Ok(config)
+}
+
+fn apply_output_args(config: &mut CrawlConfig, output: &cli::OutputArgs) {
+    if let Some(dir) = &output.output_dir {
+        config.output.dir = dir.clone();
+    }
+    if !output.format.is_empty() {
+        config.output.formats = output.format.clone();
+    }
+}
+
+fn run_crawl(args: cli::CrawlArgs) -> ExitCode {
+    let config = match build_config(&args.profile, &args.limits, &args.output, &args.seeds, None) {
+        Ok(config) => config,
+        Err(e) => {
+            eprintln!("Invalid crawl configuration: {}", e);
+            return ExitCode::FAILURE;
+        }
+    };
+    let seeds = config.seeds.clone();
+    let max_depth = config.max_depth;
+    let mut crawler = match Crawler::new(config) {
+        Ok(crawler) => crawler,
+        Err(e) => {
+            eprintln!("Error initializing crawler: {}", e);
+            return ExitCode::FAILURE;
+        }
+    };
+    
+    if crawler.config.checkpoint.enabled {
+        let path = crawler.config.checkpoint.path.clone()
+            .unwrap_or_else(|| crawler.config.output.dir.join(format!("{}.checkpoint.db", crawler.output_stem())));
+        match Checkpoint::create(&path, &crawler.config, &seeds[0]) {
+            Ok(checkpoint) => {
+                println!("Checkpointing progress to {}", path.display());
+                crawler.checkpoint = Some(Mutex::new(checkpoint));
+            }
+            Err(e) => {
+                eprintln!("Error creating checkpoint {}: {}", path.display(), e);
+                return ExitCode::FAILURE;
             }
         }
+    }
+    
+    if let Err(e) = crawler.open_outputs(&seeds[0]) {
+        eprintln!("Error opening output in {}: {}", crawler.config.output.dir.display(), e);
+        return ExitCode::from(2);
+    }
+    
+    let mut start: Vec<(String, usize)> = Vec::new();
+    if !crawler.config.sitemaps.only {
+        start.extend(seeds.iter().map(|seed| (seed.clone(), 0)));
+    }
+    if crawler.config.sitemaps.enabled() {
+        start.extend(crawler.discover_sitemaps());
+    }
+    crawler.crawl(start, max_depth);
+    finish_crawl(&mut crawler)
+}
+
+fn run_resume(args: cli::ResumeArgs) -> ExitCode {
+    if Checkpoint::is_checkpoint(&args.input) {
+        return resume_checkpoint(args);
+    }
+    
+    let previous = match load_output(&args.input) {
+        Ok(previous) => previous,
+        Err(e) => {
+            eprintln!("Error reading {}: {}", args.input.display(), e);
+            return ExitCode::FAILURE;
+        }
+    };
+    let root_url = previous.root_url.clone();
+    let mut config = match build_config(&args.profile, &args.limits, &args.output, &[], None) {
+        Ok(config) => config,
+        Err(e) => {
+            eprintln!("Invalid crawl configuration: {}", e);
+            return ExitCode::FAILURE;
+        }
+    };
+    if config.seeds.is_empty() {
+        config.seeds = vec![root_url.clone()];
+    }
+    let max_depth = config.max_depth;
+    let mut crawler = match Crawler::new(config) {
+        Ok(crawler) => crawler,
         Err(e) => {
             eprintln!("Error initializing crawler: {}", e);
+            return ExitCode::FAILURE;
         }
+    };
+    
+    if let Err(e) = crawler.open_outputs(&root_url) {
+        eprintln!("Error opening output in {}: {}", crawler.config.output.dir.display(), e);
+        return ExitCode::from(2);
     }
+    
+    crawler.resume_from(previous, max_depth);
+    finish_crawl(&mut crawler)
 }
 
+/// Carries on from a checkpoint written by `crawl`, using the configuration
+/// stored in it unless a profile is passed with `--config`.
+fn resume_checkpoint(args: cli::ResumeArgs) -> ExitCode {
+    let checkpoint = match Checkpoint::open(&args.input) {
+        Ok(checkpoint) => checkpoint,
+        Err(e) => {
+            eprintln!("Error opening checkpoint {}: {}", args.input.display(), e);
+            return ExitCode::FAILURE;
+        }
+    };
+    let state = match checkpoint.config().and_then(|config| Ok((config, checkpoint.load()?))) {
+        Ok(state) => state,
+        Err(e) => {
+            eprintln!("Error reading checkpoint {}: {}", args.input.display(), e);
+            return ExitCode::FAILURE;
+        }
+    };
+    let (stored_config, state) = state;
+    if checkpoint.is_finished().unwrap_or(false) {
+        println!("Checkpoint is from a crawl that already finished; continuing with whatever it left queued");
+    }
+    
+    let config = match build_config(&args.profile, &args.limits, &args.output, &[], Some(stored_config)) {
+        Ok(config) => config,
+        Err(e) => {
+            eprintln!("Invalid crawl configuration: {}", e);
+            return ExitCode::FAILURE;
+        }
+    };
+    let max_depth = config.max_depth;
+    let mut crawler = match Crawler::new(config) {
+        Ok(crawler) => crawler,
+        Err(e) => {
+            eprintln!("Error initializing crawler: {}", e);
+            return ExitCode::FAILURE;
+        }
+    };
+    
+    let root_url = state.root_url.clone();
+    if let Err(e) = crawler.open_outputs(&root_url) {
+        eprintln!("Error opening output in {}: {}", crawler.config.output.dir.display(), e);
+        return ExitCode::from(2);
+    }
+    
+    let pending = crawler.restore(state);
+    crawler.checkpoint = Some(Mutex::new(checkpoint));
+    crawler.crawl(pending, max_depth);
+    finish_crawl(&mut crawler)
+}
 
+fn run_export(args: cli::ExportArgs) -> ExitCode {
+    let output = match load_output(&args.input) {
+        Ok(output) => output,
+        Err(e) => {
+            eprintln!("Error reading {}: {}", args.input.display(), e);
+            return ExitCode::FAILURE;
+        }
+    };
+    if let Err(e) = output::write_output(&output, &args.output, args.format) {
+        eprintln!("Error writing {}: {}", args.output.display(), e);
+        return ExitCode::from(2);
+    }
+    println!("Exported {} pages to {}", output.pages.len(), args.output.display());
+    ExitCode::SUCCESS
+}
+
+/// The target of the first response record in `inputs`, used as the root
+/// URL when the profile names no seeds.
+fn first_archived_url(inputs: &[PathBuf]) -> Option<String> {
+    inputs.iter().find_map(|path| {
+        WarcReader::open(path)
+            .ok()?
+            .map_while(Result::ok)
+            .find(|record| record.field("WARC-Type") == Some("response"))
+            .and_then(|record| record.field("WARC-Target-URI").map(str::to_string))
+    })
+}
+
+fn run_reprocess(args: cli::ReprocessArgs) -> ExitCode {
+    let config = match &args.config {
+        Some(path) => CrawlConfig::load(path),
+        None => Ok(CrawlConfig::default()),
+    };
+    let mut config = match config {
+        Ok(config) => config,
+        Err(e) => {
+            eprintln!("Invalid crawl configuration: {}", e);
+            return ExitCode::FAILURE;
+        }
+    };
+    apply_output_args(&mut config, &args.output);
+    let urls = match UrlMapping::new(args.base_url.clone(), args.url_map.as_deref()) {
+        Ok(urls) => urls,
+        Err(e) => {
+            eprintln!("Error reading URL mapping: {}", e);
+            return ExitCode::FAILURE;
+        }
+    };
+    let sources = match args.inputs.iter().map(|path| source::open(path, &urls).map_err(|e| (path, e))).collect::<Result<Vec<_>, _>>() {
+        Ok(sources) => sources,
+        Err((path, e)) => {
+            eprintln!("Error opening {}: {}", path.display(), e);
+            return ExitCode::FAILURE;
+        }
+    };
+    // Nothing is fetched, so there is nothing to archive or checkpoint.
+    config.warc.enabled = false;
+    config.checkpoint.enabled = false;
+    if config.seeds.is_empty() {
+        match urls.root().or_else(|| first_archived_url(&args.inputs)) {
+            Some(url) => config.seeds = vec![url],
+            None => {
+                eprintln!("No root URL: pass --base-url or --url-map, or name seeds in the profile");
+                return ExitCode::FAILURE;
+            }
+        }
+    }
+    let root_url = config.seeds[0].clone();
+    let mut crawler = match Crawler::new(config) {
+        Ok(crawler) => crawler,
+        Err(e) => {
+            eprintln!("Error initializing crawler: {}", e);
+            return ExitCode::FAILURE;
+        }
+    };
+    
+    if let Err(e) = crawler.open_outputs(&root_url) {
+        eprintln!("Error opening output in {}: {}", crawler.config.output.dir.display(), e);
+        return ExitCode::from(2);
+    }
+    crawler.reprocess(sources);
+    finish_crawl(&mut crawler)
+}
 
+fn run_inspect(args: cli::InspectArgs) -> ExitCode {
+    let output = match load_output(&args.input) {
+        Ok(output) => output,
+        Err(e) => {
+            eprintln!("Error reading {}: {}", args.input.display(), e);
+            return ExitCode::FAILURE;
+        }
+    };
+    
+    let failed = output.pages.iter().filter(|page| page.title == "Failed to crawl").count();
+    let words: usize = output.pages.iter().map(|page| page.metadata.word_count).sum();
+    let chunks: usize = output.pages.iter().map(|page| page.content.chunks.len()).sum();
+    let mut by_depth: BTreeMap<usize, usize> = BTreeMap::new();
+    for page in &output.pages {
+        *by_depth.entry(page.metadata.depth).or_default() += 1;
+    }
+    
+    println!("Domain:      {}", output.domain);
+    println!("Root URL:    {}", output.root_url);
+    println!("Crawled at:  {}", output.crawl_timestamp.to_rfc3339());
+    println!("Pages:       {} ({} failed)", output.total_pages, failed);
+    println!("Skipped:     {}", output.skipped.len());
+    println!("Words:       {}", words);
+    println!("Chunks:      {}", chunks);
+    for (depth, count) in by_depth {
+        println!("  depth {}:   {} pages", depth, count);
+    }
+    ExitCode::SUCCESS
+}
+
+fn main() -> ExitCode {
+    let cli = Cli::parse();
+    
+    match cli.command {
+        Command::Crawl(args) => run_crawl(args),
+        Command::Resume(args) => run_resume(args),
+        Command::Export(args) => run_export(args),
+        Command::Inspect(args) => run_inspect(args),
+        Command::Reprocess(args) => run_reprocess(args),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn scrape(html: &str) -> PageData {
+        scrape_with(CrawlConfig::default(), html)
+    }
+
+    fn scrape_with(config: CrawlConfig, html: &str) -> PageData {
+        let crawler = Crawler::new(CrawlConfig { seeds: vec!["https://example.org/".to_string()], ..config }).unwrap();
+        let body = Body { bytes: html.as_bytes().to_vec(), content_type: Some("text/html".to_string()), content_language: None };
+        let fetched = FetchedPage { url: "https://example.org/open-days".to_string(), depth: 0, body: Ok(body) };
+        match crawler.extract(&fetched) {
+            Extraction::Page(page) => *page,
+            Extraction::Skipped(reason) | Extraction::Failed(reason) => panic!("not extracted: {}", reason),
+            Extraction::Deferred => panic!("not extracted: deferred"),
+        }
+    }
+
+    #[test]
+    fn sections_end_at_the_next_heading_of_the_same_or_a_higher_level() {
+        let page = scrape(
+            "<html><body><main>
+            <h1>Open days</h1><p>Come and see the campus for yourself.</p>
+            <h2>Undergraduate</h2><p>Tours run every hour from the main entrance.</p>
+            <h3>Booking</h3><p>Book a place online before you travel.</p>
+            <h2>Research</h2><p>Meet our research groups and their students.</p>
+            <h2>Search</h2><p>Find a course that suits you.</p>
+            </main></body></html>",
+        );
+        let full_text = &page.content.full_text;
+        let texts: Vec<&str> = page.content.headings.iter().map(|heading| heading.text.as_str()).collect();
+        assert_eq!(texts, ["Open days", "Undergraduate", "Booking", "Research"]);
+
+        let research = full_text.find("Research").unwrap();
+        // The boilerplate "Search" heading is left out but still ends the section before it.
+        let search = full_text.rfind("Search").unwrap();
+        let ranges: Vec<(Option<usize>, Option<usize>)> =
+            page.content.headings.iter().map(|heading| (heading.section_start, heading.section_end)).collect();
+        assert_eq!(
+            ranges,
+            [
+                (Some(0), Some(full_text.len())),
+                (full_text.find("Undergraduate"), Some(research)),
+                (full_text.find("Booking"), Some(research)),
+                (Some(research), Some(search)),
+            ]
+        );
+
+        let parents: Vec<Option<usize>> = page.content.headings.iter().map(|heading| heading.parent_id).collect();
+        assert_eq!(parents, [None, Some(0), Some(1), Some(0)]);
+        assert_eq!(page.content.outline.len(), 1);
+        assert_eq!(page.content.outline[0].children.len(), 2);
+    }
+
+    #[test]
+    fn link_only_headings_stay_out_of_chunk_breadcrumbs() {
+        let mut config = CrawlConfig::default();
+        config.chunking.strategy = config::ChunkStrategy::Sections;
+        let page = scrape_with(
+            config,
+            "<html><body><main>
+            <h1>Open days</h1><p>Come and see the campus for yourself.</p>
+            <h2><a href=\"/\">Home</a></h2><p>Links back to the start.</p>
+            <h3>Booking</h3><p>Book a place online before you travel.</p>
+            </main></body></html>",
+        );
+        let crumbs: Vec<Option<&str>> = page.content.chunks.iter().map(|chunk| chunk.section_heading.as_deref()).collect();
+        assert_eq!(crumbs, [Some("Open days"), Some("Open days"), Some("Open days > Booking")]);
+    }
+}
@@ -0,0 +1,427 @@
+use crate::tables::{self, Table};
+use scraper::{ElementRef, Node};
+use url::Url;
+
+/// Renders an element's content as Markdown: headings, paragraphs, nested
+/// lists, links (made absolute), emphasis, inline code, fenced code blocks,
+/// blockquotes, images and tables.
+pub struct MarkdownRenderer<'a> {
+    pub base: &'a Url,
+    /// Elements left out along with everything inside them.
+    pub skippable: &'a dyn Fn(ElementRef) -> bool,
+    /// Text nodes left out, such as cookie banner phrases.
+    pub is_noise: &'a dyn Fn(&str) -> bool,
+}
+
+impl MarkdownRenderer<'_> {
+    pub fn render(&self, root: ElementRef) -> String {
+        let mut out = String::new();
+        self.children(root, &mut out);
+        tidy(&out)
+    }
+
+    fn children(&self, element: ElementRef, out: &mut String) {
+        for child in element.children() {
+            match child.value() {
+                Node::Text(text) => self.text(text, out),
+                Node::Element(_) => {
+                    if let Some(child) = ElementRef::wrap(child) {
+                        if !(self.skippable)(child) {
+                            self.element(child, out);
+                        }
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+
+    fn text(&self, text: &str, out: &mut String) {
+        if (self.is_noise)(text.trim()) {
+            return;
+        }
+        let mut collapsed = text.split_whitespace().collect::<Vec<_>>().join(" ");
+        if text.starts_with(char::is_whitespace) && !collapsed.is_empty() {
+            collapsed.insert(0, ' ');
+        }
+        if text.ends_with(char::is_whitespace) {
+            collapsed.push(' ');
+        }
+        // No space at the start of a line or after another space.
+        let line_start = out.is_empty() || out.ends_with('\n');
+        if line_start || out.ends_with(' ') {
+            collapsed = collapsed.trim_start().to_string();
+        }
+        out.push_str(&escape(&collapsed, line_start));
+    }
+
+    /// Renders the children on their own, trimmed and on a single line.
+    fn inline(&self, element: ElementRef) -> String {
+        let mut inner = String::new();
+        self.children(element, &mut inner);
+        inner.split_whitespace().collect::<Vec<_>>().join(" ")
+    }
+
+    /// Wraps inline content in `marker`, keeping surrounding spaces outside it.
+    fn wrap(&self, element: ElementRef, marker: &str, out: &mut String) {
+        let inner = self.inline(element);
+        if inner.is_empty() {
+            return;
+        }
+        self.inline_wrapped(element, &format!("{}{}{}", marker, inner, marker), out);
+    }
+
+    /// Appends inline markup, moving whitespace at the element's edges
+    /// outside it so `<b>bold </b>text` doesn't run together.
+    fn inline_wrapped(&self, element: ElementRef, markup: &str, out: &mut String) {
+        let text = element.text().collect::<String>();
+        if text.starts_with(char::is_whitespace) && !out.is_empty() && !out.ends_with([' ', '\n']) {
+            out.push(' ');
+        }
+        out.push_str(markup);
+        if text.ends_with(char::is_whitespace) {
+            out.push(' ');
+        }
+    }
+
+    fn element(&self, element: ElementRef, out: &mut String) {
+        let name = element.value().name();
+        match name {
+            "h1" | "h2" | "h3" | "h4" | "h5" | "h6" => {
+                let text = self.inline(element);
+                if !text.is_empty() {
+                    block_break(out);
+                    let level = name[1..].parse::<usize>().unwrap_or(1);
+                    out.push_str(&format!("{} {}", "#".repeat(level), text));
+                    block_break(out);
+                }
+            }
+            "strong" | "b" => self.wrap(element, "**", out),
+            "em" | "i" => self.wrap(element, "*", out),
+            "del" | "s" | "strike" => self.wrap(element, "~~", out),
+            "code" | "kbd" | "samp" => {
+                let code = element.text().collect::<String>();
+                let code = code.trim();
+                if !code.is_empty() {
+                    let ticks = "`".repeat(longest_backtick_run(code) + 1);
+                    // A space keeps a backtick at either end from joining the delimiter.
+                    let pad = if code.starts_with('`') || code.ends_with('`') { " " } else { "" };
+                    out.push_str(&format!("{}{}{}{}{}", ticks, pad, code, pad, ticks));
+                }
+            }
+            "pre" => {
+                let code = element.text().collect::<String>();
+                let code = code.trim_matches('\n').trim_end();
+                if !code.is_empty() {
+                    block_break(out);
+                    let fence = "`".repeat(longest_backtick_run(code).max(2) + 1);
+                    out.push_str(&format!("{}{}\n{}\n{}", fence, code_language(element).unwrap_or_default(), code, fence));
+                    block_break(out);
+                }
+            }
+            "a" => {
+                let text = self.inline(element);
+                let href = element.value().attr("href").map(str::trim).unwrap_or("");
+                let target = if href.is_empty() || href.to_lowercase().starts_with("javascript:") {
+                    None
+                } else if href.starts_with('#') {
+                    Some(href.to_string())
+                } else {
+                    self.base.join(href).ok().map(|url| url.to_string())
+                };
+                match target {
+                    Some(target) if !text.is_empty() => self.inline_wrapped(element, &format!("[{}]({})", text, target), out),
+                    _ => self.inline_wrapped(element, &text, out),
+                }
+            }
+            "img" => {
+                let alt = element.value().attr("alt").map(str::trim).unwrap_or("");
+                let src = element.value().attr("src").unwrap_or("");
+                if !alt.is_empty() && !src.starts_with("data:") {
+                    if let Ok(src) = self.base.join(src) {
+                        out.push_str(&format!("![{}]({})", alt, src));
+                    }
+                }
+            }
+            "br" => {
+                let trimmed = out.trim_end_matches(' ').len();
+                out.truncate(trimmed);
+                out.push('\n');
+            }
+            "hr" => {
+                block_break(out);
+                out.push_str("---");
+                block_break(out);
+            }
+            "ul" | "ol" => {
+                block_break(out);
+                let mut number = element.value().attr("start").and_then(|start| start.parse::<usize>().ok()).unwrap_or(1);
+                for item in element.children().filter_map(ElementRef::wrap) {
+                    if item.value().name() != "li" || (self.skippable)(item) {
+                        continue;
+                    }
+                    let marker = if name == "ol" { format!("{}. ", number) } else { "- ".to_string() };
+                    number += 1;
+                    let mut content = String::new();
+                    self.children(item, &mut content);
+                    let content = tighten(&tidy(&content));
+                    if content.is_empty() {
+                        continue;
+                    }
+                    if !out.is_empty() && !out.ends_with('\n') {
+                        out.push('\n');
+                    }
+                    out.push_str(&marker);
+                    out.push_str(&indent(&content, &" ".repeat(marker.len())));
+                }
+                block_break(out);
+            }
+            "blockquote" => {
+                let mut content = String::new();
+                self.children(element, &mut content);
+                let content = tidy(&content);
+                if !content.is_empty() {
+                    block_break(out);
+                    let quoted: Vec<String> = content
+                        .lines()
+                        .map(|line| if line.is_empty() { ">".to_string() } else { format!("> {}", line) })
+                        .collect();
+                    out.push_str(&quoted.join("\n"));
+                    block_break(out);
+                }
+            }
+            "table" => {
+                if let Some(table) = Table::from_element(element, None, self.skippable) {
+                    block_break(out);
+                    out.push_str(table.to_markdown().trim_end());
+                    block_break(out);
+                }
+                for nested in tables::nested_tables(element, self.skippable) {
+                    self.element(nested, out);
+                }
+            }
+            "dt" => {
+                let text = self.inline(element);
+                if !text.is_empty() {
+                    block_break(out);
+                    out.push_str(&format!("**{}**", text));
+                    out.push('\n');
+                }
+            }
+            "p" | "div" | "section" | "article" | "main" | "header" | "footer" | "aside" | "nav" | "figure"
+            | "figcaption" | "dl" | "dd" | "address" | "details" | "summary" | "fieldset" | "li" => {
+                block_break(out);
+                self.children(element, out);
+                block_break(out);
+            }
+            _ => self.children(element, out),
+        }
+    }
+}
+
+/// Ends the current block so the next starts after a blank line.
+fn block_break(out: &mut String) {
+    let trimmed = out.trim_end_matches([' ', '\n']).len();
+    out.truncate(trimmed);
+    if !out.is_empty() {
+        out.push_str("\n\n");
+    }
+}
+
+/// Indents every line but the first, for list item continuations.
+fn indent(content: &str, prefix: &str) -> String {
+    content
+        .lines()
+        .enumerate()
+        .map(|(i, line)| if i == 0 || line.is_empty() { line.to_string() } else { format!("{}{}", prefix, line) })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// Strips trailing spaces and runs of blank lines, leaving code blocks alone.
+fn tidy(markdown: &str) -> String {
+    let mut lines: Vec<&str> = Vec::new();
+    let mut fences = Fences::default();
+    for line in markdown.lines() {
+        let in_code = fences.in_code(line);
+        let line = if in_code { line } else { line.trim_end() };
+        if !in_code && line.is_empty() && lines.last().is_none_or(|last| last.is_empty()) {
+            continue;
+        }
+        lines.push(line);
+    }
+    lines.join("\n").trim().to_string()
+}
+
+/// Keeps a list item tight by dropping the blank line before a nested
+/// list. Breaks between paragraphs, and anything in a code block, stay.
+fn tighten(content: &str) -> String {
+    let lines: Vec<&str> = content.lines().collect();
+    let mut fences = Fences::default();
+    let mut kept = Vec::with_capacity(lines.len());
+    for (i, line) in lines.iter().enumerate() {
+        let in_code = fences.in_code(line);
+        let before_list = lines.get(i + 1).is_some_and(|next| starts_list_item(next));
+        if !in_code && line.is_empty() && before_list {
+            continue;
+        }
+        kept.push(*line);
+    }
+    kept.join("\n")
+}
+
+/// Whether a rendered line opens a list item. Text that only looks like one is escaped, so this is exact.
+fn starts_list_item(line: &str) -> bool {
+    let digits = line.len() - line.trim_start_matches(|c: char| c.is_ascii_digit()).len();
+    line.starts_with("- ") || (digits > 0 && line[digits..].starts_with(". "))
+}
+
+/// Follows fenced code blocks line by line.
+#[derive(Default)]
+struct Fences {
+    /// The length of the open fence's run of backticks.
+    open: Option<usize>,
+}
+
+impl Fences {
+    /// Whether `line` belongs to a code block, counting its fences.
+    fn in_code(&mut self, line: &str) -> bool {
+        let trimmed = line.trim_start();
+        let ticks = trimmed.len() - trimmed.trim_start_matches('`').len();
+        match self.open {
+            Some(open) => {
+                if ticks >= open && trimmed[ticks..].trim().is_empty() {
+                    self.open = None;
+                }
+                true
+            }
+            None if ticks >= 3 => {
+                self.open = Some(ticks);
+                true
+            }
+            None => false,
+        }
+    }
+}
+
+fn longest_backtick_run(code: &str) -> usize {
+    code.split(|c| c != '`').map(str::len).max().unwrap_or(0)
+}
+
+/// Escapes what would read as Markdown in plain text: emphasis, code and
+/// link characters anywhere, and at the start of a line anything that would
+/// open a heading, quote, list, rule or fence.
+fn escape(text: &str, line_start: bool) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        if matches!(c, '\\' | '`' | '*' | '_' | '[' | ']') {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    if !line_start {
+        return escaped;
+    }
+    let digits = escaped.len() - escaped.trim_start_matches(|c: char| c.is_ascii_digit()).len();
+    if escaped.starts_with(['#', '>', '-', '+', '=', '~', '|']) {
+        escaped.insert(0, '\\');
+    } else if digits > 0 && escaped[digits..].starts_with(['.', ')']) && escaped[digits + 1..].chars().next().is_none_or(char::is_whitespace) {
+        escaped.insert(digits, '\\');
+    }
+    escaped
+}
+
+/// The language named by a `language-*` or `lang-*` class on a `<pre>` or its `<code>`.
+fn code_language(pre: ElementRef) -> Option<String> {
+    let code = pre.children().filter_map(ElementRef::wrap).find(|child| child.value().name() == "code");
+    [Some(pre), code].into_iter().flatten().find_map(|element| {
+        element.value().classes().find_map(|class| {
+            class.strip_prefix("language-").or_else(|| class.strip_prefix("lang-")).map(str::to_string)
+        })
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use scraper::{Html, Selector};
+
+    fn render(body: &str) -> String {
+        let document = Html::parse_document(&format!("<html><body>{}</body></html>", body));
+        let root = document.select(&Selector::parse("body").unwrap()).next().unwrap();
+        let base = Url::parse("https://example.org/courses/").unwrap();
+        let skippable = |element: ElementRef| element.value().name() == "nav";
+        let renderer = MarkdownRenderer { base: &base, skippable: &skippable, is_noise: &|text| text == "Accept cookies" };
+        renderer.render(root)
+    }
+
+    #[test]
+    fn headings_paragraphs_and_emphasis() {
+        assert_eq!(
+            render("<h1>Open <em>days</em></h1><p>Book <b>now </b>or <s>later</s>.</p><nav>Menu</nav><p>Accept cookies</p><h3>More</h3>"),
+            "# Open *days*\n\nBook **now** or ~~later~~.\n\n### More"
+        );
+    }
+
+    #[test]
+    fn nested_lists_stay_tight() {
+        assert_eq!(
+            render("<ul><li>Fees<ul><li>Home</li><li>Overseas</li></ul></li><li>Dates</li></ul><ol start=3><li>Three</li><li>Four</li></ol>"),
+            "- Fees\n  - Home\n  - Overseas\n- Dates\n\n3. Three\n4. Four"
+        );
+        // Paragraphs inside an item keep the blank line that separates them.
+        assert_eq!(render("<ul><li><p>First</p><p>Second</p></li></ul>"), "- First\n\n  Second");
+    }
+
+    #[test]
+    fn links_are_made_absolute() {
+        assert_eq!(
+            render(r##"<p><a href="history">History</a>, <a href="#fees">fees</a>, <a href="javascript:void(0)">menu</a> and <a href="/x"></a></p>"##),
+            "[History](https://example.org/courses/history), [fees](#fees), menu and"
+        );
+        assert_eq!(render(r#"<img src="/a.png" alt="Campus">"#), "![Campus](https://example.org/a.png)");
+    }
+
+    #[test]
+    fn inline_code_takes_a_longer_delimiter_than_its_content() {
+        assert_eq!(render("<p>Run <code>cargo test</code> first</p>"), "Run `cargo test` first");
+        assert_eq!(render("<p><code>a `b` c</code></p>"), "``a `b` c``");
+        assert_eq!(render("<p><code>`x</code></p>"), "`` `x ``");
+    }
+
+    #[test]
+    fn fenced_code_keeps_its_text() {
+        let code = "fn main() {\n\n    println!(\"*hi*\");\n}";
+        assert_eq!(
+            render(&format!("<pre><code class=\"language-rust\">{}</code></pre>", code)),
+            format!("```rust\n{}\n```", code)
+        );
+        // A fence is longer than any run of backticks inside the code.
+        assert_eq!(render("<pre>```\nnested\n```</pre><p>after</p>"), "````\n```\nnested\n```\n````\n\nafter");
+    }
+
+    #[test]
+    fn code_inside_list_items_keeps_its_blank_lines() {
+        assert_eq!(
+            render("<ol><li>Install:<pre>a\n\nb</pre></li><li>Run</li></ol>"),
+            "1. Install:\n\n   ```\n   a\n\n   b\n   ```\n2. Run"
+        );
+    }
+
+    #[test]
+    fn prose_that_looks_like_markdown_is_escaped() {
+        assert_eq!(render("<p>2 * 3 = 6, snake_case and [1]</p>"), "2 \\* 3 = 6, snake\\_case and \\[1\\]");
+        assert_eq!(render("<p># not a heading</p><p>1. not a list</p><p>- nor this</p>"), "\\# not a heading\n\n1\\. not a list\n\n\\- nor this");
+        // Only where they would mean something.
+        assert_eq!(render("<p>C# in 2024. Up 3.5% - well</p>"), "C# in 2024. Up 3.5% - well");
+        assert_eq!(render("<ul><li># one</li></ul>"), "- \\# one");
+    }
+
+    #[test]
+    fn nested_tables_follow_their_table() {
+        assert_eq!(
+            render("<table><tr><th>Fee</th></tr><tr><td>£9,250 <nav>Menu</nav><table><tr><td>inner</td></tr></table></td></tr></table>"),
+            "| Fee |\n| --- |\n| £9,250 |\n\n| inner |\n| --- |"
+        );
+    }
+}
@@ -0,0 +1,531 @@
+use crate::{CrawlOutput, LinkType, PageData, SkippedUrl};
+use chrono::{DateTime, Utc};
+use rusqlite::{params, Connection};
+use serde::{Deserialize, Serialize};
+use std::fs::{self, File};
+use std::io::{BufRead, BufReader, BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+/// The built-in sinks, as named in crawl profiles and on the command line.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, clap::ValueEnum)]
+#[serde(rename_all = "kebab-case")]
+pub enum OutputFormat {
+    /// Pretty-printed JSON
+    Json,
+    /// JSON on a single line
+    JsonCompact,
+    /// One page per line, written as pages finish, plus a `.manifest.json` with the crawl details
+    Jsonl,
+    /// One summary row per page
+    Csv,
+    /// An SQLite database with pages, chunks, links and skipped URLs in their own tables
+    Sqlite,
+}
+
+impl OutputFormat {
+    pub fn extension(self) -> &'static str {
+        match self {
+            OutputFormat::Json | OutputFormat::JsonCompact => "json",
+            OutputFormat::Jsonl => "jsonl",
+            OutputFormat::Csv => "csv",
+            OutputFormat::Sqlite => "sqlite",
+        }
+    }
+
+    /// Builds the sink that writes this format to `path`.
+    pub fn sink(self, path: PathBuf) -> Box<dyn OutputSink> {
+        match self {
+            OutputFormat::Json => Box::new(JsonSink::new(path, true)),
+            OutputFormat::JsonCompact => Box::new(JsonSink::new(path, false)),
+            OutputFormat::Jsonl => Box::new(JsonlSink::new(path)),
+            OutputFormat::Csv => Box::new(CsvSink::new(path)),
+            OutputFormat::Sqlite => Box::new(SqliteSink::new(path)),
+        }
+    }
+}
+
+/// What a sink is told about the crawl before any page arrives.
+#[derive(Debug, Clone)]
+pub struct CrawlInfo {
+    pub domain: String,
+    pub root_url: String,
+    pub crawl_timestamp: DateTime<Utc>,
+}
+
+/// A destination for crawl results. `open` is called once before the first
+/// page, `write_page` once per finished page in the order pages finish, and
+/// `finish` once at the end with the URLs that were skipped.
+pub trait OutputSink: Send {
+    fn open(&mut self, crawl: &CrawlInfo) -> Result<(), Box<dyn std::error::Error>>;
+
+    fn write_page(&mut self, page: &PageData) -> Result<(), Box<dyn std::error::Error>>;
+
+    /// Completes the output and returns the path of the file describing the whole crawl.
+    fn finish(&mut self, skipped: &[SkippedUrl]) -> Result<PathBuf, Box<dyn std::error::Error>>;
+}
+
+fn create_parent(path: &Path) -> std::io::Result<()> {
+    match path.parent().filter(|parent| !parent.as_os_str().is_empty()) {
+        Some(parent) => fs::create_dir_all(parent),
+        None => Ok(()),
+    }
+}
+
+fn file_name(path: &Path) -> String {
+    path.file_name().map(|name| name.to_string_lossy().into_owned()).unwrap_or_default()
+}
+
+/// Writes a single `CrawlOutput` document, streaming each page into its
+/// `pages` array as it finishes so nothing is held in memory. Pages appear in
+/// the order they finish, and `total_pages` and `skipped` come after the
+/// array because they are only known at the end; JSON readers don't mind.
+pub struct JsonSink {
+    path: PathBuf,
+    pretty: bool,
+    writer: Option<BufWriter<File>>,
+    written: usize,
+}
+
+impl JsonSink {
+    pub fn new(path: PathBuf, pretty: bool) -> Self {
+        JsonSink { path, pretty, writer: None, written: 0 }
+    }
+
+    /// Writes `"name": value`, preceded by the line break and indent of a
+    /// member of the top-level object.
+    fn write_field(&mut self, name: &str, value: &impl Serialize, first: bool) -> Result<(), Box<dyn std::error::Error>> {
+        let pretty = self.pretty;
+        let writer = self.writer.as_mut().ok_or("output was not opened")?;
+        if !first {
+            writer.write_all(b",")?;
+        }
+        if pretty {
+            write!(writer, "\n  {}: ", serde_json::to_string(name)?)?;
+        } else {
+            write!(writer, "{}:", serde_json::to_string(name)?)?;
+        }
+        write_json(writer, value, pretty, "  ")
+    }
+}
+
+/// Writes `value` as JSON, pretty-printed with every line after the first
+/// indented by `indent` so it nests inside the surrounding document.
+fn write_json(writer: &mut impl Write, value: &impl Serialize, pretty: bool, indent: &str) -> Result<(), Box<dyn std::error::Error>> {
+    if pretty {
+        // Newlines inside JSON strings are escaped, so every raw one is a line break.
+        let text = serde_json::to_string_pretty(value)?;
+        writer.write_all(text.replace('\n', &format!("\n{}", indent)).as_bytes())?;
+    } else {
+        serde_json::to_writer(writer, value)?;
+    }
+    Ok(())
+}
+
+impl OutputSink for JsonSink {
+    fn open(&mut self, crawl: &CrawlInfo) -> Result<(), Box<dyn std::error::Error>> {
+        create_parent(&self.path)?;
+        let mut writer = BufWriter::new(File::create(&self.path)?);
+        writer.write_all(b"{")?;
+        self.writer = Some(writer);
+        self.written = 0;
+        self.write_field("domain", &crawl.domain, true)?;
+        self.write_field("root_url", &crawl.root_url, false)?;
+        self.write_field("crawl_timestamp", &crawl.crawl_timestamp, false)?;
+        let writer = self.writer.as_mut().ok_or("output was not opened")?;
+        writer.write_all(if self.pretty { b",\n  \"pages\": [" } else { b",\"pages\":[" })?;
+        Ok(())
+    }
+
+    fn write_page(&mut self, page: &PageData) -> Result<(), Box<dyn std::error::Error>> {
+        let writer = self.writer.as_mut().ok_or("output was not opened")?;
+        if self.written > 0 {
+            writer.write_all(b",")?;
+        }
+        if self.pretty {
+            writer.write_all(b"\n    ")?;
+        }
+        write_json(writer, page, self.pretty, "    ")?;
+        self.written += 1;
+        Ok(())
+    }
+
+    fn finish(&mut self, skipped: &[SkippedUrl]) -> Result<PathBuf, Box<dyn std::error::Error>> {
+        let writer = self.writer.as_mut().ok_or("output was not opened")?;
+        if self.pretty && self.written > 0 {
+            writer.write_all(b"\n  ")?;
+        }
+        writer.write_all(b"]")?;
+        let total_pages = self.written;
+        self.write_field("total_pages", &total_pages, false)?;
+        self.write_field("skipped", &skipped, false)?;
+        let mut writer = self.writer.take().ok_or("output was not opened")?;
+        writer.write_all(if self.pretty { b"\n}\n" } else { b"}" })?;
+        writer.flush()?;
+        Ok(self.path.clone())
+    }
+}
+
+/// The `CrawlOutput` header written next to a JSONL page file.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Manifest {
+    pub domain: String,
+    pub root_url: String,
+    pub crawl_timestamp: DateTime<Utc>,
+    pub total_pages: usize,
+    /// The JSONL file holding the pages, relative to the manifest.
+    pub pages_file: String,
+    #[serde(default)]
+    pub skipped: Vec<SkippedUrl>,
+}
+
+/// Writes each page to `<stem>.jsonl` as soon as it is finished, one JSON
+/// object per line, and the header fields to `<stem>.manifest.json` once the
+/// crawl is over. Lines appear in the order pages finish.
+pub struct JsonlSink {
+    pages_path: PathBuf,
+    manifest_path: PathBuf,
+    writer: Option<BufWriter<File>>,
+    crawl: Option<CrawlInfo>,
+    written: usize,
+}
+
+impl JsonlSink {
+    pub fn new(pages_path: PathBuf) -> Self {
+        let name = file_name(&pages_path);
+        let stem = name.strip_suffix(".jsonl").unwrap_or(&name);
+        let manifest_path = pages_path.with_file_name(format!("{}.manifest.json", stem));
+        JsonlSink { pages_path, manifest_path, writer: None, crawl: None, written: 0 }
+    }
+}
+
+impl OutputSink for JsonlSink {
+    fn open(&mut self, crawl: &CrawlInfo) -> Result<(), Box<dyn std::error::Error>> {
+        create_parent(&self.pages_path)?;
+        self.writer = Some(BufWriter::new(File::create(&self.pages_path)?));
+        self.crawl = Some(crawl.clone());
+        Ok(())
+    }
+
+    fn write_page(&mut self, page: &PageData) -> Result<(), Box<dyn std::error::Error>> {
+        let writer = self.writer.as_mut().ok_or("output was not opened")?;
+        serde_json::to_writer(&mut *writer, page)?;
+        writer.write_all(b"\n")?;
+        // Flushed per page so the file can be tailed while the crawl runs.
+        writer.flush()?;
+        self.written += 1;
+        Ok(())
+    }
+
+    /// Writes the manifest and returns its path.
+    fn finish(&mut self, skipped: &[SkippedUrl]) -> Result<PathBuf, Box<dyn std::error::Error>> {
+        self.writer.take().ok_or("output was not opened")?.flush()?;
+        let crawl = self.crawl.take().ok_or("output was not opened")?;
+        let manifest = Manifest {
+            domain: crawl.domain,
+            root_url: crawl.root_url,
+            crawl_timestamp: crawl.crawl_timestamp,
+            total_pages: self.written,
+            pages_file: file_name(&self.pages_path),
+            skipped: skipped.to_vec(),
+        };
+        fs::write(&self.manifest_path, serde_json::to_string_pretty(&manifest)?)?;
+        Ok(self.manifest_path.clone())
+    }
+}
+
+/// Writes one row per page with its headline figures, for spreadsheets and
+/// quick filtering. The extracted text itself is left to the other formats.
+pub struct CsvSink {
+    path: PathBuf,
+    writer: Option<csv::Writer<File>>,
+}
+
+impl CsvSink {
+    pub fn new(path: PathBuf) -> Self {
+        CsvSink { path, writer: None }
+    }
+}
+
+impl OutputSink for CsvSink {
+    fn open(&mut self, _crawl: &CrawlInfo) -> Result<(), Box<dyn std::error::Error>> {
+        create_parent(&self.path)?;
+        let mut writer = csv::Writer::from_path(&self.path)?;
+        writer.write_record([
+            "url", "title", "depth", "word_count", "headings", "chunks", "internal_links", "external_links",
+            "language", "description", "lastmod", "sitemap_priority", "crawl_timestamp",
+        ])?;
+        self.writer = Some(writer);
+        Ok(())
+    }
+
+    fn write_page(&mut self, page: &PageData) -> Result<(), Box<dyn std::error::Error>> {
+        let writer = self.writer.as_mut().ok_or("output was not opened")?;
+        let internal = page.links.iter().filter(|link| matches!(link.link_type, LinkType::Internal)).count();
+        let external = page.links.iter().filter(|link| matches!(link.link_type, LinkType::External)).count();
+        writer.write_record([
+            page.url.clone(),
+            page.title.clone(),
+            page.metadata.depth.to_string(),
+            page.metadata.word_count.to_string(),
+            page.content.headings.len().to_string(),
+            page.content.chunks.len().to_string(),
+            internal.to_string(),
+            external.to_string(),
+            page.metadata.language.clone().unwrap_or_default(),
+            page.metadata.description.clone().unwrap_or_default(),
+            page.metadata.lastmod.clone().unwrap_or_default(),
+            page.metadata.sitemap_priority.map(|p| p.to_string()).unwrap_or_default(),
+            page.metadata.crawl_timestamp.to_rfc3339(),
+        ])?;
+        writer.flush()?;
+        Ok(())
+    }
+
+    fn finish(&mut self, _skipped: &[SkippedUrl]) -> Result<PathBuf, Box<dyn std::error::Error>> {
+        self.writer.take().ok_or("output was not opened")?.flush()?;
+        Ok(self.path.clone())
+    }
+}
+
+const SQLITE_SCHEMA: &str = "
+    CREATE TABLE crawl (domain TEXT NOT NULL, root_url TEXT NOT NULL, crawl_timestamp TEXT NOT NULL, total_pages INTEGER);
+    CREATE TABLE pages (
+        url TEXT PRIMARY KEY, title TEXT NOT NULL, depth INTEGER NOT NULL, word_count INTEGER NOT NULL,
+        language TEXT, description TEXT, lastmod TEXT, sitemap_priority REAL, crawl_timestamp TEXT NOT NULL,
+        full_text TEXT NOT NULL, content_hash TEXT, data TEXT NOT NULL
+    );
+    CREATE TABLE chunks (
+        chunk_id TEXT NOT NULL, url TEXT NOT NULL REFERENCES pages (url), position INTEGER NOT NULL,
+        text TEXT NOT NULL, byte_start INTEGER NOT NULL, byte_end INTEGER NOT NULL, char_start INTEGER NOT NULL, char_end INTEGER NOT NULL,
+        section_heading TEXT, token_count INTEGER, language TEXT
+    );
+    CREATE TABLE links (url TEXT NOT NULL REFERENCES pages (url), href TEXT NOT NULL, text TEXT NOT NULL, link_type TEXT NOT NULL);
+    CREATE TABLE skipped (url TEXT PRIMARY KEY, reason TEXT NOT NULL, found_on TEXT);
+    CREATE INDEX chunks_url ON chunks (url);
+    CREATE INDEX links_url ON links (url);
+";
+
+/// Writes an SQLite database with a row per page, chunk, link and skipped
+/// URL. Each page is committed as it finishes; `pages.data` holds the full
+/// page as JSON so nothing is lost to the flattened columns.
+pub struct SqliteSink {
+    path: PathBuf,
+    conn: Option<Connection>,
+    written: usize,
+}
+
+impl SqliteSink {
+    pub fn new(path: PathBuf) -> Self {
+        SqliteSink { path, conn: None, written: 0 }
+    }
+}
+
+impl OutputSink for SqliteSink {
+    fn open(&mut self, crawl: &CrawlInfo) -> Result<(), Box<dyn std::error::Error>> {
+        create_parent(&self.path)?;
+        if self.path.exists() {
+            fs::remove_file(&self.path)?;
+        }
+        let conn = Connection::open(&self.path)?;
+        conn.execute_batch(SQLITE_SCHEMA)?;
+        conn.execute(
+            "INSERT INTO crawl (domain, root_url, crawl_timestamp) VALUES (?1, ?2, ?3)",
+            params![crawl.domain, crawl.root_url, crawl.crawl_timestamp.to_rfc3339()],
+        )?;
+        self.conn = Some(conn);
+        Ok(())
+    }
+
+    fn write_page(&mut self, page: &PageData) -> Result<(), Box<dyn std::error::Error>> {
+        let conn = self.conn.as_mut().ok_or("output was not opened")?;
+        let tx = conn.transaction()?;
+        // A page retried on resume replaces the failed attempt.
+        tx.execute("DELETE FROM chunks WHERE url = ?1", [&page.url])?;
+        tx.execute("DELETE FROM links WHERE url = ?1", [&page.url])?;
+        tx.execute(
+            "INSERT OR REPLACE INTO pages (url, title, depth, word_count, language, description, lastmod, sitemap_priority, crawl_timestamp, full_text, content_hash, data)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12)",
+            params![
+                page.url,
+                page.title,
+                page.metadata.depth as i64,
+                page.metadata.word_count as i64,
+                page.metadata.language,
+                page.metadata.description,
+                page.metadata.lastmod,
+                page.metadata.sitemap_priority,
+                page.metadata.crawl_timestamp.to_rfc3339(),
+                page.content.full_text,
+                page.content_hash,
+                serde_json::to_string(page)?,
+            ],
+        )?;
+        {
+            let mut insert = tx.prepare(
+                "INSERT INTO chunks (chunk_id, url, position, text, byte_start, byte_end, char_start, char_end, section_heading, token_count, language)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11)",
+            )?;
+            for (position, chunk) in page.content.chunks.iter().enumerate() {
+                insert.execute(params![
+                    chunk.chunk_id,
+                    page.url,
+                    position as i64,
+                    chunk.text,
+                    chunk.byte_start as i64,
+                    chunk.byte_end as i64,
+                    chunk.char_start as i64,
+                    chunk.char_end as i64,
+                    chunk.section_heading,
+                    chunk.token_count.map(|count| count as i64),
+                    chunk.language,
+                ])?;
+            }
+            let mut insert = tx.prepare("INSERT INTO links (url, href, text, link_type) VALUES (?1, ?2, ?3, ?4)")?;
+            for link in &page.links {
+                insert.execute(params![page.url, link.href, link.text, format!("{:?}", link.link_type)])?;
+            }
+        }
+        tx.commit()?;
+        self.written += 1;
+        Ok(())
+    }
+
+    fn finish(&mut self, skipped: &[SkippedUrl]) -> Result<PathBuf, Box<dyn std::error::Error>> {
+        let mut conn = self.conn.take().ok_or("output was not opened")?;
+        let tx = conn.transaction()?;
+        tx.execute("UPDATE crawl SET total_pages = ?1", [self.written as i64])?;
+        {
+            let mut insert = tx.prepare("INSERT OR REPLACE INTO skipped (url, reason, found_on) VALUES (?1, ?2, ?3)")?;
+            for skip in skipped {
+                insert.execute(params![skip.url, skip.reason, skip.found_on])?;
+            }
+        }
+        tx.commit()?;
+        Ok(self.path.clone())
+    }
+}
+
+/// Whether `path` names either half of a JSONL crawl output.
+pub fn is_jsonl_output(path: &Path) -> bool {
+    let name = file_name(path);
+    name.ends_with(".jsonl") || name.ends_with(".manifest.json")
+}
+
+/// Reads a JSONL crawl output back into a `CrawlOutput`, given either its manifest or its page file.
+pub fn read_jsonl(path: &Path) -> Result<CrawlOutput, Box<dyn std::error::Error>> {
+    let name = file_name(path);
+    let manifest_path = match name.strip_suffix(".jsonl") {
+        Some(stem) => path.with_file_name(format!("{}.manifest.json", stem)),
+        None => path.to_path_buf(),
+    };
+    let manifest: Manifest = serde_json::from_str(&fs::read_to_string(&manifest_path)?)?;
+    let pages_path = manifest_path.with_file_name(&manifest.pages_file);
+
+    let mut pages = Vec::with_capacity(manifest.total_pages);
+    for line in BufReader::new(File::open(&pages_path)?).lines() {
+        let line = line?;
+        if !line.trim().is_empty() {
+            pages.push(serde_json::from_str(&line)?);
+        }
+    }
+
+    Ok(CrawlOutput {
+        domain: manifest.domain,
+        root_url: manifest.root_url,
+        crawl_timestamp: manifest.crawl_timestamp,
+        total_pages: pages.len(),
+        pages,
+        skipped: manifest.skipped,
+    })
+}
+
+/// Reads an SQLite crawl output back into a `CrawlOutput`, from the JSON kept in `pages.data`.
+pub fn read_sqlite(path: &Path) -> Result<CrawlOutput, Box<dyn std::error::Error>> {
+    let conn = Connection::open_with_flags(path, rusqlite::OpenFlags::SQLITE_OPEN_READ_ONLY)?;
+    let (domain, root_url, crawl_timestamp): (String, String, String) = conn.query_row(
+        "SELECT domain, root_url, crawl_timestamp FROM crawl",
+        [],
+        |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)),
+    )?;
+
+    let mut pages = Vec::new();
+    let mut statement = conn.prepare("SELECT data FROM pages ORDER BY depth, url")?;
+    for data in statement.query_map([], |row| row.get::<_, String>(0))? {
+        pages.push(serde_json::from_str(&data?)?);
+    }
+    let skipped = conn
+        .prepare("SELECT url, reason, found_on FROM skipped ORDER BY url")?
+        .query_map([], |row| Ok(SkippedUrl { url: row.get(0)?, reason: row.get(1)?, found_on: row.get(2)? }))?
+        .collect::<Result<Vec<_>, _>>()?;
+
+    Ok(CrawlOutput {
+        domain,
+        root_url,
+        crawl_timestamp: DateTime::parse_from_rfc3339(&crawl_timestamp)?.with_timezone(&Utc),
+        total_pages: pages.len(),
+        pages,
+        skipped,
+    })
+}
+
+/// Writes a complete `CrawlOutput` through the sink for `format`, for `export`.
+pub fn write_output(output: &CrawlOutput, path: &Path, format: OutputFormat) -> Result<(), Box<dyn std::error::Error>> {
+    let mut sink = format.sink(path.to_path_buf());
+    sink.open(&CrawlInfo {
+        domain: output.domain.clone(),
+        root_url: output.root_url.clone(),
+        crawl_timestamp: output.crawl_timestamp,
+    })?;
+    for page in &output.pages {
+        sink.write_page(page)?;
+    }
+    sink.finish(&output.skipped)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::failed_page;
+
+    #[test]
+    fn json_streams_a_complete_crawl_output() {
+        let dir = std::env::temp_dir().join(format!("output-test-{}", std::process::id()));
+        let crawl = CrawlInfo {
+            domain: "example.org".to_string(),
+            root_url: "https://example.org/".to_string(),
+            crawl_timestamp: Utc::now(),
+        };
+        let pages = [failed_page("https://example.org/b", 1, "timed out"), failed_page("https://example.org/a\n", 0, "HTTP 500")];
+        let skipped = [SkippedUrl { url: "https://example.org/private".to_string(), reason: "robots.txt".to_string(), found_on: None }];
+        for pretty in [true, false] {
+            for count in [0, 2] {
+                let path = dir.join(format!("{}-{}.json", pretty, count));
+                let mut sink = JsonSink::new(path.clone(), pretty);
+                sink.open(&crawl).unwrap();
+                for page in &pages[..count] {
+                    sink.write_page(page).unwrap();
+                }
+                sink.finish(&skipped).unwrap();
+
+                let text = fs::read_to_string(&path).unwrap();
+                let expected = CrawlOutput {
+                    domain: crawl.domain.clone(),
+                    root_url: crawl.root_url.clone(),
+                    crawl_timestamp: crawl.crawl_timestamp,
+                    total_pages: count,
+                    pages: pages[..count].to_vec(),
+                    skipped: skipped.to_vec(),
+                };
+                let written: serde_json::Value = serde_json::from_str(&text).unwrap();
+                assert_eq!(written, serde_json::to_value(&expected).unwrap());
+                if pretty && count > 0 {
+                    assert!(text.contains("\n  \"pages\": [\n    {\n      \"url\": \"https://example.org/b\""), "{}", text);
+                } else if !pretty {
+                    assert!(!text.contains('\n'));
+                }
+            }
+        }
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,117 @@
+use chrono::{DateTime, Utc};
+use reqwest::header::{HeaderMap, RETRY_AFTER};
+use std::collections::HashMap;
+use std::sync::{Condvar, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+struct HostState {
+    /// Earliest time the next request to this host may start.
+    next_slot: Instant,
+    active: usize,
+}
+
+/// Spaces requests to each host at least `min_interval` apart (or the
+/// host's robots.txt `Crawl-delay`, if longer) and caps how many of them may
+/// be open at once. Fetch workers call `acquire` before every request and
+/// hold the returned permit until the response body has been read.
+pub struct HostScheduler {
+    min_interval: Duration,
+    max_connections: usize,
+    hosts: Mutex<HashMap<String, HostState>>,
+    freed: Condvar,
+}
+
+pub struct HostPermit<'a> {
+    scheduler: &'a HostScheduler,
+    host: String,
+}
+
+impl HostScheduler {
+    pub fn new(min_interval: Duration, max_connections: usize) -> Self {
+        HostScheduler {
+            min_interval,
+            max_connections,
+            hosts: Mutex::new(HashMap::new()),
+            freed: Condvar::new(),
+        }
+    }
+
+    /// Blocks until a request to `host` may start.
+    pub fn acquire(&self, host: &str, crawl_delay: Option<Duration>) -> HostPermit<'_> {
+        let interval = self.min_interval.max(crawl_delay.unwrap_or_default());
+        let mut hosts = self.hosts.lock().unwrap();
+        loop {
+            let now = Instant::now();
+            let state = hosts
+                .entry(host.to_string())
+                .or_insert(HostState { next_slot: now, active: 0 });
+            if state.active < self.max_connections {
+                let slot = state.next_slot.max(now);
+                state.next_slot = slot + interval;
+                state.active += 1;
+                drop(hosts);
+                thread::sleep(slot - now);
+                return HostPermit { scheduler: self, host: host.to_string() };
+            }
+            hosts = self.freed.wait(hosts).unwrap();
+        }
+    }
+
+    /// Holds off every request to `host` for at least `delay`, e.g. after a 429 or 503.
+    pub fn back_off(&self, host: &str, delay: Duration) {
+        let mut hosts = self.hosts.lock().unwrap();
+        let until = Instant::now() + delay;
+        if let Some(state) = hosts.get_mut(host) {
+            state.next_slot = state.next_slot.max(until);
+        }
+    }
+}
+
+impl Drop for HostPermit<'_> {
+    fn drop(&mut self) {
+        let mut hosts = self.scheduler.hosts.lock().unwrap();
+        if let Some(state) = hosts.get_mut(&self.host) {
+            state.active -= 1;
+        }
+        self.scheduler.freed.notify_all();
+    }
+}
+
+/// Reads a `Retry-After` header given either as seconds or as an HTTP date.
+pub fn retry_after(headers: &HeaderMap) -> Option<Duration> {
+    let value = headers.get(RETRY_AFTER)?.to_str().ok()?.trim();
+    if let Ok(secs) = value.parse::<u64>() {
+        return Some(Duration::from_secs(secs));
+    }
+    let date = DateTime::parse_from_rfc2822(value).ok()?.with_timezone(&Utc);
+    Some((date - Utc::now()).to_std().unwrap_or_default())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use reqwest::header::HeaderValue;
+
+    fn retry_after_value(value: &str) -> Option<Duration> {
+        let mut headers = HeaderMap::new();
+        headers.insert(RETRY_AFTER, HeaderValue::from_str(value).unwrap());
+        retry_after(&headers)
+    }
+
+    #[test]
+    fn retry_after_takes_seconds_or_a_date() {
+        assert_eq!(retry_after(&HeaderMap::new()), None);
+        assert_eq!(retry_after_value("120"), Some(Duration::from_secs(120)));
+        assert_eq!(retry_after_value(" 0 "), Some(Duration::ZERO));
+
+        let later = (Utc::now() + chrono::Duration::seconds(90)).format("%a, %d %b %Y %H:%M:%S GMT").to_string();
+        let wait = retry_after_value(&later).unwrap();
+        assert!(wait > Duration::from_secs(80) && wait <= Duration::from_secs(90), "{:?}", wait);
+        // A date already gone means no wait at all.
+        assert_eq!(retry_after_value("Sun, 06 Nov 1994 08:49:37 GMT"), Some(Duration::ZERO));
+
+        assert_eq!(retry_after_value("soon"), None);
+        assert_eq!(retry_after_value("-5"), None);
+    }
+}
@@ -0,0 +1,300 @@
+use ego_tree::NodeId;
+use regex::Regex;
+use scraper::{Element, ElementRef, Html, Selector};
+use std::collections::HashMap;
+
+/// Elements whose text is scored and credited to their ancestors.
+const SCORED_TAGS: &[&str] = &["p", "pre", "td", "blockquote"];
+
+/// A `div` containing any of these is a container rather than a paragraph.
+const BLOCK_TAGS: &[&str] = &[
+    "address", "article", "aside", "blockquote", "div", "dl", "fieldset", "figure", "footer", "form", "h1", "h2",
+    "h3", "h4", "h5", "h6", "header", "hr", "main", "nav", "ol", "p", "pre", "section", "table", "ul",
+];
+
+/// How many ancestors of a scored paragraph share in its score.
+const ANCESTOR_LEVELS: usize = 5;
+
+/// Paragraphs shorter than this (in characters) are ignored.
+const MIN_PARAGRAPH_LEN: usize = 25;
+
+/// Picks the element holding a page's main content by scoring candidates the
+/// way Mozilla's Readability does: each paragraph's text length and comma
+/// count is credited to its nearest ancestors, ancestors are weighted by tag
+/// and by class/id names, and the totals are discounted by link density.
+pub struct ContentScorer {
+    positive: Regex,
+    negative: Regex,
+}
+
+/// The chosen element and what led to it.
+pub struct MainContent<'a> {
+    pub element: ElementRef<'a>,
+    /// A CSS selector path from `html` down to the element, for debugging extraction.
+    pub selector_path: String,
+}
+
+impl ContentScorer {
+    pub fn new() -> Self {
+        ContentScorer {
+            positive: Regex::new(r"(?i)article|body|content|entry|hentry|h-entry|main|page|post|text|blog|story").unwrap(),
+            negative: Regex::new(
+                r"(?i)-ad-|hidden|^hid$|banner|combx|comment|com-|contact|cookie|foot|gdpr|masthead|media|meta|modal|outbrain|promo|related|scroll|share|shoutbox|sidebar|skyscraper|sponsor|shopping|tags|tool|widget",
+            )
+            .unwrap(),
+        }
+    }
+
+    /// Finds the main content. Elements matching `hints` get a bonus as
+    /// candidates, and a hinted ancestor of the winner is taken instead when
+    /// the winner already holds most of its text, so a `<main>` wrapper keeps
+    /// its title. `skippable` marks boilerplate that neither scores nor counts
+    /// towards text length. Returns `None` when no paragraph scores at all.
+    pub fn find<'a>(
+        &self,
+        document: &'a Html,
+        hints: &[Selector],
+        skippable: &dyn Fn(ElementRef) -> bool,
+    ) -> Option<MainContent<'a>> {
+        let mut scores: HashMap<NodeId, f64> = HashMap::new();
+        let mut candidates: Vec<ElementRef<'a>> = Vec::new();
+
+        let all = Selector::parse("*").unwrap();
+        for element in document.select(&all) {
+            if !self.is_paragraph(element) || skippable(element) {
+                continue;
+            }
+            let text = collapsed_text(element);
+            let length = text.chars().count();
+            if length < MIN_PARAGRAPH_LEN {
+                continue;
+            }
+            let score = 1.0 + text.matches(',').count() as f64 + (length as f64 / 100.0).floor().min(3.0);
+
+            let mut ancestor = element.parent_element();
+            for level in 0..ANCESTOR_LEVELS {
+                let Some(current) = ancestor else { break };
+                if current.value().name() == "html" {
+                    break;
+                }
+                let entry = scores.entry(current.id()).or_insert_with(|| {
+                    candidates.push(current);
+                    self.initial_score(current, hints)
+                });
+                let divider = match level {
+                    0 => 1.0,
+                    1 => 2.0,
+                    _ => level as f64 * 3.0,
+                };
+                *entry += score / divider;
+                ancestor = current.parent_element();
+            }
+        }
+
+        for candidate in &candidates {
+            if let Some(score) = scores.get_mut(&candidate.id()) {
+                *score *= 1.0 - link_density(*candidate);
+            }
+        }
+        let score_of = |element: ElementRef| scores.get(&element.id()).copied();
+        let mut top = *candidates
+            .iter()
+            .max_by(|a, b| score_of(**a).unwrap_or(0.0).total_cmp(&score_of(**b).unwrap_or(0.0)))?;
+
+        // Climb while the parent scores better, as when content is split across sibling blocks.
+        let mut last_score = score_of(top).unwrap_or(0.0);
+        let threshold = last_score / 3.0;
+        let mut parent = top.parent_element();
+        while let Some(current) = parent.filter(|p| p.value().name() != "body" && p.value().name() != "html") {
+            if let Some(parent_score) = score_of(current) {
+                if parent_score < threshold {
+                    break;
+                }
+                if parent_score > last_score {
+                    top = current;
+                    break;
+                }
+                last_score = parent_score;
+            }
+            parent = current.parent_element();
+        }
+        // A lone child is no better than its parent.
+        while let Some(parent) = top.parent_element().filter(|p| p.value().name() != "body" && p.value().name() != "html") {
+            if parent.children().filter_map(ElementRef::wrap).count() != 1 {
+                break;
+            }
+            top = parent;
+        }
+
+        let top_length = text_length(top, skippable);
+        let mut ancestor = top.parent_element();
+        while let Some(current) = ancestor.filter(|a| a.value().name() != "html") {
+            if hints.iter().any(|hint| hint.matches(&current)) {
+                if top_length * 2 >= text_length(current, skippable) {
+                    top = current;
+                }
+                break;
+            }
+            ancestor = current.parent_element();
+        }
+
+        Some(MainContent { element: top, selector_path: selector_path(top) })
+    }
+
+    fn is_paragraph(&self, element: ElementRef) -> bool {
+        let name = element.value().name();
+        if SCORED_TAGS.contains(&name) {
+            return true;
+        }
+        name == "div" && !element.children().filter_map(ElementRef::wrap).any(|child| BLOCK_TAGS.contains(&child.value().name()))
+    }
+
+    fn initial_score(&self, element: ElementRef, hints: &[Selector]) -> f64 {
+        let tag = match element.value().name() {
+            "div" | "article" | "main" | "section" => 5.0,
+            "pre" | "td" | "blockquote" => 3.0,
+            "address" | "ol" | "ul" | "dl" | "dd" | "dt" | "li" | "form" => -3.0,
+            "h1" | "h2" | "h3" | "h4" | "h5" | "h6" | "th" => -5.0,
+            _ => 0.0,
+        };
+        let hint = if hints.iter().any(|hint| hint.matches(&element)) { 25.0 } else { 0.0 };
+        tag + self.class_weight(element) + hint
+    }
+
+    fn class_weight(&self, element: ElementRef) -> f64 {
+        let mut weight = 0.0;
+        let class = element.value().attr("class").unwrap_or("");
+        let id = element.value().id().unwrap_or("");
+        for name in [class, id].into_iter().filter(|name| !name.is_empty()) {
+            if self.negative.is_match(name) {
+                weight -= 25.0;
+            }
+            if self.positive.is_match(name) {
+                weight += 25.0;
+            }
+        }
+        weight
+    }
+}
+
+fn collapsed_text(element: ElementRef) -> String {
+    element.text().flat_map(str::split_whitespace).collect::<Vec<_>>().join(" ")
+}
+
+/// Characters of text under `element`, leaving out skippable subtrees.
+fn text_length(element: ElementRef, skippable: &dyn Fn(ElementRef) -> bool) -> usize {
+    element
+        .children()
+        .map(|child| match ElementRef::wrap(child) {
+            Some(child) if skippable(child) => 0,
+            Some(child) => text_length(child, skippable),
+            None => child.value().as_text().map_or(0, |text| text.trim().chars().count()),
+        })
+        .sum()
+}
+
+/// Share of the element's text that sits inside links.
+fn link_density(element: ElementRef) -> f64 {
+    let total: usize = element.text().map(|t| t.trim().chars().count()).sum();
+    if total == 0 {
+        return 0.0;
+    }
+    let links = Selector::parse("a").unwrap();
+    let linked: usize = element
+        .select(&links)
+        .flat_map(|a| a.text())
+        .map(|t| t.trim().chars().count())
+        .sum();
+    (linked as f64 / total as f64).min(1.0)
+}
+
+/// `html > body > div#page > main.content:nth-of-type(2)`-style path to `element`.
+pub fn selector_path(element: ElementRef) -> String {
+    let mut parts = Vec::new();
+    let mut current = Some(element);
+    while let Some(el) = current {
+        let name = el.value().name();
+        let mut part = name.to_string();
+        if let Some(id) = el.value().id().filter(|id| !id.contains(char::is_whitespace)) {
+            part.push('#');
+            part.push_str(id);
+        } else {
+            for class in el.value().classes().take(2) {
+                part.push('.');
+                part.push_str(class);
+            }
+            if let Some(parent) = el.parent_element() {
+                let same_tag: Vec<_> = parent.children().filter_map(ElementRef::wrap).filter(|sibling| sibling.value().name() == name).collect();
+                if same_tag.len() > 1 {
+                    let position = same_tag.iter().position(|sibling| sibling.id() == el.id()).unwrap_or(0) + 1;
+                    part.push_str(&format!(":nth-of-type({})", position));
+                }
+            }
+        }
+        parts.push(part);
+        current = el.parent_element();
+    }
+    parts.reverse();
+    parts.join(" > ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PARAGRAPH: &str = "Open days are the best way to see the campus, meet our academics, talk to current students, \
+        and find out what studying here is really like, from the teaching to the accommodation and the sports facilities.";
+
+    fn paragraphs(count: usize) -> String {
+        format!("<p>{}</p>", PARAGRAPH).repeat(count)
+    }
+
+    fn hints(selectors: &[&str]) -> Vec<Selector> {
+        selectors.iter().map(|s| Selector::parse(s).unwrap()).collect()
+    }
+
+    fn main_path(html: &str, hint_selectors: &[&str]) -> String {
+        let document = Html::parse_document(html);
+        let main = ContentScorer::new().find(&document, &hints(hint_selectors), &|_| false).unwrap();
+        main.selector_path
+    }
+
+    #[test]
+    fn small_content_sidebar_loses_to_article() {
+        let html = format!(
+            r#"<body><div class="sidebar"><div class="content"><p>Sign up for our newsletter, it is free.</p></div></div>
+            <article>{}</article></body>"#,
+            paragraphs(8)
+        );
+        assert_eq!(main_path(&html, &[".content", "article"]), "html > body > article");
+        // Taking the first hint outright would have picked the widget.
+        assert_eq!(main_path(&html, &[".content"]), "html > body > article");
+    }
+
+    #[test]
+    fn link_dense_navigation_is_penalised() {
+        let links = format!(r#"<p><a href="/a">{}</a></p>"#, PARAGRAPH).repeat(6);
+        let html = format!(r#"<body><div id="menu">{}</div><div id="story">{}</div></body>"#, links, paragraphs(3));
+        assert_eq!(main_path(&html, &[]), "html > body > div#story");
+    }
+
+    #[test]
+    fn selector_path_records_ids_classes_and_position() {
+        let html = format!(
+            r#"<body><div id="page"><section class="intro">{}</section><section class="body text wide">{}</section></div></body>"#,
+            paragraphs(1),
+            paragraphs(6)
+        );
+        assert_eq!(main_path(&html, &[]), "html > body > div#page > section.body.text:nth-of-type(2)");
+    }
+
+    #[test]
+    fn hints_break_ties_and_keep_wrappers() {
+        let html = format!(r#"<body><div class="one">{0}</div><div class="two">{0}</div></body>"#, paragraphs(3));
+        assert_eq!(main_path(&html, &[".two"]), "html > body > div.two:nth-of-type(2)");
+
+        // A hinted wrapper whose text is mostly the winner's is taken instead, keeping its title.
+        let html = format!(r#"<body><main><h1>Open days</h1><div><div>{}</div><aside>Share</aside></div></main></body>"#, paragraphs(4));
+        assert_eq!(main_path(&html, &["main"]), "html > body > main");
+    }
+}
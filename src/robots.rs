@@ -0,0 +1,366 @@
+use crate::politeness::HostScheduler;
+use reqwest::header::USER_AGENT;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use url::Url;
+
+/// One `Allow`/`Disallow` line.
+#[derive(Debug)]
+struct Rule {
+    allow: bool,
+    pattern: String,
+}
+
+#[derive(Debug, Default)]
+struct Group {
+    agents: Vec<String>,
+    rules: Vec<Rule>,
+    crawl_delay: Option<f64>,
+}
+
+/// A parsed robots.txt, reduced to the rules that apply to one user-agent token.
+#[derive(Debug, Default)]
+pub struct RobotsTxt {
+    rules: Vec<Rule>,
+    crawl_delay: Option<Duration>,
+    sitemaps: Vec<String>,
+    disallow_all: bool,
+}
+
+impl RobotsTxt {
+    /// Parses `body` and keeps the group for `agent`: the group(s) naming the
+    /// longest user-agent that prefixes `agent`, or `*` when none does.
+    pub fn parse(body: &str, agent: &str) -> Self {
+        let mut groups: Vec<Group> = Vec::new();
+        let mut sitemaps = Vec::new();
+        let mut in_agent_lines = false;
+
+        for line in body.lines() {
+            let line = line.split('#').next().unwrap_or("").trim();
+            let Some((key, value)) = line.split_once(':') else { continue };
+            let key = key.trim().to_lowercase();
+            let value = value.trim();
+
+            match key.as_str() {
+                "user-agent" => {
+                    if !in_agent_lines {
+                        groups.push(Group::default());
+                        in_agent_lines = true;
+                    }
+                    if let Some(group) = groups.last_mut() {
+                        group.agents.push(value.to_lowercase());
+                    }
+                }
+                "allow" | "disallow" => {
+                    in_agent_lines = false;
+                    // An empty Disallow means "allow everything" and adds no rule.
+                    if value.is_empty() {
+                        continue;
+                    }
+                    if let Some(group) = groups.last_mut() {
+                        group.rules.push(Rule { allow: key == "allow", pattern: value.to_string() });
+                    }
+                }
+                "crawl-delay" => {
+                    in_agent_lines = false;
+                    if let (Some(group), Ok(delay)) = (groups.last_mut(), value.parse::<f64>()) {
+                        if delay.is_finite() && delay >= 0.0 {
+                            group.crawl_delay = Some(delay);
+                        }
+                    }
+                }
+                // Sitemap lines are not tied to any group.
+                "sitemap" => sitemaps.push(value.to_string()),
+                _ => {}
+            }
+        }
+
+        let agent = agent.to_lowercase();
+        let best = groups
+            .iter()
+            .flat_map(|g| g.agents.iter())
+            .filter(|a| a.as_str() != "*" && agent.starts_with(a.as_str()))
+            .max_by_key(|a| a.len())
+            .cloned()
+            .unwrap_or_else(|| "*".to_string());
+
+        let mut robots = RobotsTxt { sitemaps, ..Default::default() };
+        for group in groups.into_iter().filter(|g| g.agents.contains(&best)) {
+            robots.rules.extend(group.rules);
+            if let Some(delay) = group.crawl_delay {
+                robots.crawl_delay = Some(Duration::from_secs_f64(delay));
+            }
+        }
+        robots
+    }
+
+    /// A robots.txt that could not be fetched because the server failed; per RFC 9309 nothing may be crawled.
+    pub fn unreachable() -> Self {
+        RobotsTxt { disallow_all: true, ..Default::default() }
+    }
+
+    /// Whether this stands for a robots.txt that could not be fetched.
+    pub fn is_unreachable(&self) -> bool {
+        self.disallow_all
+    }
+
+    /// Explains why `url` may not be fetched, or returns `None` if it may.
+    /// The longest matching pattern wins and `Allow` wins a tie.
+    pub fn disallow_reason(&self, url: &Url) -> Option<String> {
+        if self.disallow_all {
+            return Some("robots.txt could not be fetched".to_string());
+        }
+        if url.path() == "/robots.txt" {
+            return None;
+        }
+        let mut path = url.path().to_string();
+        if let Some(query) = url.query() {
+            path.push('?');
+            path.push_str(query);
+        }
+
+        self.rules
+            .iter()
+            .filter(|rule| pattern_matches(&rule.pattern, &path))
+            .max_by_key(|rule| (rule.pattern.len(), rule.allow))
+            .filter(|rule| !rule.allow)
+            .map(|rule| format!("disallowed by robots.txt (Disallow: {})", rule.pattern))
+    }
+
+    pub fn crawl_delay(&self) -> Option<Duration> {
+        self.crawl_delay
+    }
+
+    pub fn sitemaps(&self) -> &[String] {
+        &self.sitemaps
+    }
+}
+
+/// Matches a robots.txt path pattern, where `*` stands for any run of
+/// characters and a trailing `$` anchors the pattern to the end of the path.
+/// Without `$` the pattern only has to match a prefix.
+fn pattern_matches(pattern: &str, path: &str) -> bool {
+    let (pattern, anchored) = match pattern.strip_suffix('$') {
+        Some(p) => (p, true),
+        None => (pattern, false),
+    };
+    let pattern = pattern.as_bytes();
+    let path = path.as_bytes();
+
+    // positions[i] is true when the pattern consumed so far can end at path[..i].
+    let mut positions = vec![false; path.len() + 1];
+    positions[0] = true;
+    for &p in pattern {
+        if p == b'*' {
+            for i in 1..=path.len() {
+                positions[i] |= positions[i - 1];
+            }
+        } else {
+            for i in (1..=path.len()).rev() {
+                positions[i] = positions[i - 1] && path[i - 1] == p;
+            }
+            positions[0] = false;
+        }
+    }
+
+    if anchored {
+        positions[path.len()]
+    } else {
+        positions.iter().any(|&matched| matched)
+    }
+}
+
+/// Fetches robots.txt once per origin and keeps the parsed result for the
+/// rest of the crawl. A robots.txt that could not be reached is only kept
+/// for `retry_unreachable`, so one network error doesn't close a host for good.
+pub struct RobotsCache {
+    agent: String,
+    retry_unreachable: Duration,
+    by_origin: Mutex<HashMap<String, CachedRobots>>,
+}
+
+struct CachedRobots {
+    robots: Arc<RobotsTxt>,
+    /// When an unreachable robots.txt should be asked for again.
+    retry_at: Option<Instant>,
+}
+
+impl RobotsCache {
+    pub fn new(agent: &str, retry_unreachable: Duration) -> Self {
+        RobotsCache {
+            agent: agent.to_string(),
+            retry_unreachable,
+            by_origin: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// The robots.txt for `url`'s origin if it is cached and not due for a
+    /// retry. Never touches the network.
+    pub fn cached(&self, url: &Url) -> Option<Arc<RobotsTxt>> {
+        let by_origin = self.by_origin.lock().unwrap();
+        let cached = by_origin.get(&url.origin().ascii_serialization())?;
+        cached.retry_at.is_none_or(|retry_at| Instant::now() < retry_at).then(|| cached.robots.clone())
+    }
+
+    /// The robots.txt for `url`'s origin. Fetching it takes a politeness
+    /// slot for the host like any other request.
+    pub fn get(&self, client: &reqwest::blocking::Client, user_agent: &str, scheduler: &HostScheduler, url: &Url) -> Arc<RobotsTxt> {
+        let origin = url.origin().ascii_serialization();
+        if let Some(cached) = self.by_origin.lock().unwrap().get(&origin) {
+            if cached.retry_at.is_none_or(|retry_at| Instant::now() < retry_at) {
+                return cached.robots.clone();
+            }
+        }
+
+        let robots = {
+            let _permit = scheduler.acquire(url.host_str().unwrap_or(""), None);
+            Arc::new(self.fetch(client, user_agent, &origin))
+        };
+        let retry_at = robots.disallow_all.then(|| Instant::now() + self.retry_unreachable);
+        let mut by_origin = self.by_origin.lock().unwrap();
+        // Another worker may have fetched it meanwhile; a reachable copy is kept over ours.
+        match by_origin.get(&origin) {
+            Some(cached) if cached.retry_at.is_none() => cached.robots.clone(),
+            _ => {
+                by_origin.insert(origin, CachedRobots { robots: robots.clone(), retry_at });
+                robots
+            }
+        }
+    }
+
+    fn fetch(&self, client: &reqwest::blocking::Client, user_agent: &str, origin: &str) -> RobotsTxt {
+        let robots_url = format!("{}/robots.txt", origin);
+        let robots = match client.get(&robots_url).header(USER_AGENT, user_agent).send() {
+            Ok(response) if response.status().is_success() => match response.text() {
+                Ok(body) => RobotsTxt::parse(&body, &self.agent),
+                Err(_) => RobotsTxt::unreachable(),
+            },
+            // A missing robots.txt (or any other 4xx) means there are no restrictions.
+            Ok(response) if response.status().is_client_error() => RobotsTxt::default(),
+            Ok(_) | Err(_) => RobotsTxt::unreachable(),
+        };
+
+        if robots.disallow_all {
+            println!("Could not load {}, retrying in {}s", robots_url, self.retry_unreachable.as_secs());
+        } else {
+            match robots.crawl_delay() {
+                Some(delay) => println!("Loaded {} (crawl-delay: {:.1}s)", robots_url, delay.as_secs_f64()),
+                None => println!("Loaded {}", robots_url),
+            }
+        }
+        robots
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{Read, Write};
+    use std::net::TcpListener;
+
+    fn allowed(robots: &RobotsTxt, path: &str) -> bool {
+        robots.disallow_reason(&Url::parse("https://example.org").unwrap().join(path).unwrap()).is_none()
+    }
+
+    #[test]
+    fn wildcards_and_end_anchors() {
+        assert!(pattern_matches("/private", "/private/a.html"));
+        assert!(!pattern_matches("/private", "/public/private"));
+        assert!(pattern_matches("/*.pdf", "/docs/a.pdf"));
+        assert!(pattern_matches("/*.pdf", "/docs/a.pdf?download=1"));
+        assert!(pattern_matches("/*.pdf$", "/docs/a.pdf"));
+        assert!(!pattern_matches("/*.pdf$", "/docs/a.pdf?download=1"));
+        assert!(pattern_matches("/a*b*c", "/a-x-b-y-c-z"));
+        assert!(!pattern_matches("/a*b*c", "/a-x-c-y-b"));
+        assert!(pattern_matches("/$", "/"));
+        assert!(!pattern_matches("/$", "/index.html"));
+    }
+
+    #[test]
+    fn longest_match_wins_and_allow_wins_ties() {
+        let robots = RobotsTxt::parse(
+            "User-agent: *\nDisallow: /news\nAllow: /news/public\nDisallow: /news/public/drafts\nAllow: /same\nDisallow: /same\n",
+            "RustCrawler",
+        );
+        assert!(!allowed(&robots, "/news/2024"));
+        assert!(allowed(&robots, "/news/public/story"));
+        assert!(!allowed(&robots, "/news/public/drafts/1"));
+        assert!(allowed(&robots, "/same/page"));
+        assert!(allowed(&robots, "/elsewhere"));
+        assert!(allowed(&robots, "/robots.txt"));
+    }
+
+    #[test]
+    fn the_most_specific_user_agent_group_applies() {
+        let body = "User-agent: *\nDisallow: /\n\nUser-agent: Rust\nDisallow: /rust-only\n\n\
+                    User-agent: RustCrawler\nUser-agent: other\nDisallow: /crawler-only\nCrawl-delay: 2\n\n\
+                    User-agent: rustcrawler\nDisallow: /also\n\nSitemap: https://example.org/sitemap.xml\n";
+
+        let robots = RobotsTxt::parse(body, "RustCrawler");
+        assert!(allowed(&robots, "/rust-only"));
+        assert!(!allowed(&robots, "/crawler-only"));
+        // Groups naming the same agent are merged, whatever the case.
+        assert!(!allowed(&robots, "/also"));
+        assert_eq!(robots.crawl_delay(), Some(Duration::from_secs(2)));
+        assert_eq!(robots.sitemaps(), ["https://example.org/sitemap.xml"]);
+
+        let robots = RobotsTxt::parse(body, "RustBot");
+        assert!(!allowed(&robots, "/rust-only"));
+        assert!(allowed(&robots, "/crawler-only"));
+
+        let robots = RobotsTxt::parse(body, "Googlebot");
+        assert!(!allowed(&robots, "/anything"));
+        assert_eq!(robots.crawl_delay(), None);
+    }
+
+    /// Serves one canned response per connection, in order, and returns the origin.
+    fn serve(responses: Vec<&'static str>) -> Url {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let origin = Url::parse(&format!("http://{}/", listener.local_addr().unwrap())).unwrap();
+        std::thread::spawn(move || {
+            for (response, stream) in responses.into_iter().zip(listener.incoming()) {
+                let mut stream = stream.unwrap();
+                let mut request = [0; 4096];
+                let _ = stream.read(&mut request);
+                let _ = stream.write_all(response.as_bytes());
+            }
+        });
+        origin
+    }
+
+    #[test]
+    fn server_errors_disallow_everything_until_the_retry() {
+        let origin = serve(vec![
+            "HTTP/1.1 503 Service Unavailable\r\nContent-Length: 0\r\nConnection: close\r\n\r\n",
+            "HTTP/1.1 200 OK\r\nContent-Length: 27\r\nConnection: close\r\n\r\nUser-agent: *\nDisallow: /x\n",
+        ]);
+        let client = reqwest::blocking::Client::new();
+        let scheduler = HostScheduler::new(Duration::ZERO, 1);
+        let page = origin.join("/page").unwrap();
+
+        let cache = RobotsCache::new("RustCrawler", Duration::from_millis(200));
+        assert!(cache.cached(&page).is_none());
+        let robots = cache.get(&client, "RustCrawler", &scheduler, &page);
+        assert!(robots.is_unreachable());
+        assert_eq!(robots.disallow_reason(&page).as_deref(), Some("robots.txt could not be fetched"));
+        // Still cached: the server would answer 200 now.
+        assert!(cache.get(&client, "RustCrawler", &scheduler, &page).disallow_reason(&page).is_some());
+        assert!(cache.cached(&page).is_some_and(|robots| robots.is_unreachable()));
+
+        std::thread::sleep(Duration::from_millis(250));
+        // Due for a retry, so it no longer counts as known.
+        assert!(cache.cached(&page).is_none());
+        let robots = cache.get(&client, "RustCrawler", &scheduler, &page);
+        assert_eq!(robots.disallow_reason(&page), None);
+        assert!(robots.disallow_reason(&origin.join("/x").unwrap()).is_some());
+    }
+
+    #[test]
+    fn a_missing_robots_txt_allows_everything() {
+        let origin = serve(vec!["HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\nConnection: close\r\n\r\n"]);
+        let cache = RobotsCache::new("RustCrawler", Duration::from_secs(60));
+        let robots = cache.get(&reqwest::blocking::Client::new(), "RustCrawler", &HostScheduler::new(Duration::ZERO, 1), &origin);
+        assert_eq!(robots.disallow_reason(&origin.join("/anything").unwrap()), None);
+    }
+}
@@ -0,0 +1,88 @@
+use std::collections::HashSet;
+use unicode_segmentation::UnicodeSegmentation;
+
+/// Splits text into sentences at Unicode (UAX #29) sentence boundaries,
+/// then rejoins those that only break after an abbreviation such as "Dr."
+/// or an initial such as "J.", which UAX #29 cannot tell apart from a full stop.
+pub struct SentenceSegmenter {
+    /// Lower case, without the final full stop.
+    abbreviations: HashSet<String>,
+}
+
+impl SentenceSegmenter {
+    pub fn new(abbreviations: &[String]) -> Self {
+        SentenceSegmenter {
+            abbreviations: abbreviations
+                .iter()
+                .map(|abbreviation| abbreviation.trim().trim_end_matches('.').to_lowercase())
+                .filter(|abbreviation| !abbreviation.is_empty())
+                .collect(),
+        }
+    }
+
+    /// Byte ranges of the sentences of `text`. Together they cover all of
+    /// it; each includes the whitespace after it.
+    pub fn split(&self, text: &str) -> Vec<(usize, usize)> {
+        let mut sentences: Vec<(usize, usize)> = Vec::new();
+        let mut joins_next = false;
+        for (start, sentence) in text.split_sentence_bound_indices() {
+            let end = start + sentence.len();
+            match sentences.last_mut() {
+                Some(last) if joins_next => last.1 = end,
+                _ => sentences.push((start, end)),
+            }
+            joins_next = self.ends_in_abbreviation(sentence);
+        }
+        sentences
+    }
+
+    fn ends_in_abbreviation(&self, sentence: &str) -> bool {
+        let Some(word) = sentence.split_whitespace().next_back() else { return false };
+        let Some(word) = word.strip_suffix('.') else { return false };
+        let word = word.trim_start_matches(|c: char| !c.is_alphanumeric());
+        let is_initial = word.chars().count() == 1 && word.chars().all(char::is_alphabetic);
+        is_initial || self.abbreviations.contains(&word.to_lowercase())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::ChunkingConfig;
+
+    fn sentences(text: &str) -> Vec<&str> {
+        let segmenter = SentenceSegmenter::new(&ChunkingConfig::default().abbreviations);
+        segmenter.split(text).into_iter().map(|(start, end)| &text[start..end]).collect()
+    }
+
+    #[test]
+    fn abbreviations_do_not_end_sentences() {
+        assert_eq!(sentences("Ask Dr. Smith about it. She knows."), ["Ask Dr. Smith about it. ", "She knows."]);
+        assert_eq!(
+            sentences("Bring something to read, e.g. A novel. Then relax."),
+            ["Bring something to read, e.g. A novel. ", "Then relax."]
+        );
+        // Case and a leading bracket don't matter.
+        assert_eq!(sentences("Many (E.g. Cardiff) said yes. Others no."), ["Many (E.g. Cardiff) said yes. ", "Others no."]);
+    }
+
+    #[test]
+    fn initials_do_not_end_sentences() {
+        assert_eq!(sentences("Written by Mr. J. Bloggs. Out now."), ["Written by Mr. J. Bloggs. ", "Out now."]);
+        assert_eq!(sentences("See J. R. R. Tolkien. He wrote it."), ["See J. R. R. Tolkien. ", "He wrote it."]);
+    }
+
+    #[test]
+    fn other_full_stops_still_end_sentences() {
+        assert_eq!(sentences("It rained. Dr. Who came. Then 3. Fine."), ["It rained. ", "Dr. Who came. ", "Then 3. ", "Fine."]);
+        assert_eq!(sentences("Is it? Yes! 好。再见。"), ["Is it? ", "Yes! ", "好。", "再见。"]);
+    }
+
+    #[test]
+    fn custom_abbreviations_are_normalised() {
+        let segmenter = SentenceSegmenter::new(&[" Approx. ".to_string(), String::new()]);
+        let text = "It costs approx. Ten pounds. Or more.";
+        let split: Vec<&str> = segmenter.split(text).into_iter().map(|(start, end)| &text[start..end]).collect();
+        assert_eq!(split, ["It costs approx. Ten pounds. ", "Or more."]);
+    }
+}
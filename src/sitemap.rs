@@ -0,0 +1,73 @@
+use flate2::read::GzDecoder;
+use serde::{Deserialize, Serialize};
+use std::io::Read;
+
+/// One `<url>` of a urlset sitemap.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SitemapEntry {
+    pub loc: String,
+    pub lastmod: Option<String>,
+    pub priority: Option<f32>,
+}
+
+#[derive(Debug)]
+pub enum Sitemap {
+    UrlSet(Vec<SitemapEntry>),
+    /// A sitemap index, listing the locations of further sitemaps.
+    Index(Vec<String>),
+}
+
+/// Parses a sitemap body. Gzipped bodies are detected by their magic bytes
+/// rather than the URL, and a body that is not XML is read as the plain-text
+/// format of one URL per line.
+pub fn parse(body: &[u8]) -> Result<Sitemap, Box<dyn std::error::Error>> {
+    let mut text = String::new();
+    if body.starts_with(&[0x1f, 0x8b]) {
+        GzDecoder::new(body).read_to_string(&mut text)?;
+    } else {
+        text = String::from_utf8_lossy(body).into_owned();
+    }
+    let text = text.trim_start_matches('\u{feff}').trim();
+
+    if !text.starts_with('<') {
+        let entries = text
+            .lines()
+            .map(str::trim)
+            .filter(|line| line.starts_with("http://") || line.starts_with("https://"))
+            .map(|line| SitemapEntry { loc: line.to_string(), lastmod: None, priority: None })
+            .collect();
+        return Ok(Sitemap::UrlSet(entries));
+    }
+
+    let document = roxmltree::Document::parse(text)?;
+    let root = document.root_element();
+    let child_text = |node: roxmltree::Node, name: &str| {
+        node.children()
+            .find(|c| c.tag_name().name() == name)
+            .and_then(|c| c.text())
+            .map(|t| t.trim().to_string())
+            .filter(|t| !t.is_empty())
+    };
+
+    match root.tag_name().name() {
+        "sitemapindex" => Ok(Sitemap::Index(
+            root.children()
+                .filter(|n| n.tag_name().name() == "sitemap")
+                .filter_map(|n| child_text(n, "loc"))
+                .collect(),
+        )),
+        "urlset" => Ok(Sitemap::UrlSet(
+            root.children()
+                .filter(|n| n.tag_name().name() == "url")
+                .filter_map(|n| {
+                    Some(SitemapEntry {
+                        loc: child_text(n, "loc")?,
+                        lastmod: child_text(n, "lastmod"),
+                        priority: child_text(n, "priority").and_then(|p| p.parse().ok()),
+                    })
+                })
+                .collect(),
+        )),
+        other => Err(format!("unexpected sitemap root element <{}>", other).into()),
+    }
+}
@@ -0,0 +1,273 @@
+use crate::warc::{HttpResponse, WarcReader};
+use crate::{Body, FetchedPage};
+use flate2::read::GzDecoder;
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::io::{BufRead, BufReader, Read};
+use std::path::{Path, PathBuf};
+use url::Url;
+
+/// Somewhere pages can be read from without touching the network.
+pub trait PageSource {
+    /// Names the source in progress and error messages.
+    fn describe(&self) -> String;
+
+    /// Hands each page to `visit` in order. An error stops this source but
+    /// keeps whatever was visited before it.
+    fn read(&mut self, visit: &mut dyn FnMut(FetchedPage)) -> Result<(), Box<dyn std::error::Error>>;
+}
+
+/// Opens `path` as the kind of source it is: a directory of HTML files, a
+/// `.tar`/`.tar.gz`/`.tgz` of them, or a WARC file, known by its `.warc` or
+/// `.warc.gz` name or else by starting with a WARC header.
+pub fn open(path: &Path, urls: &UrlMapping) -> Result<Box<dyn PageSource>, Box<dyn std::error::Error>> {
+    let name = path.file_name().map(|name| name.to_string_lossy().to_lowercase()).unwrap_or_default();
+    if path.is_dir() {
+        Ok(Box::new(DirectorySource { root: path.to_path_buf(), urls: urls.clone() }))
+    } else if name.ends_with(".tar") || name.ends_with(".tar.gz") || name.ends_with(".tgz") {
+        Ok(Box::new(TarSource { path: path.to_path_buf(), urls: urls.clone() }))
+    } else if name.ends_with(".warc") || name.ends_with(".warc.gz") || starts_with_warc_header(path)? {
+        Ok(Box::new(WarcSource { path: path.to_path_buf() }))
+    } else {
+        Err("unsupported input: expected a directory, a .tar/.tar.gz/.tgz archive or a WARC file".into())
+    }
+}
+
+/// Whether the file, once any gzip is undone, begins with a WARC version line.
+fn starts_with_warc_header(path: &Path) -> Result<bool, Box<dyn std::error::Error>> {
+    let mut file = BufReader::new(File::open(path)?);
+    let reader: Box<dyn Read> = if file.fill_buf()?.starts_with(&[0x1f, 0x8b]) {
+        Box::new(GzDecoder::new(file))
+    } else {
+        Box::new(file)
+    };
+    let mut magic = Vec::new();
+    // A gzip stream that isn't valid just isn't a WARC file.
+    let _ = reader.take(5).read_to_end(&mut magic);
+    Ok(magic == b"WARC/")
+}
+
+/// Decides the URL a local HTML file is extracted as. An explicit mapping
+/// wins; otherwise the file's relative path is resolved against the base
+/// URL, with `index.html` standing for its directory.
+#[derive(Debug, Clone, Default)]
+pub struct UrlMapping {
+    base: Option<Url>,
+    explicit: HashMap<String, String>,
+    /// The first URL of the mapping file, used as the root URL when there is no base.
+    first: Option<String>,
+}
+
+impl UrlMapping {
+    /// Reads the mapping file, a CSV with `path` and `url` columns.
+    pub fn new(base: Option<Url>, map_file: Option<&Path>) -> Result<Self, Box<dyn std::error::Error>> {
+        let mut mapping = UrlMapping { base, ..UrlMapping::default() };
+        if let Some(map_file) = map_file {
+            let mut reader = csv::Reader::from_path(map_file)?;
+            let headers = reader.headers()?.clone();
+            let column = |name: &str| {
+                headers
+                    .iter()
+                    .position(|header| header.trim() == name)
+                    .ok_or_else(|| format!("{}: missing a {:?} column", map_file.display(), name))
+            };
+            let (path_column, url_column) = (column("path")?, column("url")?);
+            for row in reader.records() {
+                let row = row?;
+                let (Some(path), Some(url)) = (row.get(path_column), row.get(url_column)) else { continue };
+                Url::parse(url).map_err(|e| format!("{}: invalid URL {:?}: {}", map_file.display(), url, e))?;
+                mapping.first.get_or_insert_with(|| url.to_string());
+                mapping.explicit.insert(normalize_path(path), url.to_string());
+            }
+        }
+        Ok(mapping)
+    }
+
+    /// The URL the extracted pages are rooted at, when the mapping names one.
+    pub fn root(&self) -> Option<String> {
+        self.base.as_ref().map(Url::to_string).or_else(|| self.first.clone())
+    }
+
+    fn url_for(&self, path: &str) -> Option<String> {
+        let path = normalize_path(path);
+        if let Some(url) = self.explicit.get(&path) {
+            return Some(url.clone());
+        }
+        let base = self.base.as_ref()?;
+        let page = match path.strip_suffix("index.html") {
+            Some(dir) if dir.is_empty() || dir.ends_with('/') => dir,
+            _ => &path,
+        };
+        base.join(page).ok().map(|url| url.to_string())
+    }
+}
+
+/// Forward slashes, no leading `./` or `/`.
+fn normalize_path(path: &str) -> String {
+    let path = path.replace('\\', "/");
+    path.trim_start_matches("./").trim_start_matches('/').to_string()
+}
+
+fn is_html(path: &str) -> bool {
+    let path = path.to_lowercase();
+    path.ends_with(".html") || path.ends_with(".htm")
+}
+
+/// Builds the page for a local file, or reports that it has no URL.
+fn local_page(urls: &UrlMapping, path: &str, bytes: &[u8]) -> Option<FetchedPage> {
+    match urls.url_for(path) {
+        Some(url) => {
+            // Only `.html`/`.htm` files are read, so they are HTML whatever their first bytes look like.
+            // With no charset given, their encoding is sniffed from the bytes alone.
+            let body = Body { bytes: bytes.to_vec(), content_type: Some("text/html".to_string()), content_language: None };
+            Some(FetchedPage { url, depth: 0, body: Ok(body) })
+        }
+        None => {
+            eprintln!("No URL mapped for {}, skipping it", path);
+            None
+        }
+    }
+}
+
+/// The response records of a WARC file written by `crawl --warc`, less redirects.
+pub struct WarcSource {
+    path: PathBuf,
+}
+
+impl PageSource for WarcSource {
+    fn describe(&self) -> String {
+        format!("WARC {}", self.path.display())
+    }
+
+    fn read(&mut self, visit: &mut dyn FnMut(FetchedPage)) -> Result<(), Box<dyn std::error::Error>> {
+        for record in WarcReader::open(&self.path)? {
+            let record = record?;
+            if record.field("WARC-Type") != Some("response") {
+                continue;
+            }
+            let Some(url) = record.field("WARC-Target-URI") else { continue };
+            let depth = record.field("Crawl-Depth").and_then(|depth| depth.parse().ok()).unwrap_or(0);
+            let response = HttpResponse::parse(&record.block).map_err(|e| e.to_string());
+            // A redirect hop has nothing to extract; the page it led to has a record of its own.
+            if response.as_ref().is_ok_and(|response| (300..400).contains(&response.status) && response.header("location").is_some()) {
+                continue;
+            }
+            visit(FetchedPage::from_response(url.to_string(), depth, response));
+        }
+        Ok(())
+    }
+}
+
+/// Every `.html`/`.htm` file under a directory, in path order.
+pub struct DirectorySource {
+    root: PathBuf,
+    urls: UrlMapping,
+}
+
+impl DirectorySource {
+    fn walk(&self, dir: &Path, visit: &mut dyn FnMut(FetchedPage)) -> Result<(), Box<dyn std::error::Error>> {
+        let mut entries = fs::read_dir(dir)?.collect::<Result<Vec<_>, _>>()?;
+        entries.sort_by_key(|entry| entry.file_name());
+        for entry in entries {
+            let path = entry.path();
+            if entry.file_type()?.is_dir() {
+                self.walk(&path, visit)?;
+                continue;
+            }
+            let relative = path.strip_prefix(&self.root)?.to_string_lossy().into_owned();
+            if !is_html(&relative) {
+                continue;
+            }
+            if let Some(page) = local_page(&self.urls, &relative, &fs::read(&path)?) {
+                visit(page);
+            }
+        }
+        Ok(())
+    }
+}
+
+impl PageSource for DirectorySource {
+    fn describe(&self) -> String {
+        format!("directory {}", self.root.display())
+    }
+
+    fn read(&mut self, visit: &mut dyn FnMut(FetchedPage)) -> Result<(), Box<dyn std::error::Error>> {
+        self.walk(&self.root, visit)
+    }
+}
+
+/// Every `.html`/`.htm` file in a tarball, in archive order. Paths are taken
+/// relative to the archive root.
+pub struct TarSource {
+    path: PathBuf,
+    urls: UrlMapping,
+}
+
+impl PageSource for TarSource {
+    fn describe(&self) -> String {
+        format!("archive {}", self.path.display())
+    }
+
+    fn read(&mut self, visit: &mut dyn FnMut(FetchedPage)) -> Result<(), Box<dyn std::error::Error>> {
+        let mut file = BufReader::new(File::open(&self.path)?);
+        let reader: Box<dyn Read> = if file.fill_buf()?.starts_with(&[0x1f, 0x8b]) {
+            Box::new(GzDecoder::new(file))
+        } else {
+            Box::new(file)
+        };
+        let mut archive = tar::Archive::new(reader);
+        for entry in archive.entries()? {
+            let mut entry = entry?;
+            if !entry.header().entry_type().is_file() {
+                continue;
+            }
+            let path = entry.path()?.to_string_lossy().into_owned();
+            if !is_html(&path) {
+                continue;
+            }
+            let mut bytes = Vec::new();
+            entry.read_to_end(&mut bytes)?;
+            if let Some(page) = local_page(&self.urls, &path, &bytes) {
+                visit(page);
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use flate2::write::GzEncoder;
+    use flate2::Compression;
+    use std::io::Write;
+
+    fn described(path: &Path) -> Result<String, String> {
+        open(path, &UrlMapping::default()).map(|source| source.describe()).map_err(|e| e.to_string())
+    }
+
+    #[test]
+    fn inputs_are_recognised_or_rejected() {
+        let dir = std::env::temp_dir().join(format!("source-test-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let warc = b"WARC/1.1\r\nWARC-Type: warcinfo\r\nContent-Length: 0\r\n\r\n\r\n\r\n";
+
+        fs::write(dir.join("page.html"), "<html><body>Hi</body></html>").unwrap();
+        fs::write(dir.join("crawl.warc"), "").unwrap();
+        fs::write(dir.join("renamed.bin"), warc).unwrap();
+        let mut gzipped = GzEncoder::new(Vec::new(), Compression::default());
+        gzipped.write_all(warc).unwrap();
+        fs::write(dir.join("renamed.gz"), gzipped.finish().unwrap()).unwrap();
+        fs::write(dir.join("broken.gz"), [0x1f, 0x8b, 0x00]).unwrap();
+
+        assert!(described(&dir).unwrap().starts_with("directory "));
+        assert!(described(&dir.join("pages.tar.gz")).unwrap().starts_with("archive "));
+        assert!(described(&dir.join("crawl.warc")).unwrap().starts_with("WARC "));
+        assert!(described(&dir.join("renamed.bin")).unwrap().starts_with("WARC "));
+        assert!(described(&dir.join("renamed.gz")).unwrap().starts_with("WARC "));
+        assert!(described(&dir.join("page.html")).unwrap_err().starts_with("unsupported input"));
+        assert!(described(&dir.join("broken.gz")).unwrap_err().starts_with("unsupported input"));
+        assert!(described(&dir.join("missing.bin")).is_err());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
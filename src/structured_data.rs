@@ -0,0 +1,428 @@
+use ego_tree::NodeId;
+use scraper::{ElementRef, Html, Selector};
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use std::collections::BTreeMap;
+use url::Url;
+
+/// Machine-readable data a page publishes about itself, kept apart from its text.
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct StructuredData {
+    /// Each `<script type="application/ld+json">` object, with top-level arrays and `@graph` lists flattened.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub json_ld: Vec<Value>,
+    /// schema.org Microdata items in the WHATWG JSON form: `type`, `id` and `properties`.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub microdata: Vec<Value>,
+    /// RDFa Lite items (`typeof` elements) in the same form, with their `vocab`.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub rdfa: Vec<Value>,
+    /// `og:*` (and `article:*`, `book:*`, ...) meta properties; repeated ones become arrays.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub opengraph: BTreeMap<String, Value>,
+    /// `twitter:*` card meta tags.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub twitter: BTreeMap<String, Value>,
+}
+
+impl StructuredData {
+    pub fn is_empty(&self) -> bool {
+        self.json_ld.is_empty() && self.microdata.is_empty() && self.rdfa.is_empty() && self.opengraph.is_empty() && self.twitter.is_empty()
+    }
+}
+
+/// OpenGraph namespaces besides `og:` itself.
+const OPENGRAPH_PREFIXES: &[&str] = &["og:", "article:", "book:", "profile:", "music:", "video:", "fb:"];
+
+/// Reads every kind of structured data in the document. Relative URLs are
+/// resolved against `base`; JSON-LD that does not parse is skipped.
+pub fn extract(document: &Html, base: &Url) -> StructuredData {
+    StructuredData {
+        json_ld: json_ld(document),
+        microdata: microdata(document, base),
+        rdfa: rdfa(document, base),
+        opengraph: meta_tags(document, |key| OPENGRAPH_PREFIXES.iter().any(|prefix| key.starts_with(prefix))),
+        twitter: meta_tags(document, |key| key.starts_with("twitter:")),
+    }
+}
+
+fn json_ld(document: &Html) -> Vec<Value> {
+    let scripts = Selector::parse("script[type]").unwrap();
+    let mut items = Vec::new();
+    for script in document.select(&scripts) {
+        let kind = script.value().attr("type").unwrap_or("");
+        if !kind.split(';').next().unwrap_or("").trim().eq_ignore_ascii_case("application/ld+json") {
+            continue;
+        }
+        let text = script.text().collect::<String>();
+        // Some sites wrap the JSON in an HTML comment or CDATA section, or end it with a semicolon.
+        let text = text.trim();
+        let text = text.strip_prefix("<!--").and_then(|t| t.strip_suffix("-->")).unwrap_or(text).trim();
+        let text = text.strip_prefix("//<![CDATA[").or_else(|| text.strip_prefix("<![CDATA[")).unwrap_or(text);
+        let text = text.strip_suffix("//]]>").or_else(|| text.strip_suffix("]]>")).unwrap_or(text).trim();
+        let text = text.strip_suffix(';').unwrap_or(text);
+        match serde_json::from_str::<Value>(text) {
+            Ok(Value::Array(values)) => values.into_iter().for_each(|value| push_json_ld(&mut items, value)),
+            Ok(value) => push_json_ld(&mut items, value),
+            Err(_) => {}
+        }
+    }
+    items
+}
+
+/// Adds a JSON-LD object, or each node of its `@graph` with the graph's
+/// `@context` copied in, so an `Event` can be found the same way in both.
+fn push_json_ld(items: &mut Vec<Value>, value: Value) {
+    let Some(Value::Array(graph)) = value.get("@graph") else {
+        items.push(value);
+        return;
+    };
+    let context = value.get("@context");
+    for node in graph {
+        let mut node = node.clone();
+        if let (Some(object), Some(context)) = (node.as_object_mut(), context) {
+            object.entry("@context").or_insert_with(|| context.clone());
+        }
+        items.push(node);
+    }
+}
+
+fn microdata(document: &Html, base: &Url) -> Vec<Value> {
+    let items = Selector::parse("[itemscope]").unwrap();
+    document
+        .select(&items)
+        .filter(|item| item.value().attr("itemprop").is_none())
+        .map(|item| microdata_item(document, item, base, &[]))
+        .collect()
+}
+
+/// `enclosing` holds the items this one is a property of, so that an
+/// `itemref` cycle ends instead of nesting forever.
+fn microdata_item(document: &Html, item: ElementRef, base: &Url, enclosing: &[NodeId]) -> Value {
+    let mut properties: Map<String, Value> = Map::new();
+    let path: Vec<NodeId> = enclosing.iter().copied().chain([item.id()]).collect();
+    let mut roots = vec![item];
+    // `itemref` pulls in properties from elements elsewhere in the page.
+    if let Some(refs) = item.value().attr("itemref") {
+        for id in refs.split_whitespace() {
+            if let Ok(selector) = Selector::parse(&format!("[id=\"{}\"]", id.replace('"', "\\\""))) {
+                roots.extend(document.select(&selector).next());
+            }
+        }
+    }
+    for (i, root) in roots.into_iter().enumerate() {
+        // Referenced elements can be properties themselves, not just hold them.
+        if i > 0 && root.value().attr("itemprop").is_some() {
+            add_microdata_property(document, root, base, &mut properties, &path);
+        }
+        collect_microdata(document, root, base, &mut properties, &path);
+    }
+
+    let mut object = Map::new();
+    if let Some(types) = item.value().attr("itemtype") {
+        object.insert("type".to_string(), types.split_whitespace().map(|t| Value::String(t.to_string())).collect());
+    }
+    if let Some(id) = item.value().attr("itemid") {
+        object.insert("id".to_string(), Value::String(resolve(base, id)));
+    }
+    object.insert("properties".to_string(), Value::Object(properties));
+    Value::Object(object)
+}
+
+/// Adds the properties under `element`, stopping at nested items, whose properties are their own.
+fn collect_microdata(document: &Html, element: ElementRef, base: &Url, properties: &mut Map<String, Value>, path: &[NodeId]) {
+    for child in element.children().filter_map(ElementRef::wrap) {
+        if child.value().attr("itemprop").is_some() {
+            add_microdata_property(document, child, base, properties, path);
+        }
+        if child.value().attr("itemscope").is_none() {
+            collect_microdata(document, child, base, properties, path);
+        }
+    }
+}
+
+fn add_microdata_property(document: &Html, element: ElementRef, base: &Url, properties: &mut Map<String, Value>, path: &[NodeId]) {
+    let value = if element.value().attr("itemscope").is_some() && !path.contains(&element.id()) {
+        microdata_item(document, element, base, path)
+    } else {
+        Value::String(element_value(element, base, "itemprop"))
+    };
+    for name in element.value().attr("itemprop").unwrap_or("").split_whitespace() {
+        add_value(properties, name, value.clone());
+    }
+}
+
+fn rdfa(document: &Html, base: &Url) -> Vec<Value> {
+    let items = Selector::parse("[typeof]").unwrap();
+    document
+        .select(&items)
+        .filter(|item| item.value().attr("property").is_none())
+        .map(|item| rdfa_item(item, base))
+        .collect()
+}
+
+fn rdfa_item(item: ElementRef, base: &Url) -> Value {
+    let mut properties: Map<String, Value> = Map::new();
+    collect_rdfa(item, base, &mut properties);
+
+    let mut object = Map::new();
+    if let Some(vocab) = inherited_vocab(item) {
+        object.insert("vocab".to_string(), Value::String(vocab));
+    }
+    let types = item.value().attr("typeof").unwrap_or("");
+    object.insert("type".to_string(), types.split_whitespace().map(|t| Value::String(t.to_string())).collect());
+    if let Some(resource) = item.value().attr("resource").or_else(|| item.value().attr("about")) {
+        object.insert("id".to_string(), Value::String(resolve(base, resource)));
+    }
+    object.insert("properties".to_string(), Value::Object(properties));
+    Value::Object(object)
+}
+
+fn collect_rdfa(element: ElementRef, base: &Url, properties: &mut Map<String, Value>) {
+    for child in element.children().filter_map(ElementRef::wrap) {
+        let is_item = child.value().attr("typeof").is_some();
+        if let Some(names) = child.value().attr("property") {
+            let value = if is_item { rdfa_item(child, base) } else { Value::String(element_value(child, base, "property")) };
+            for name in names.split_whitespace() {
+                add_value(properties, name, value.clone());
+            }
+        }
+        if !is_item {
+            collect_rdfa(child, base, properties);
+        }
+    }
+}
+
+/// The `vocab` in effect for an element: its own or its nearest ancestor's.
+fn inherited_vocab(element: ElementRef) -> Option<String> {
+    let mut current = Some(element);
+    while let Some(el) = current {
+        if let Some(vocab) = el.value().attr("vocab") {
+            return Some(vocab.to_string());
+        }
+        current = el.parent().and_then(ElementRef::wrap);
+    }
+    None
+}
+
+/// The value of a property element, by the Microdata rules: URLs for links
+/// and media, machine-readable attributes where there are some, otherwise
+/// the text. RDFa's `content` and `resource` attributes take precedence.
+fn element_value(element: ElementRef, base: &Url, attribute: &str) -> String {
+    let el = element.value();
+    if attribute == "property" {
+        if let Some(value) = el.attr("content") {
+            return value.to_string();
+        }
+        if let Some(resource) = el.attr("resource") {
+            return resolve(base, resource);
+        }
+    }
+    let url_attr = match el.name() {
+        "a" | "area" | "link" => Some("href"),
+        "audio" | "embed" | "iframe" | "img" | "source" | "track" | "video" => Some("src"),
+        "object" => Some("data"),
+        _ => None,
+    };
+    if let Some(url) = url_attr.and_then(|attr| el.attr(attr)) {
+        return resolve(base, url);
+    }
+    let value = match el.name() {
+        "meta" => el.attr("content"),
+        "data" | "meter" => el.attr("value"),
+        "time" => el.attr("datetime"),
+        _ => None,
+    };
+    match value {
+        Some(value) => value.to_string(),
+        None => element.text().flat_map(str::split_whitespace).collect::<Vec<_>>().join(" "),
+    }
+}
+
+/// `<meta property|name="...">` tags whose key `wanted` accepts.
+fn meta_tags(document: &Html, wanted: impl Fn(&str) -> bool) -> BTreeMap<String, Value> {
+    let metas = Selector::parse("meta[content]").unwrap();
+    let mut tags: Map<String, Value> = Map::new();
+    for meta in document.select(&metas) {
+        let key = meta.value().attr("property").or_else(|| meta.value().attr("name")).unwrap_or("").trim().to_lowercase();
+        if wanted(&key) {
+            push_property(&mut tags, &key, Value::String(meta.value().attr("content").unwrap_or("").trim().to_string()));
+        }
+    }
+    tags.into_iter().collect()
+}
+
+/// Appends to the array of values of an item property.
+fn add_value(properties: &mut Map<String, Value>, name: &str, value: Value) {
+    if let Value::Array(values) = properties.entry(name).or_insert_with(|| Value::Array(Vec::new())) {
+        values.push(value);
+    }
+}
+
+/// Sets `name`, turning it into an array once it has more than one value.
+fn push_property(properties: &mut Map<String, Value>, name: &str, value: Value) {
+    match properties.get_mut(name) {
+        Some(Value::Array(values)) => values.push(value),
+        Some(existing) => *existing = Value::Array(vec![existing.take(), value]),
+        None => {
+            properties.insert(name.to_string(), value);
+        }
+    }
+}
+
+fn resolve(base: &Url, url: &str) -> String {
+    base.join(url.trim()).map(|url| url.to_string()).unwrap_or_else(|_| url.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn extract_from(html: &str) -> StructuredData {
+        extract(&Html::parse_document(html), &Url::parse("https://example.org/events/").unwrap())
+    }
+
+    const JSON_LD_PAGE: &str = r#"<html><head>
+        <script type="application/ld+json">
+          {"@context": "https://schema.org", "@graph": [
+            {"@type": "WebPage", "@id": "https://example.org/events/#page"},
+            {"@type": "Event", "name": "Open day", "startDate": "2025-06-14", "@context": "https://schema.org/"}
+          ]}
+        </script>
+        <script type="application/ld+json; charset=utf-8">
+          [{"@type": "BreadcrumbList"}, {"@type": "FAQPage"}]
+        </script>
+        <script type="application/ld+json"><!-- {"@type": "Course", "name": "History"}; --></script>
+        <script type="application/ld+json">{"@type": "Broken",</script>
+        <script type="text/javascript">{"@type": "NotStructuredData"}</script>
+        <script type="application/ld+json">{"@graph": {"@type": "Place"}}</script>
+    </head><body></body></html>"#;
+
+    #[test]
+    fn json_ld_graphs_and_arrays_are_flattened() {
+        let data = extract_from(JSON_LD_PAGE);
+        assert_eq!(
+            data.json_ld,
+            [
+                json!({"@context": "https://schema.org", "@type": "WebPage", "@id": "https://example.org/events/#page"}),
+                // A node's own context is kept.
+                json!({"@context": "https://schema.org/", "@type": "Event", "name": "Open day", "startDate": "2025-06-14"}),
+                json!({"@type": "BreadcrumbList"}),
+                json!({"@type": "FAQPage"}),
+                json!({"@type": "Course", "name": "History"}),
+                // Only an array is a list of nodes.
+                json!({"@graph": {"@type": "Place"}}),
+            ]
+        );
+        assert!(data.microdata.is_empty() && data.rdfa.is_empty());
+    }
+
+    const MICRODATA_PAGE: &str = r#"<html><body>
+        <div itemscope itemtype="https://schema.org/Event" itemid="/events/open-day" itemref="venue">
+          <h1 itemprop="name">Open  day</h1>
+          <time itemprop="startDate" datetime="2025-06-14T10:00">14 June</time>
+          <a itemprop="url" href="open-day">Details</a>
+          <div itemprop="organizer" itemscope itemtype="https://schema.org/Organization">
+            <span itemprop="name">Admissions</span>
+            <div itemprop="member" itemscope itemtype="https://schema.org/Person"><span itemprop="name">Sam</span></div>
+          </div>
+          <span itemprop="keywords">tours</span><span itemprop="keywords">talks</span>
+        </div>
+        <p id="venue" itemprop="location">Main campus</p>
+        <div itemscope itemtype="https://schema.org/Thing" id="loop" itemref="loop-ref">
+          <div id="loop-ref" itemprop="self" itemscope itemref="loop"></div>
+        </div>
+    </body></html>"#;
+
+    #[test]
+    fn microdata_items_nest() {
+        let data = extract_from(MICRODATA_PAGE);
+        assert_eq!(data.microdata.len(), 2);
+        assert_eq!(
+            data.microdata[0],
+            json!({
+                "type": ["https://schema.org/Event"],
+                "id": "https://example.org/events/open-day",
+                "properties": {
+                    "name": ["Open day"],
+                    "startDate": ["2025-06-14T10:00"],
+                    "url": ["https://example.org/events/open-day"],
+                    "organizer": [{
+                        "type": ["https://schema.org/Organization"],
+                        "properties": {
+                            "name": ["Admissions"],
+                            "member": [{"type": ["https://schema.org/Person"], "properties": {"name": ["Sam"]}}],
+                        },
+                    }],
+                    "keywords": ["tours", "talks"],
+                    "location": ["Main campus"],
+                },
+            })
+        );
+        // An itemref cycle stops at the item it started from.
+        assert_eq!(data.microdata[1]["properties"]["self"][0]["properties"]["self"], json!([""]));
+    }
+
+    const RDFA_PAGE: &str = r##"<html><body vocab="https://schema.org/">
+        <div typeof="Course" resource="#history">
+          <h1 property="name">History BA</h1>
+          <meta property="courseCode" content="V100">
+          <a property="url" href="history">More</a>
+          <div property="provider" typeof="CollegeOrUniversity">
+            <span property="name">Example University</span>
+          </div>
+          <span property="name alternateName">History</span>
+        </div>
+    </body></html>"##;
+
+    #[test]
+    fn rdfa_lite_items() {
+        let data = extract_from(RDFA_PAGE);
+        assert_eq!(
+            data.rdfa,
+            [json!({
+                "vocab": "https://schema.org/",
+                "type": ["Course"],
+                "id": "https://example.org/events/#history",
+                "properties": {
+                    "name": ["History BA", "History"],
+                    "courseCode": ["V100"],
+                    "url": ["https://example.org/events/history"],
+                    "provider": [{
+                        "vocab": "https://schema.org/",
+                        "type": ["CollegeOrUniversity"],
+                        "properties": {"name": ["Example University"]},
+                    }],
+                    "alternateName": ["History"],
+                },
+            })]
+        );
+    }
+
+    #[test]
+    fn opengraph_and_twitter_meta() {
+        let data = extract_from(
+            r#"<html><head>
+                <meta property="og:title" content=" Open day ">
+                <meta property="og:image" content="https://example.org/a.jpg">
+                <meta property="og:image" content="https://example.org/b.jpg">
+                <meta property="article:published_time" content="2025-01-01">
+                <meta name="twitter:card" content="summary">
+                <meta name="Twitter:Site" content="@example">
+                <meta name="description" content="Not OpenGraph">
+                <meta property="og:description">
+            </head></html>"#,
+        );
+        assert_eq!(
+            serde_json::to_value(&data.opengraph).unwrap(),
+            json!({
+                "og:title": "Open day",
+                "og:image": ["https://example.org/a.jpg", "https://example.org/b.jpg"],
+                "article:published_time": "2025-01-01",
+            })
+        );
+        assert_eq!(serde_json::to_value(&data.twitter).unwrap(), json!({"twitter:card": "summary", "twitter:site": "@example"}));
+        assert!(extract_from("<html><body><p>Nothing here</p></body></html>").is_empty());
+    }
+}
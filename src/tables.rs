@@ -0,0 +1,333 @@
+use scraper::{Element, ElementRef};
+use serde::{Deserialize, Serialize};
+
+/// A `<table>` from the main content, with spanned cells repeated into every
+/// row and column they cover so each row has one entry per column.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Table {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub caption: Option<String>,
+    /// Text of the nearest heading before the table.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub heading: Option<String>,
+    /// Rows in `<thead>`, or leading rows made up only of `<th>` cells.
+    pub header_rows: Vec<Vec<String>>,
+    pub rows: Vec<Vec<String>>,
+}
+
+impl Table {
+    /// Reads a table element, or returns `None` if it has no text in any cell.
+    /// Cell text leaves out `skippable` subtrees and nested tables, which
+    /// are read as tables of their own.
+    pub fn from_element(table: ElementRef, heading: Option<String>, skippable: &dyn Fn(ElementRef) -> bool) -> Option<Table> {
+        let caption = table
+            .children()
+            .filter_map(ElementRef::wrap)
+            .find(|child| child.value().name() == "caption")
+            .map(|caption| cell_text(caption, skippable))
+            .filter(|caption| !caption.is_empty());
+
+        // Rows of nested tables belong to those tables, not this one.
+        let rows: Vec<ElementRef> = table
+            .descendants()
+            .filter_map(ElementRef::wrap)
+            .filter(|el| el.value().name() == "tr" && owning_table(*el).is_some_and(|owner| owner.id() == table.id()))
+            .collect();
+
+        let mut grid: Vec<Vec<Option<String>>> = vec![Vec::new(); rows.len()];
+        let mut header_count = 0;
+        let mut leading_headers = true;
+        for (r, row) in rows.iter().enumerate() {
+            let cells: Vec<ElementRef> = row
+                .children()
+                .filter_map(ElementRef::wrap)
+                .filter(|cell| matches!(cell.value().name(), "td" | "th"))
+                .collect();
+            let in_thead = row.parent_element().is_some_and(|parent| parent.value().name() == "thead");
+            let all_th = !cells.is_empty() && cells.iter().all(|cell| cell.value().name() == "th");
+            if leading_headers && (in_thead || all_th) {
+                header_count = r + 1;
+            } else {
+                leading_headers = false;
+            }
+
+            let mut col = 0;
+            for cell in cells {
+                while grid[r].get(col).is_some_and(Option::is_some) {
+                    col += 1;
+                }
+                let text = cell_text(cell, skippable);
+                let colspan = span(cell, "colspan").max(1);
+                // `rowspan="0"` runs to the end of the table.
+                let rowspan = match span(cell, "rowspan") {
+                    0 => rows.len() - r,
+                    n => n.min(rows.len() - r),
+                };
+                for spanned_row in &mut grid[r..r + rowspan] {
+                    if spanned_row.len() < col + colspan {
+                        spanned_row.resize(col + colspan, None);
+                    }
+                    // Where spans overlap, the cell that got there first keeps the slot.
+                    for slot in spanned_row[col..col + colspan].iter_mut().filter(|slot| slot.is_none()) {
+                        *slot = Some(text.clone());
+                    }
+                }
+                col += colspan;
+            }
+        }
+
+        let width = grid.iter().map(Vec::len).max().unwrap_or(0);
+        let mut grid: Vec<Vec<String>> = grid
+            .into_iter()
+            .map(|row| {
+                let mut row: Vec<String> = row.into_iter().map(Option::unwrap_or_default).collect();
+                row.resize(width, String::new());
+                row
+            })
+            .collect();
+        if grid.iter().flatten().all(String::is_empty) {
+            return None;
+        }
+        let rows = grid.split_off(header_count);
+        Some(Table { caption, heading, header_rows: grid, rows })
+    }
+
+    /// Renders the table as a GitHub-flavoured Markdown table. Markdown has
+    /// a single header row, so stacked header rows are merged per column
+    /// ("Fee" over "Home" becomes "Fee Home"); a table without headers uses
+    /// its first row.
+    pub fn to_markdown(&self) -> String {
+        let (header, body) = if self.header_rows.is_empty() {
+            match self.rows.split_first() {
+                Some((first, rest)) => (first.clone(), rest),
+                None => return String::new(),
+            }
+        } else {
+            let width = self.header_rows[0].len();
+            let merged = (0..width)
+                .map(|col| {
+                    let mut parts: Vec<&str> = Vec::new();
+                    for row in &self.header_rows {
+                        let cell = row[col].as_str();
+                        if !cell.is_empty() && parts.last() != Some(&cell) {
+                            parts.push(cell);
+                        }
+                    }
+                    parts.join(" ")
+                })
+                .collect();
+            (merged, self.rows.as_slice())
+        };
+
+        let mut markdown = String::new();
+        if let Some(caption) = &self.caption {
+            markdown.push_str(&format!("**{}**\n\n", escape(caption)));
+        }
+        let line = |cells: &[String]| format!("| {} |\n", cells.iter().map(|cell| escape(cell)).collect::<Vec<_>>().join(" | "));
+        markdown.push_str(&line(&header));
+        markdown.push_str(&format!("|{}\n", " --- |".repeat(header.len())));
+        for row in body {
+            markdown.push_str(&line(row));
+        }
+        markdown
+    }
+}
+
+/// Every non-skippable table under `root` in document order, each paired
+/// with the heading closest before it.
+pub fn extract_tables(root: ElementRef, skippable: &dyn Fn(ElementRef) -> bool) -> Vec<Table> {
+    let mut tables = Vec::new();
+    let mut last_heading: Option<String> = None;
+    for element in root.descendants().filter_map(ElementRef::wrap) {
+        match element.value().name() {
+            "h1" | "h2" | "h3" | "h4" | "h5" | "h6" if !skippable(element) => {
+                let text = collapsed_text(element);
+                if !text.is_empty() {
+                    last_heading = Some(text);
+                }
+            }
+            "table" if !skippable(element) => tables.extend(Table::from_element(element, last_heading.clone(), skippable)),
+            _ => {}
+        }
+    }
+    tables
+}
+
+/// The tables directly inside `table`'s cells, not counting those nested
+/// deeper still or inside `skippable` subtrees.
+pub fn nested_tables<'a>(table: ElementRef<'a>, skippable: &dyn Fn(ElementRef) -> bool) -> Vec<ElementRef<'a>> {
+    fn collect<'a>(element: ElementRef<'a>, skippable: &dyn Fn(ElementRef) -> bool, tables: &mut Vec<ElementRef<'a>>) {
+        for child in element.children().filter_map(ElementRef::wrap) {
+            if skippable(child) {
+                continue;
+            }
+            if child.value().name() == "table" {
+                tables.push(child);
+            } else {
+                collect(child, skippable, tables);
+            }
+        }
+    }
+    let mut tables = Vec::new();
+    collect(table, skippable, &mut tables);
+    tables
+}
+
+fn owning_table(element: ElementRef) -> Option<ElementRef> {
+    let mut current = element.parent_element();
+    while let Some(el) = current {
+        if el.value().name() == "table" {
+            return Some(el);
+        }
+        current = el.parent_element();
+    }
+    None
+}
+
+/// A `colspan`/`rowspan` value, defaulting to 1 and capped as browsers do.
+fn span(cell: ElementRef, attr: &str) -> usize {
+    cell.value()
+        .attr(attr)
+        .and_then(|value| value.trim().parse::<usize>().ok())
+        .unwrap_or(1)
+        .min(if attr == "colspan" { 1000 } else { 65534 })
+}
+
+fn collapsed_text(element: ElementRef) -> String {
+    element.text().flat_map(str::split_whitespace).collect::<Vec<_>>().join(" ")
+}
+
+/// The whitespace-collapsed text of a cell or caption, without skippable
+/// subtrees or nested tables.
+fn cell_text(cell: ElementRef, skippable: &dyn Fn(ElementRef) -> bool) -> String {
+    fn collect<'a>(element: ElementRef<'a>, skippable: &dyn Fn(ElementRef) -> bool, words: &mut Vec<&'a str>) {
+        for child in element.children() {
+            match ElementRef::wrap(child) {
+                Some(child) if child.value().name() == "table" || skippable(child) => {}
+                Some(child) => collect(child, skippable, words),
+                None => words.extend(child.value().as_text().into_iter().flat_map(|text| text.split_whitespace())),
+            }
+        }
+    }
+    let mut words = Vec::new();
+    collect(cell, skippable, &mut words);
+    words.join(" ")
+}
+
+fn escape(cell: &str) -> String {
+    cell.replace('|', "\\|")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use scraper::{Html, Selector};
+
+    fn parse(html: &str) -> Table {
+        let document = Html::parse_document(html);
+        let table = document.select(&Selector::parse("table").unwrap()).next().unwrap();
+        Table::from_element(table, None, &|_| false).unwrap()
+    }
+
+    fn cells(rows: &[&[&str]]) -> Vec<Vec<String>> {
+        rows.iter().map(|row| row.iter().map(|cell| cell.to_string()).collect()).collect()
+    }
+
+    #[test]
+    fn spans_are_repeated_into_every_slot() {
+        let table = parse(
+            "<table>
+               <tr><td rowspan=2>A</td><td colspan=2>B</td></tr>
+               <tr><td>C</td><td>D</td></tr>
+               <tr><td colspan=2 rowspan=2>E</td><td>F</td></tr>
+               <tr><td>G</td></tr>
+             </table>",
+        );
+        assert!(table.header_rows.is_empty());
+        assert_eq!(table.rows, cells(&[&["A", "B", "B"], &["A", "C", "D"], &["E", "E", "F"], &["E", "E", "G"]]));
+    }
+
+    #[test]
+    fn overlapping_spans_keep_the_earlier_cell() {
+        let table = parse(
+            "<table>
+               <tr><td>a</td><td rowspan=2>B</td><td>c</td></tr>
+               <tr><td colspan=3>X</td></tr>
+             </table>",
+        );
+        assert_eq!(table.rows, cells(&[&["a", "B", "c"], &["X", "B", "X"]]));
+    }
+
+    #[test]
+    fn rowspans_stop_at_the_last_row() {
+        let table = parse(
+            "<table>
+               <tr><td rowspan=0>A</td><td>1</td></tr>
+               <tr><td>2</td></tr>
+               <tr><td rowspan=9>3</td><td>x</td></tr>
+             </table>",
+        );
+        assert_eq!(table.rows, cells(&[&["A", "1", ""], &["A", "2", ""], &["A", "3", "x"]]));
+    }
+
+    #[test]
+    fn ragged_rows_are_padded() {
+        let table = parse("<table><tr><td>1</td></tr><tr><td>2</td><td>3</td><td>4</td></tr><tr></tr></table>");
+        assert_eq!(table.rows, cells(&[&["1", "", ""], &["2", "3", "4"], &["", "", ""]]));
+    }
+
+    #[test]
+    fn header_rows_come_from_thead_or_leading_th_rows() {
+        let table = parse(
+            "<table>
+               <thead><tr><td colspan=2>Fee</td></tr></thead>
+               <tr><th>Home</th><th>Overseas</th></tr>
+               <tr><td>£9,250</td><td>£20,000</td></tr>
+               <tr><th>Total</th><th>-</th></tr>
+             </table>",
+        );
+        assert_eq!(table.header_rows, cells(&[&["Fee", "Fee"], &["Home", "Overseas"]]));
+        // A row of <th> after the body has started is a body row.
+        assert_eq!(table.rows, cells(&[&["£9,250", "£20,000"], &["Total", "-"]]));
+        assert_eq!(table.to_markdown(), "| Fee Home | Fee Overseas |\n| --- | --- |\n| £9,250 | £20,000 |\n| Total | - |\n");
+
+        // Row headers down the side don't make a header row.
+        let side = parse("<table><tr><th>Year</th><td>2024</td></tr><tr><th>Fee</th><td>£9,250</td></tr></table>");
+        assert!(side.header_rows.is_empty());
+        assert_eq!(side.to_markdown(), "| Year | 2024 |\n| --- | --- |\n| Fee | £9,250 |\n");
+    }
+
+    #[test]
+    fn nested_tables_and_empty_tables() {
+        let html = "<table><caption> Fees </caption><tr><td>a<table><tr><td>inner</td></tr></table> b</td></tr></table>";
+        let table = parse(html);
+        assert_eq!(table.caption.as_deref(), Some("Fees"));
+        assert_eq!(table.rows, cells(&[&["a b"]]));
+        // The inner table is read on its own instead.
+        let document = Html::parse_document(html);
+        let tables = extract_tables(document.root_element(), &|_| false);
+        assert_eq!(tables.len(), 2);
+        assert_eq!(tables[1].rows, cells(&[&["inner"]]));
+        let outer = document.select(&Selector::parse("table").unwrap()).next().unwrap();
+        assert_eq!(nested_tables(outer, &|_| false).len(), 1);
+        assert!(nested_tables(outer, &|element| element.value().name() == "td").is_empty());
+
+        let document = Html::parse_document("<table><tr><td> </td></tr></table>");
+        let empty = document.select(&Selector::parse("table").unwrap()).next().unwrap();
+        assert!(Table::from_element(empty, None, &|_| false).is_none());
+    }
+
+    #[test]
+    fn skippable_content_is_left_out_of_cells() {
+        let document = Html::parse_document(
+            "<table><caption>Fees <span class=hidden>(draft)</span></caption>
+               <tr><td>£9,250 <nav><a href=/fees>All fees</a></nav></td><td><span class=hidden>secret</span></td></tr>
+             </table>",
+        );
+        let table = document.select(&Selector::parse("table").unwrap()).next().unwrap();
+        let skippable = |element: ElementRef| element.value().name() == "nav" || element.value().has_class("hidden", scraper::CaseSensitivity::CaseSensitive);
+        let table = Table::from_element(table, None, &skippable).unwrap();
+        assert_eq!(table.caption.as_deref(), Some("Fees"));
+        assert_eq!(table.rows, cells(&[&["£9,250", ""]]));
+    }
+}
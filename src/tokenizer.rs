@@ -0,0 +1,623 @@
+use crate::config::{ChunkingConfig, ConfigError, TokenizerKind};
+use base64::Engine;
+use fancy_regex::Regex;
+use serde_json::Value;
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, HashMap};
+use std::fs;
+use std::path::Path;
+
+/// The split used by OpenAI's `cl100k_base`.
+const CL100K_PATTERN: &str = r"(?i:'s|'t|'re|'ve|'m|'ll|'d)|[^\r\n\p{L}\p{N}]?\p{L}+|\p{N}{1,3}| ?[^\s\p{L}\p{N}]+[\r\n]*|\s*[\r\n]+|\s+(?!\S)|\s+";
+
+/// The split used by OpenAI's `o200k_base`.
+const O200K_PATTERN: &str = concat!(
+    r"[^\r\n\p{L}\p{N}]?[\p{Lu}\p{Lt}\p{Lm}\p{Lo}\p{M}]*[\p{Ll}\p{Lm}\p{Lo}\p{M}]+(?i:'s|'t|'re|'ve|'m|'ll|'d)?",
+    r"|[^\r\n\p{L}\p{N}]?[\p{Lu}\p{Lt}\p{Lm}\p{Lo}\p{M}]+[\p{Ll}\p{Lm}\p{Lo}\p{M}]*(?i:'s|'t|'re|'ve|'m|'ll|'d)?",
+    r"|\p{N}{1,3}| ?[^\s\p{L}\p{N}]+[\r\n/]*|\s*[\r\n]+|\s+(?!\S)|\s+",
+);
+
+/// The GPT-2 split, used by `r50k_base` and `p50k_base` and by Hugging Face `ByteLevel` pre-tokenizers.
+const GPT2_PATTERN: &str = r"'s|'t|'re|'ve|'m|'ll|'d| ?\p{L}+| ?\p{N}+| ?[^\s\p{L}\p{N}]+|\s+(?!\S)|\s+";
+
+/// The split used by Hugging Face `Whitespace` and `BertPreTokenizer`.
+const WORD_PATTERN: &str = r"\w+|[^\w\s]+";
+
+/// Words and the whitespace between them, for a `tokenizer.json` whose
+/// pre-tokenizer gives no split, so a long text is never encoded as one piece.
+const FALLBACK_PATTERN: &str = r"\S+|\s+";
+
+/// The split for a tiktoken encoding, known from its file name, e.g. `o200k_base.tiktoken`.
+fn tiktoken_pattern(path: &Path) -> Option<&'static str> {
+    let name = path.file_stem()?.to_string_lossy().to_ascii_lowercase();
+    if name.contains("cl100k") {
+        Some(CL100K_PATTERN)
+    } else if name.contains("o200k") {
+        Some(O200K_PATTERN)
+    } else if ["r50k", "p50k", "gpt2"].iter().any(|encoding| name.contains(encoding)) {
+        Some(GPT2_PATTERN)
+    } else {
+        None
+    }
+}
+
+/// Counts text the way an embedding model would, so chunk sizes can be set in its tokens.
+pub trait Tokenizer: Send + Sync {
+    /// The byte offset in `text` where each token ends, in order. A token
+    /// may end inside a multi-byte character.
+    fn token_ends(&self, text: &str) -> Vec<usize>;
+
+    fn count(&self, text: &str) -> usize {
+        self.token_ends(text).len()
+    }
+}
+
+/// One token per run of non-whitespace.
+pub struct WhitespaceTokenizer;
+
+impl Tokenizer for WhitespaceTokenizer {
+    fn token_ends(&self, text: &str) -> Vec<usize> {
+        let mut ends = Vec::new();
+        let mut in_word = false;
+        for (i, c) in text.char_indices() {
+            if c.is_whitespace() {
+                if in_word {
+                    ends.push(i);
+                }
+                in_word = false;
+            } else {
+                in_word = true;
+            }
+        }
+        if in_word {
+            ends.push(text.len());
+        }
+        ends
+    }
+
+    fn count(&self, text: &str) -> usize {
+        text.split_whitespace().count()
+    }
+}
+
+/// How adjacent symbols are ranked for merging.
+enum Merges {
+    /// tiktoken: the rank of the token the pair would become.
+    Ranks(HashMap<Vec<u8>, u32>),
+    /// Hugging Face: an id for every symbol in the merge list, and for each
+    /// pair of ids its position in the list and the id of what it becomes.
+    Pairs { ids: HashMap<Vec<u8>, u32>, pairs: HashMap<(u32, u32), (u32, u32)> },
+}
+
+/// A symbol of a piece being encoded, as a node of a linked list.
+struct Symbol {
+    /// Its bytes in the piece's symbol bytes.
+    start: usize,
+    end: usize,
+    /// How many bytes of the original text it covers.
+    text_len: usize,
+    /// Its id in `Merges::Pairs`, if it has one.
+    id: Option<u32>,
+    prev: Option<usize>,
+    next: Option<usize>,
+    /// Merged into the symbol before it.
+    merged: bool,
+}
+
+/// How text is turned into the symbols BPE starts from.
+enum Alphabet {
+    /// Raw bytes, as tiktoken does.
+    Bytes,
+    /// Each byte as a printable character, as GPT-2-style `ByteLevel` does.
+    ByteLevel,
+    /// Characters, with spaces shown as `▁` and one put before each word, as SentencePiece does.
+    Metaspace,
+    /// Plain characters.
+    Chars,
+}
+
+/// Byte-pair encoding with a vocabulary read from a `.tiktoken` file or a
+/// Hugging Face `tokenizer.json`. Special tokens and normalisers are not
+/// applied, so counts can differ from the model's by a token or two at the
+/// edges of a chunk.
+pub struct BpeTokenizer {
+    merges: Merges,
+    alphabet: Alphabet,
+    /// Splits text into pieces that are encoded separately; `None` for `Metaspace`, which splits at spaces.
+    pattern: Option<Regex>,
+    /// Whitespace the pattern doesn't match is dropped rather than counted, as `Whitespace` pre-tokenizers do.
+    drops_whitespace: bool,
+    byte_chars: Vec<char>,
+}
+
+impl BpeTokenizer {
+    /// Loads `path` as a Hugging Face tokenizer if it ends in `.json`,
+    /// otherwise as a tiktoken file. A tiktoken file is split with
+    /// `split_pattern` if there is one, else with the pattern of the
+    /// encoding its name gives.
+    pub fn load(path: &Path, split_pattern: Option<Regex>) -> Result<Self, Box<dyn std::error::Error>> {
+        let contents = fs::read_to_string(path)?;
+        let is_json = path.extension().is_some_and(|ext| ext.eq_ignore_ascii_case("json"));
+        if is_json {
+            return Self::from_tokenizer_json(&contents);
+        }
+        let pattern = match split_pattern {
+            Some(pattern) => pattern,
+            None => {
+                let pattern = tiktoken_pattern(path).ok_or(
+                    "cannot tell which encoding this is from its name (cl100k_base, o200k_base, p50k_base or r50k_base); \
+                     rename it or set chunking.split_pattern",
+                )?;
+                Regex::new(pattern)?
+            }
+        };
+        Self::from_tiktoken(&contents, pattern)
+    }
+
+    /// Reads tiktoken's format: a base64-encoded token and its rank on each line.
+    fn from_tiktoken(contents: &str, pattern: Regex) -> Result<Self, Box<dyn std::error::Error>> {
+        let mut ranks = HashMap::new();
+        for (i, line) in contents.lines().enumerate() {
+            if line.trim().is_empty() {
+                continue;
+            }
+            let mut fields = line.split_whitespace();
+            let (Some(token), Some(rank)) = (fields.next(), fields.next()) else {
+                return Err(format!("line {}: expected a token and a rank", i + 1).into());
+            };
+            let token = base64::engine::general_purpose::STANDARD
+                .decode(token)
+                .map_err(|e| format!("line {}: {}", i + 1, e))?;
+            let rank: u32 = rank.parse().map_err(|e| format!("line {}: {}", i + 1, e))?;
+            ranks.insert(token, rank);
+        }
+        if ranks.is_empty() {
+            return Err("no tokens found".into());
+        }
+        Ok(BpeTokenizer {
+            merges: Merges::Ranks(ranks),
+            alphabet: Alphabet::Bytes,
+            pattern: Some(pattern),
+            drops_whitespace: false,
+            byte_chars: byte_chars(),
+        })
+    }
+
+    /// Reads the `model.merges` of a BPE `tokenizer.json`, and picks the
+    /// alphabet and split from its `pre_tokenizer` (or, for SentencePiece
+    /// conversions without one, its `normalizer`).
+    fn from_tokenizer_json(contents: &str) -> Result<Self, Box<dyn std::error::Error>> {
+        let json: Value = serde_json::from_str(contents)?;
+        let model = &json["model"];
+        if model["type"].as_str().is_some_and(|kind| kind != "BPE") {
+            return Err(format!("unsupported model type {}, only BPE is supported", model["type"]).into());
+        }
+        let merge_list = model["merges"].as_array().ok_or("model.merges is missing")?;
+        let mut ids: HashMap<Vec<u8>, u32> = HashMap::new();
+        let mut id = |symbol: &str| {
+            let next = ids.len() as u32;
+            *ids.entry(symbol.as_bytes().to_vec()).or_insert(next)
+        };
+        let mut pairs = HashMap::new();
+        for (rank, merge) in merge_list.iter().enumerate() {
+            // Either "a b" or ["a", "b"], depending on the version that wrote the file.
+            let pair = match merge {
+                Value::String(merge) => merge.split_once(' ').map(|(a, b)| (a.to_string(), b.to_string())),
+                Value::Array(parts) => match (parts.first().and_then(Value::as_str), parts.get(1).and_then(Value::as_str)) {
+                    (Some(a), Some(b)) => Some((a.to_string(), b.to_string())),
+                    _ => None,
+                },
+                _ => None,
+            };
+            let (a, b) = pair.ok_or_else(|| format!("model.merges[{}]: expected a pair, got {}", rank, merge))?;
+            let key = (id(&a), id(&b));
+            let merged = id(&format!("{}{}", a, b));
+            pairs.entry(key).or_insert((rank as u32, merged));
+        }
+
+        let mut pre_tokenizers = Vec::new();
+        collect_pre_tokenizers(&json["pre_tokenizer"], &mut pre_tokenizers);
+        let has = |kind: &str| pre_tokenizers.iter().any(|p| p["type"] == kind);
+        let split_pattern = pre_tokenizers.iter().find_map(|p| {
+            (p["type"] == "Split").then(|| p["pattern"]["Regex"].as_str()).flatten()
+        });
+
+        let drops_whitespace = has("Whitespace") || has("BertPreTokenizer");
+        let (alphabet, pattern) = if has("ByteLevel") {
+            (Alphabet::ByteLevel, Some(split_pattern.unwrap_or(GPT2_PATTERN)))
+        } else if has("Metaspace") || json["normalizer"].to_string().contains('\u{2581}') {
+            (Alphabet::Metaspace, None)
+        } else if drops_whitespace {
+            (Alphabet::Chars, Some(WORD_PATTERN))
+        } else {
+            (Alphabet::Chars, Some(split_pattern.unwrap_or(FALLBACK_PATTERN)))
+        };
+        Ok(BpeTokenizer {
+            merges: Merges::Pairs { ids, pairs },
+            alphabet,
+            pattern: pattern.map(Regex::new).transpose()?,
+            drops_whitespace,
+            byte_chars: byte_chars(),
+        })
+    }
+
+    /// The text split into pieces that are encoded on their own, as byte ranges.
+    fn pieces(&self, text: &str) -> Vec<(usize, usize)> {
+        if matches!(self.alphabet, Alphabet::Metaspace) {
+            // Each word with the space before it; `▁` stands in for the space.
+            let mut pieces = Vec::new();
+            let mut start = 0;
+            for (i, c) in text.char_indices() {
+                if c == ' ' && i > start {
+                    pieces.push((start, i));
+                    start = i;
+                }
+            }
+            if start < text.len() {
+                pieces.push((start, text.len()));
+            }
+            return pieces;
+        }
+        let Some(pattern) = &self.pattern else { return vec![(0, text.len())] };
+        let mut pieces = Vec::new();
+        let mut last = 0;
+        for found in pattern.find_iter(text) {
+            let Ok(found) = found else { break };
+            if found.start() > last && !(self.drops_whitespace && text[last..found.start()].trim().is_empty()) {
+                pieces.push((last, found.start()));
+            }
+            if found.end() > found.start() {
+                pieces.push((found.start(), found.end()));
+            }
+            last = found.end();
+        }
+        // Anything unmatched, or left after the matcher gave up, is one piece.
+        if last < text.len() && !(self.drops_whitespace && text[last..].trim().is_empty()) {
+            pieces.push((last, text.len()));
+        }
+        pieces
+    }
+
+    /// The starting symbols of a piece: their bytes laid end to end, and
+    /// each one's length in those bytes and in the original text.
+    fn symbols(&self, piece: &str, first: bool) -> (Vec<u8>, Vec<(usize, usize)>) {
+        match self.alphabet {
+            Alphabet::Bytes => (piece.as_bytes().to_vec(), vec![(1, 1); piece.len()]),
+            Alphabet::ByteLevel => {
+                let mut bytes = Vec::with_capacity(piece.len() * 2);
+                let mut lens = Vec::with_capacity(piece.len());
+                for b in piece.bytes() {
+                    let shown = self.byte_chars[b as usize];
+                    bytes.extend_from_slice(shown.encode_utf8(&mut [0; 4]).as_bytes());
+                    lens.push((shown.len_utf8(), 1));
+                }
+                (bytes, lens)
+            }
+            Alphabet::Metaspace => {
+                let mut bytes = Vec::with_capacity(piece.len() + 3);
+                let mut lens = Vec::with_capacity(piece.len() + 1);
+                // The `▁` put before the first word covers no text, but can still end up a token of its own.
+                if first && !piece.starts_with(' ') {
+                    bytes.extend_from_slice("\u{2581}".as_bytes());
+                    lens.push(('\u{2581}'.len_utf8(), 0));
+                }
+                for c in piece.chars() {
+                    let shown = if c == ' ' { '\u{2581}' } else { c };
+                    bytes.extend_from_slice(shown.encode_utf8(&mut [0; 4]).as_bytes());
+                    lens.push((shown.len_utf8(), c.len_utf8()));
+                }
+                (bytes, lens)
+            }
+            Alphabet::Chars => (piece.as_bytes().to_vec(), piece.chars().map(|c| (c.len_utf8(), c.len_utf8())).collect()),
+        }
+    }
+
+    /// The rank of merging two adjacent symbols, looked up without building the merged token.
+    fn pair_rank(&self, bytes: &[u8], left: &Symbol, right: &Symbol) -> Option<u32> {
+        match &self.merges {
+            Merges::Ranks(ranks) => ranks.get(&bytes[left.start..right.end]).copied(),
+            Merges::Pairs { pairs, .. } => pairs.get(&(left.id?, right.id?)).map(|&(rank, _)| rank),
+        }
+    }
+
+    /// Merges the lowest-ranked adjacent pair, leftmost first, until none
+    /// can be merged, and returns how many bytes of the original text each
+    /// resulting token covers. Candidate pairs wait in a priority queue, and
+    /// those a merge has since changed are skipped when they come up.
+    fn encode_piece(&self, bytes: &[u8], lens: &[(usize, usize)]) -> Vec<usize> {
+        if let Merges::Ranks(ranks) = &self.merges {
+            if lens.len() > 1 && ranks.contains_key(bytes) {
+                return vec![lens.iter().map(|(_, text_len)| text_len).sum()];
+            }
+        }
+        let mut symbols = Vec::with_capacity(lens.len());
+        let mut start = 0;
+        for (i, &(len, text_len)) in lens.iter().enumerate() {
+            let id = match &self.merges {
+                Merges::Pairs { ids, .. } => ids.get(&bytes[start..start + len]).copied(),
+                Merges::Ranks(_) => None,
+            };
+            let next = (i + 1 < lens.len()).then_some(i + 1);
+            symbols.push(Symbol { start, end: start + len, text_len, id, prev: i.checked_sub(1), next, merged: false });
+            start += len;
+        }
+
+        // Each entry is (rank, left, right, where right ended when it was queued).
+        let mut queue = BinaryHeap::new();
+        let push = |queue: &mut BinaryHeap<_>, symbols: &[Symbol], left: usize, right: usize| {
+            if let Some(rank) = self.pair_rank(bytes, &symbols[left], &symbols[right]) {
+                queue.push(Reverse((rank, left, right, symbols[right].end)));
+            }
+        };
+        for right in 1..symbols.len() {
+            push(&mut queue, &symbols, right - 1, right);
+        }
+        while let Some(Reverse((_, left, right, right_end))) = queue.pop() {
+            // A merged left, or a right that has grown or gone, means the pair is out of date.
+            if symbols[left].merged || symbols[left].next != Some(right) || symbols[right].end != right_end {
+                continue;
+            }
+            let merged_id = match &self.merges {
+                Merges::Pairs { pairs, .. } => symbols[left].id.zip(symbols[right].id).and_then(|pair| pairs.get(&pair)).map(|&(_, id)| id),
+                Merges::Ranks(_) => None,
+            };
+            symbols[right].merged = true;
+            let (end, text_len, next) = (symbols[right].end, symbols[right].text_len, symbols[right].next);
+            let symbol = &mut symbols[left];
+            symbol.end = end;
+            symbol.text_len += text_len;
+            symbol.id = merged_id;
+            symbol.next = next;
+            if let Some(next) = next {
+                symbols[next].prev = Some(left);
+                push(&mut queue, &symbols, left, next);
+            }
+            if let Some(prev) = symbols[left].prev {
+                push(&mut queue, &symbols, prev, left);
+            }
+        }
+        symbols.iter().filter(|symbol| !symbol.merged).map(|symbol| symbol.text_len).collect()
+    }
+}
+
+impl Tokenizer for BpeTokenizer {
+    fn token_ends(&self, text: &str) -> Vec<usize> {
+        let mut ends = Vec::new();
+        for (i, (start, end)) in self.pieces(text).into_iter().enumerate() {
+            let mut offset = start;
+            let (bytes, lens) = self.symbols(&text[start..end], i == 0);
+            for len in self.encode_piece(&bytes, &lens) {
+                offset += len;
+                ends.push(offset);
+            }
+        }
+        ends
+    }
+}
+
+/// Flattens a `Sequence` pre-tokenizer into its parts.
+fn collect_pre_tokenizers<'a>(pre_tokenizer: &'a Value, into: &mut Vec<&'a Value>) {
+    match pre_tokenizer["pretokenizers"].as_array() {
+        Some(parts) => parts.iter().for_each(|part| collect_pre_tokenizers(part, into)),
+        None if pre_tokenizer.is_object() => into.push(pre_tokenizer),
+        None => {}
+    }
+}
+
+/// GPT-2's mapping of bytes to printable characters: printable Latin-1
+/// bytes stand for themselves and the rest are moved up past 255.
+fn byte_chars() -> Vec<char> {
+    let mut shifted = 0;
+    (0..=255u32)
+        .map(|b| {
+            let printable = (33..=126).contains(&b) || (161..=172).contains(&b) || (174..=255).contains(&b);
+            if printable {
+                char::from_u32(b).unwrap()
+            } else {
+                shifted += 1;
+                char::from_u32(255 + shifted).unwrap()
+            }
+        })
+        .collect()
+}
+
+/// The tokenizer `chunking.tokenizer` names, or `None` when sizes are in bytes.
+pub fn from_config(config: &ChunkingConfig) -> Result<Option<Box<dyn Tokenizer>>, ConfigError> {
+    match config.tokenizer {
+        TokenizerKind::Bytes => Ok(None),
+        TokenizerKind::Whitespace => Ok(Some(Box::new(WhitespaceTokenizer))),
+        TokenizerKind::Bpe => {
+            let path = config.vocab_file.as_deref().ok_or_else(|| ConfigError::new("chunking.vocab_file", "is required by the bpe tokenizer"))?;
+            let split_pattern = config
+                .split_pattern
+                .as_deref()
+                .map(Regex::new)
+                .transpose()
+                .map_err(|e| ConfigError::new("chunking.split_pattern", e.to_string()))?;
+            let tokenizer = BpeTokenizer::load(path, split_pattern)
+                .map_err(|e| ConfigError::new("chunking.vocab_file", format!("cannot load {}: {}", path.display(), e)))?;
+            Ok(Some(Box::new(tokenizer)))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use proptest::prelude::*;
+
+    fn cl100k() -> Regex {
+        Regex::new(CL100K_PATTERN).unwrap()
+    }
+
+    /// The tokens of `text`, as the slices between successive token ends.
+    fn tokens<'a>(tokenizer: &BpeTokenizer, text: &'a str) -> Vec<&'a str> {
+        let mut start = 0;
+        tokenizer
+            .token_ends(text)
+            .into_iter()
+            .map(|end| {
+                let token = &text[start..end];
+                start = end;
+                token
+            })
+            .collect()
+    }
+
+    fn tiktoken(tokens: &[&str]) -> String {
+        tokens
+            .iter()
+            .enumerate()
+            .map(|(rank, token)| format!("{} {}\n", base64::engine::general_purpose::STANDARD.encode(token), rank))
+            .collect()
+    }
+
+    #[test]
+    fn tiktoken_merges_by_rank() {
+        let mut vocab: Vec<&str> = vec!["h", "e", "l", "o", " "];
+        vocab.extend(["he", "ll", "hell", " hello", "12", "123", "34"]);
+        let tokenizer = BpeTokenizer::from_tiktoken(&tiktoken(&vocab), cl100k()).unwrap();
+
+        // "hello" has no token of its own, so it stops at "hell" + "o";
+        // " hello" does, so it is a single token.
+        assert_eq!(tokens(&tokenizer, "hello hello"), ["hell", "o", " hello"]);
+        assert_eq!(tokenizer.count("hello hello"), 3);
+        // Digits are split off in threes before merging, so "34" never forms.
+        assert_eq!(tokens(&tokenizer, "he1234"), ["he", "123", "4"]);
+    }
+
+    #[test]
+    fn tiktoken_errors_name_the_line() {
+        let error = BpeTokenizer::from_tiktoken("aGU= 0\naGVsbA==\n", cl100k()).err().unwrap();
+        assert_eq!(error.to_string(), "line 2: expected a token and a rank");
+        assert!(BpeTokenizer::from_tiktoken("\n\n", cl100k()).is_err());
+    }
+
+    #[test]
+    fn byte_level_tokenizer_json() {
+        let json = r#"{
+            "pre_tokenizer": {"type": "Sequence", "pretokenizers": [{"type": "ByteLevel", "add_prefix_space": false}]},
+            "model": {"type": "BPE", "merges": ["h e", "l l", ["he", "ll"], "hell o", "Ġ hello"]}
+        }"#;
+        let tokenizer = BpeTokenizer::from_tokenizer_json(json).unwrap();
+        assert!(matches!(tokenizer.alphabet, Alphabet::ByteLevel));
+
+        assert_eq!(tokens(&tokenizer, "hello hello!"), ["hello", " hello", "!"]);
+        // Without a merge for its two bytes, "é" is two tokens, the first ending inside the character.
+        assert_eq!(tokenizer.token_ends("é"), [1, 2]);
+    }
+
+    #[test]
+    fn metaspace_tokenizer_json() {
+        let json = r#"{
+            "pre_tokenizer": {"type": "Metaspace", "replacement": "▁", "add_prefix_space": true},
+            "model": {"type": "BPE", "merges": ["▁ h", "▁h i", "t h", "e r", "▁ th", "▁th er", "▁ther e"]}
+        }"#;
+        let tokenizer = BpeTokenizer::from_tokenizer_json(json).unwrap();
+        assert!(matches!(tokenizer.alphabet, Alphabet::Metaspace));
+        assert_eq!(tokens(&tokenizer, "hi there"), ["hi", " there"]);
+        assert_eq!(tokens(&tokenizer, "hit"), ["hi", "t"]);
+
+        // SentencePiece conversions may only show `▁` in their normalizer.
+        let json = r#"{
+            "normalizer": {"type": "Replace", "pattern": {"String": " "}, "content": "▁"},
+            "model": {"type": "BPE", "merges": ["▁ h", "▁h i"]}
+        }"#;
+        let tokenizer = BpeTokenizer::from_tokenizer_json(json).unwrap();
+        assert!(matches!(tokenizer.alphabet, Alphabet::Metaspace));
+        assert_eq!(tokens(&tokenizer, "hi hi"), ["hi", " hi"]);
+    }
+
+    #[test]
+    fn whitespace_split_tokenizer_json() {
+        let json = r#"{
+            "pre_tokenizer": {"type": "Whitespace"},
+            "model": {"type": "BPE", "merges": ["h i", "t h", "th e", "r e", "the re"]}
+        }"#;
+        let tokenizer = BpeTokenizer::from_tokenizer_json(json).unwrap();
+        assert!(matches!(tokenizer.alphabet, Alphabet::Chars));
+        // The spaces between words are not tokens.
+        assert_eq!(tokenizer.token_ends("hi, there "), [2, 3, 9]);
+        assert_eq!(tokenizer.count("hi, there "), 3);
+    }
+
+    #[test]
+    fn non_bpe_models_are_rejected() {
+        let error = BpeTokenizer::from_tokenizer_json(r#"{"model": {"type": "WordPiece", "vocab": {}}}"#).err().unwrap();
+        assert!(error.to_string().contains("only BPE is supported"));
+    }
+
+    #[test]
+    fn load_picks_the_format_and_split_from_the_name() {
+        let dir = std::env::temp_dir().join(format!("tokenizer-test-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let json = dir.join("tokenizer.json");
+        fs::write(&json, r#"{"model": {"type": "BPE", "merges": ["a b"]}}"#).unwrap();
+        let vocab = tiktoken(&["a", "b", "A", "bA", "ab", "Ab"]);
+        for name in ["cl100k_base.tiktoken", "o200k_base.tiktoken", "p50k_base.tiktoken", "custom.tiktoken"] {
+            fs::write(dir.join(name), &vocab).unwrap();
+        }
+        let load = |name: &str, split: Option<&str>| BpeTokenizer::load(&dir.join(name), split.map(|split| Regex::new(split).unwrap()));
+
+        assert_eq!(load("tokenizer.json", None).unwrap().count("abab"), 2);
+        assert_eq!(load("cl100k_base.tiktoken", None).unwrap().count("abab"), 2);
+        // cl100k keeps "abAb" as one piece, where "bA" merges first; o200k splits it into "ab" and "Ab".
+        assert_eq!(load("cl100k_base.tiktoken", None).unwrap().count("abAb"), 3);
+        assert_eq!(load("o200k_base.tiktoken", None).unwrap().count("abAb"), 2);
+        // GPT-2 puts the space in front of the word: " ab" has no token, so it is " " + "ab".
+        assert_eq!(load("p50k_base.tiktoken", None).unwrap().token_ends("ab ab"), [2, 3, 5]);
+
+        let error = load("custom.tiktoken", None).err().unwrap();
+        assert!(error.to_string().contains("chunking.split_pattern"));
+        assert_eq!(load("custom.tiktoken", Some(r"\S{1,2}|\s+")).unwrap().count("abab ab"), 4);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn tokenizer_json_without_a_split_falls_back_to_words() {
+        let json = r#"{"model": {"type": "BPE", "merges": ["h i", "hi  ", "i  "]}}"#;
+        let tokenizer = BpeTokenizer::from_tokenizer_json(json).unwrap();
+        // Merges with the space never apply, because spaces are pieces of their own.
+        assert_eq!(tokens(&tokenizer, "hi hi"), ["hi", " ", "hi"]);
+
+        // A long page is encoded word by word, not as one piece.
+        let text = "hi ".repeat(50_000);
+        assert_eq!(tokenizer.count(&text), 100_000);
+    }
+
+    /// The textbook algorithm: merge the lowest-ranked pair, leftmost first, and rescan.
+    fn reference_token_lens(merges: &[(&str, &str)], piece: &str) -> Vec<usize> {
+        let mut symbols: Vec<String> = piece.chars().map(String::from).collect();
+        loop {
+            let best = (1..symbols.len())
+                .filter_map(|i| merges.iter().position(|&(a, b)| a == symbols[i - 1] && b == symbols[i]).map(|rank| (rank, i)))
+                .min();
+            let Some((_, i)) = best else { break };
+            let right = symbols.remove(i);
+            symbols[i - 1].push_str(&right);
+        }
+        symbols.iter().map(String::len).collect()
+    }
+
+    const MERGES: &[(&str, &str)] = &[("a", "b"), ("b", "a"), ("ab", "a"), ("a", "a"), ("ab", "ab"), ("aba", "b"), ("b", "b"), ("bb", "a")];
+
+    proptest! {
+        #[test]
+        fn merges_match_the_reference_algorithm(text in "[ab ]{0,60}") {
+            let merges: Vec<String> = MERGES.iter().map(|(a, b)| format!("{} {}", a, b)).collect();
+            let json = serde_json::json!({"model": {"type": "BPE", "merges": merges}}).to_string();
+            let tokenizer = BpeTokenizer::from_tokenizer_json(&json).unwrap();
+
+            let mut expected = Vec::new();
+            let mut offset = 0;
+            for (start, end) in tokenizer.pieces(&text) {
+                prop_assert_eq!(start, offset);
+                for len in reference_token_lens(MERGES, &text[start..end]) {
+                    offset += len;
+                    expected.push(offset);
+                }
+            }
+            prop_assert_eq!(tokenizer.token_ends(&text), expected);
+        }
+    }
+}
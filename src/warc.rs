@@ -0,0 +1,398 @@
+use chrono::Utc;
+use flate2::read::MultiGzDecoder;
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use reqwest::header::HeaderMap;
+use sha2::{Digest, Sha256};
+use std::fs::{self, File};
+use std::io::{self, BufRead, BufReader, BufWriter, Read, Write};
+use std::path::{Path, PathBuf};
+use url::Url;
+
+/// A response as it came off the wire (after transfer decoding), kept so it
+/// can be archived and extracted from later.
+pub struct HttpResponse {
+    pub version: String,
+    pub status: u16,
+    pub headers: Vec<(String, Vec<u8>)>,
+    pub body: Vec<u8>,
+}
+
+impl HttpResponse {
+    pub fn read(response: reqwest::blocking::Response) -> reqwest::Result<Self> {
+        let version = format!("{:?}", response.version());
+        let status = response.status().as_u16();
+        let headers = response
+            .headers()
+            .iter()
+            .map(|(name, value)| (name.as_str().to_string(), value.as_bytes().to_vec()))
+            .collect();
+        let body = response.bytes()?.to_vec();
+        Ok(HttpResponse { version, status, headers, body })
+    }
+
+    /// Parses an `application/http;msgtype=response` block.
+    pub fn parse(block: &[u8]) -> Result<Self, Box<dyn std::error::Error>> {
+        let head_end = find(block, b"\r\n\r\n").ok_or("HTTP response has no end of headers")?;
+        let head = String::from_utf8_lossy(&block[..head_end]);
+        let mut lines = head.split("\r\n");
+        let status_line = lines.next().unwrap_or_default();
+        let mut parts = status_line.splitn(3, ' ');
+        let version = parts.next().unwrap_or_default().to_string();
+        let status = parts
+            .next()
+            .and_then(|code| code.parse().ok())
+            .ok_or_else(|| format!("malformed status line {:?}", status_line))?;
+        let headers = lines
+            .filter_map(|line| line.split_once(':'))
+            .map(|(name, value)| (name.trim().to_ascii_lowercase(), value.trim().as_bytes().to_vec()))
+            .collect();
+        Ok(HttpResponse {
+            version,
+            status,
+            headers,
+            body: block[head_end + 4..].to_vec(),
+        })
+    }
+
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(header, _)| header.eq_ignore_ascii_case(name))
+            .and_then(|(_, value)| std::str::from_utf8(value).ok())
+    }
+
+    /// The status line, headers and body. `Transfer-Encoding` is left out
+    /// because the body has already been de-chunked.
+    fn to_block(&self) -> Vec<u8> {
+        let reason = reqwest::StatusCode::from_u16(self.status)
+            .ok()
+            .and_then(|status| status.canonical_reason())
+            .unwrap_or("");
+        let mut block = format!("{} {} {}\r\n", self.version, self.status, reason).into_bytes();
+        for (name, value) in self.headers.iter().filter(|(name, _)| !name.eq_ignore_ascii_case("transfer-encoding")) {
+            block.extend_from_slice(name.as_bytes());
+            block.extend_from_slice(b": ");
+            block.extend_from_slice(value);
+            block.extend_from_slice(b"\r\n");
+        }
+        block.extend_from_slice(b"\r\n");
+        block.extend_from_slice(&self.body);
+        block
+    }
+}
+
+fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack.windows(needle.len()).position(|window| window == needle)
+}
+
+/// Rebuilds the request line and headers of a GET for `url`.
+fn request_block(url: &Url, headers: &HeaderMap) -> Vec<u8> {
+    let mut target = url.path().to_string();
+    if let Some(query) = url.query() {
+        target.push('?');
+        target.push_str(query);
+    }
+    let host = match url.port() {
+        Some(port) => format!("{}:{}", url.host_str().unwrap_or(""), port),
+        None => url.host_str().unwrap_or("").to_string(),
+    };
+    let mut block = format!("GET {} HTTP/1.1\r\nhost: {}\r\n", target, host).into_bytes();
+    for (name, value) in headers {
+        block.extend_from_slice(name.as_str().as_bytes());
+        block.extend_from_slice(b": ");
+        block.extend_from_slice(value.as_bytes());
+        block.extend_from_slice(b"\r\n");
+    }
+    block.extend_from_slice(b"\r\n");
+    block
+}
+
+/// `sha256:` and the hex digest of an HTTP body, for `WARC-Payload-Digest`.
+fn payload_digest(body: &[u8]) -> String {
+    let digest: String = Sha256::digest(body).iter().map(|b| format!("{:02x}", b)).collect();
+    format!("sha256:{}", digest)
+}
+
+fn record_id() -> String {
+    format!("<urn:uuid:{}>", uuid::Uuid::new_v4())
+}
+
+/// Writes request/response pairs to WARC 1.1 files named
+/// `<prefix>-00000.warc.gz`, `<prefix>-00001.warc.gz`, ... A new file is
+/// started once the current one reaches `max_file_size` bytes; a pair is never
+/// split across files. Each file opens with a `warcinfo` record, and when
+/// compressed every record is its own gzip member, as WARC readers expect.
+pub struct WarcWriter {
+    dir: PathBuf,
+    prefix: String,
+    max_file_size: u64,
+    gzip: bool,
+    /// `key: value` lines for the `warcinfo` record at the head of each file.
+    info: Vec<(String, String)>,
+    serial: usize,
+    current: Option<WarcFile>,
+}
+
+struct WarcFile {
+    writer: BufWriter<File>,
+    warcinfo_id: String,
+    size: u64,
+}
+
+impl WarcWriter {
+    pub fn new(
+        dir: &Path,
+        prefix: &str,
+        max_file_size: u64,
+        gzip: bool,
+        info: Vec<(String, String)>,
+    ) -> io::Result<Self> {
+        fs::create_dir_all(dir)?;
+        Ok(WarcWriter {
+            dir: dir.to_path_buf(),
+            prefix: prefix.to_string(),
+            max_file_size,
+            gzip,
+            info,
+            serial: 0,
+            current: None,
+        })
+    }
+
+    /// Archives one fetch of `target` as a `response` record followed by its
+    /// `request` record. A redirect is archived as a fetch of its own, so
+    /// `target` is the URL asked for, not where the response ended up.
+    ///
+    /// `Crawl-Depth` is not a WARC 1.1 field. The `WARC-` prefix is reserved
+    /// for fields the standard defines, so it goes without one, as extension
+    /// fields from other crawlers do.
+    pub fn write_exchange(&mut self, target: &Url, request_headers: &HeaderMap, response: &HttpResponse, depth: usize) -> io::Result<()> {
+        if self.current.as_ref().is_none_or(|file| file.size >= self.max_file_size) {
+            self.start_file()?;
+        }
+        let date = Utc::now().format("%Y-%m-%dT%H:%M:%SZ").to_string();
+        let warcinfo_id = self.current.as_ref().map(|file| file.warcinfo_id.clone()).unwrap_or_default();
+        let response_id = record_id();
+
+        self.write_record(&[
+            ("WARC-Type", "response".to_string()),
+            ("WARC-Record-ID", response_id.clone()),
+            ("WARC-Date", date.clone()),
+            ("WARC-Target-URI", target.to_string()),
+            ("WARC-Warcinfo-ID", warcinfo_id.clone()),
+            ("WARC-Payload-Digest", payload_digest(&response.body)),
+            ("Content-Type", "application/http;msgtype=response".to_string()),
+            ("Crawl-Depth", depth.to_string()),
+        ], &response.to_block())?;
+        self.write_record(&[
+            ("WARC-Type", "request".to_string()),
+            ("WARC-Record-ID", record_id()),
+            ("WARC-Date", date),
+            ("WARC-Target-URI", target.to_string()),
+            ("WARC-Warcinfo-ID", warcinfo_id),
+            ("WARC-Concurrent-To", response_id),
+            ("Content-Type", "application/http;msgtype=request".to_string()),
+        ], &request_block(target, request_headers))
+    }
+
+    fn start_file(&mut self) -> io::Result<()> {
+        self.flush()?;
+        let name = format!("{}-{:05}.warc{}", self.prefix, self.serial, if self.gzip { ".gz" } else { "" });
+        self.serial += 1;
+        let warcinfo_id = record_id();
+        self.current = Some(WarcFile {
+            writer: BufWriter::new(File::create(self.dir.join(&name))?),
+            warcinfo_id: warcinfo_id.clone(),
+            size: 0,
+        });
+
+        let mut block = format!("software: {}/{}\r\nformat: WARC File Format 1.1\r\n", env!("CARGO_PKG_NAME"), env!("CARGO_PKG_VERSION"));
+        for (key, value) in &self.info {
+            block.push_str(&format!("{}: {}\r\n", key, value));
+        }
+        self.write_record(&[
+            ("WARC-Type", "warcinfo".to_string()),
+            ("WARC-Record-ID", warcinfo_id),
+            ("WARC-Date", Utc::now().format("%Y-%m-%dT%H:%M:%SZ").to_string()),
+            ("WARC-Filename", name),
+            ("Content-Type", "application/warc-fields".to_string()),
+        ], block.as_bytes())
+    }
+
+    fn write_record(&mut self, fields: &[(&str, String)], block: &[u8]) -> io::Result<()> {
+        let mut record = b"WARC/1.1\r\n".to_vec();
+        for (name, value) in fields {
+            record.extend_from_slice(format!("{}: {}\r\n", name, value).as_bytes());
+        }
+        record.extend_from_slice(format!("Content-Length: {}\r\n\r\n", block.len()).as_bytes());
+        record.extend_from_slice(block);
+        record.extend_from_slice(b"\r\n\r\n");
+
+        if self.gzip {
+            let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+            encoder.write_all(&record)?;
+            record = encoder.finish()?;
+        }
+        let file = self.current.as_mut().ok_or_else(|| io::Error::other("no WARC file open"))?;
+        file.writer.write_all(&record)?;
+        file.size += record.len() as u64;
+        Ok(())
+    }
+
+    pub fn flush(&mut self) -> io::Result<()> {
+        match self.current.as_mut() {
+            Some(file) => file.writer.flush(),
+            None => Ok(()),
+        }
+    }
+
+    /// Number of files started so far.
+    pub fn file_count(&self) -> usize {
+        self.serial
+    }
+}
+
+/// One record read back from a WARC file.
+pub struct WarcRecord {
+    pub fields: Vec<(String, String)>,
+    pub block: Vec<u8>,
+}
+
+impl WarcRecord {
+    pub fn field(&self, name: &str) -> Option<&str> {
+        self.fields
+            .iter()
+            .find(|(field, _)| field.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    }
+}
+
+/// Reads the records of a `.warc` or `.warc.gz` file in order.
+pub struct WarcReader {
+    reader: Box<dyn BufRead>,
+}
+
+impl WarcReader {
+    pub fn open(path: &Path) -> io::Result<Self> {
+        let mut file = BufReader::new(File::open(path)?);
+        let reader: Box<dyn BufRead> = if file.fill_buf()?.starts_with(&[0x1f, 0x8b]) {
+            Box::new(BufReader::new(MultiGzDecoder::new(file)))
+        } else {
+            Box::new(file)
+        };
+        Ok(WarcReader { reader })
+    }
+
+    fn read_record(&mut self) -> Result<Option<WarcRecord>, Box<dyn std::error::Error>> {
+        let mut line = String::new();
+        // Skip the blank lines that end the previous record.
+        loop {
+            line.clear();
+            if self.reader.read_line(&mut line)? == 0 {
+                return Ok(None);
+            }
+            if !line.trim().is_empty() {
+                break;
+            }
+        }
+        if !line.starts_with("WARC/") {
+            return Err(format!("expected a WARC version line, found {:?}", line.trim_end()).into());
+        }
+
+        let mut fields = Vec::new();
+        loop {
+            line.clear();
+            if self.reader.read_line(&mut line)? == 0 {
+                return Err("WARC record ends inside its header".into());
+            }
+            let trimmed = line.trim_end();
+            if trimmed.is_empty() {
+                break;
+            }
+            if let Some((name, value)) = trimmed.split_once(':') {
+                fields.push((name.trim().to_string(), value.trim().to_string()));
+            }
+        }
+
+        let record = WarcRecord { fields, block: Vec::new() };
+        let length: usize = record
+            .field("Content-Length")
+            .and_then(|length| length.parse().ok())
+            .ok_or("WARC record has no Content-Length")?;
+        let mut block = vec![0; length];
+        self.reader.read_exact(&mut block)?;
+        Ok(Some(WarcRecord { block, ..record }))
+    }
+}
+
+impl Iterator for WarcReader {
+    type Item = Result<WarcRecord, Box<dyn std::error::Error>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.read_record().transpose()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::source::{self, UrlMapping};
+
+    fn response(status: u16, headers: &[(&str, &str)], body: &str) -> HttpResponse {
+        HttpResponse {
+            version: "HTTP/1.1".to_string(),
+            status,
+            headers: headers.iter().map(|(name, value)| (name.to_string(), value.as_bytes().to_vec())).collect(),
+            body: body.as_bytes().to_vec(),
+        }
+    }
+
+    #[test]
+    fn archived_exchanges_read_back_as_pages() {
+        let dir = std::env::temp_dir().join(format!("warc-test-{}", std::process::id()));
+        let body = "<html><body><p>Moved here</p></body></html>";
+        for gzip in [true, false] {
+            let prefix = if gzip { "gzipped" } else { "plain" };
+            let mut writer = WarcWriter::new(&dir, prefix, 1 << 20, gzip, vec![("operator".to_string(), "tests".to_string())]).unwrap();
+            let old = Url::parse("https://example.org/old?x=1").unwrap();
+            let new = Url::parse("https://example.org/new").unwrap();
+            writer.write_exchange(&old, &HeaderMap::new(), &response(301, &[("location", "/new")], ""), 2).unwrap();
+            writer.write_exchange(&new, &HeaderMap::new(), &response(200, &[("content-type", "text/html"), ("transfer-encoding", "chunked")], body), 2).unwrap();
+            writer.flush().unwrap();
+            let path = dir.join(format!("{}-00000.warc{}", prefix, if gzip { ".gz" } else { "" }));
+
+            let records: Vec<WarcRecord> = WarcReader::open(&path).unwrap().map(Result::unwrap).collect();
+            let summary: Vec<(Option<&str>, Option<&str>)> =
+                records.iter().map(|record| (record.field("WARC-Type"), record.field("WARC-Target-URI"))).collect();
+            assert_eq!(
+                summary,
+                [
+                    (Some("warcinfo"), None),
+                    (Some("response"), Some("https://example.org/old?x=1")),
+                    (Some("request"), Some("https://example.org/old?x=1")),
+                    (Some("response"), Some("https://example.org/new")),
+                    (Some("request"), Some("https://example.org/new")),
+                ]
+            );
+            assert!(records[2].block.starts_with(b"GET /old?x=1 HTTP/1.1\r\nhost: example.org\r\n"));
+            assert_eq!(records[3].field("Crawl-Depth"), Some("2"));
+            assert_eq!(
+                records[3].field("WARC-Payload-Digest"),
+                Some("sha256:507223a429bc7e9099811a4e17d6dbf3d0fdbb1dc314ffee7daad051315281f6")
+            );
+            assert_eq!(records[3].field("WARC-Concurrent-To"), None);
+            assert_eq!(records[4].field("WARC-Concurrent-To"), records[3].field("WARC-Record-ID"));
+
+            // The redirect is left out; the page it led to keeps its URL, depth and body.
+            let mut pages = Vec::new();
+            source::open(&path, &UrlMapping::default()).unwrap().read(&mut |page| pages.push(page)).unwrap();
+            assert_eq!(pages.len(), 1);
+            assert_eq!((pages[0].url.as_str(), pages[0].depth), ("https://example.org/new", 2));
+            let page_body = pages[0].body.as_ref().unwrap();
+            assert_eq!(page_body.bytes, body.as_bytes());
+            assert_eq!(page_body.content_type.as_deref(), Some("text/html"));
+        }
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}